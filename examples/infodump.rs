@@ -1,10 +1,9 @@
 use std::error::Error;
 
 use v_ayylmao::{
-    config::{Config, ConfigAttribType},
+    config::{Config, ConfigAttribEnum},
     context::Context,
     display::Display,
-    surface::RTFormat,
     vpp::Filters,
     Entrypoint, Profile,
 };
@@ -39,12 +38,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             let attribs = config.query_config_attributes()?;
             println!("    {} config attributes", attribs.len());
             for attrib in attribs {
-                print!("    - {:?} ", attrib.attrib_type());
-
-                if attrib.attrib_type() == ConfigAttribType::RTFormat {
-                    println!("{:?}", RTFormat::from_bits_truncate(attrib.raw_value()));
-                } else {
-                    println!("{:08x}", attrib.raw_value());
+                match attrib.as_enum() {
+                    Some(ConfigAttribEnum::Other(ty, value)) => {
+                        println!("    - {ty:?} {value:08x}")
+                    }
+                    Some(value) => println!("    - {value:?}"),
+                    None => println!("    - {:?} (not supported)", attrib.attrib_type()),
                 }
             }
             let attribs = match config.query_surface_attributes() {
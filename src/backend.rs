@@ -0,0 +1,24 @@
+//! Pluggable libva implementations.
+//!
+//! By default, a [`Display`][crate::display::Display] calls into the system's *libva*, which is
+//! loaded at runtime. The [`Backend`] trait allows substituting a different implementation, which
+//! can be passed to [`Display::with_backend`][crate::display::Display::with_backend].
+//!
+//! [`FakeBackend`] is a pure-Rust implementation that keeps all objects in memory. It doesn't do
+//! any actual decoding, but it makes it possible to test code using this library on machines
+//! without VA-API capable hardware.
+
+mod fake;
+
+#[cfg(test)]
+mod tests;
+
+pub use crate::dlopen::Backend;
+pub use crate::image::VAImage;
+pub use crate::raw::{
+    VABufferID, VABufferInfo, VAConfigID, VAContextID, VADisplay, VAGenericID, VAImageID,
+    VAMFContextID, VAMessageCallback, VAPrivFunc, VAProcessingRateParameter, VASubpictureID,
+    VASurfaceID,
+};
+pub use crate::vpp::RawProcPipelineCaps;
+pub use fake::{FakeBackend, LiveObjects, Submission, SubmittedBuffer};
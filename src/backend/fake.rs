@@ -0,0 +1,1564 @@
+//! An in-memory [`Backend`] for testing.
+
+#![allow(non_snake_case)]
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, c_uint, c_void, CStr, CString},
+    ptr, slice,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    buffer::BufferType,
+    config::{ConfigAttrib, ConfigAttribType, MultipleFrame, ProcessingRate, ATTRIB_NOT_SUPPORTED},
+    display::{DisplayAttribFlags, DisplayAttribType, DisplayAttribute, LibvaFeatures, PciId},
+    error::{VAError, VAStatus},
+    image::{ImageFormat, VAImage},
+    raw::{
+        VABufferID, VAConfigID, VAContextID, VADisplay, VAGenericID, VAImageID, VAMFContextID,
+        VAMessageCallback, VAProcessingRateParameter, VASurfaceID,
+    },
+    surface::{
+        GenericValue, RTFormat, SurfaceAttrib, SurfaceAttribFlags, SurfaceAttribMemoryType,
+        SurfaceAttribType, SurfaceDecodeMBErrors, SurfaceStatus,
+    },
+    vpp::FilterType,
+    Entrypoint, PixelFormat, Profile,
+};
+
+use super::Backend;
+
+/// Maximum number of config attributes returned by `vaQueryConfigAttributes`.
+const MAX_CONFIG_ATTRIBUTES: usize = 64;
+
+/// Largest surface or image dimension the fake driver accepts.
+const MAX_DIMENSION: u32 = 16384;
+
+const IMAGE_FORMATS: &[PixelFormat] = &[PixelFormat::NV12, PixelFormat::RGBA, PixelFormat::BGRA];
+
+/// A [`Backend`] that keeps all libva objects in memory, without performing any actual work.
+///
+/// [`FakeBackend`] emulates a driver that supports baseline JPEG decoding and video processing by
+/// default. Its bookkeeping is shared between all clones, so a clone can be passed to
+/// [`Display::with_backend`][crate::display::Display::with_backend] while the original is used to
+/// inspect the created objects and the submitted work.
+///
+/// All operations complete instantly. No pixel data is ever produced, so surfaces and images keep
+/// whatever content they were created with (zeroes).
+#[derive(Clone)]
+pub struct FakeBackend {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeBackend {
+    /// Creates a [`FakeBackend`] that supports baseline JPEG decoding and video processing.
+    pub fn new() -> Self {
+        let this = Self::without_profiles();
+        this.support(
+            Profile::JPEGBaseline,
+            Entrypoint::VLD,
+            RTFormat::YUV420 | RTFormat::YUV422 | RTFormat::YUV444 | RTFormat::YUV400,
+        );
+        this.support(
+            Profile::None,
+            Entrypoint::VideoProc,
+            RTFormat::YUV420 | RTFormat::YUV422 | RTFormat::RGB32,
+        );
+        this
+    }
+
+    /// Creates a [`FakeBackend`] that doesn't support any [`Profile`].
+    pub fn without_profiles() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                vendor: CString::new("v-ayylmao fake driver").unwrap(),
+                next_id: 1,
+                features: LibvaFeatures::SYNC_SURFACE2
+                    | LibvaFeatures::SYNC_BUFFER
+                    | LibvaFeatures::PROCESSING_RATE
+                    | LibvaFeatures::MULTI_FRAME,
+                display_attributes: default_display_attributes(),
+                ..State::default()
+            })),
+        }
+    }
+
+    /// Adds support for a [`Profile`] and [`Entrypoint`] combination, rendering to surfaces of the
+    /// given [`RTFormat`]s.
+    pub fn support(&self, profile: Profile, entrypoint: Entrypoint, rt_formats: RTFormat) {
+        let mut state = self.lock();
+        state
+            .profiles
+            .retain(|p| (p.profile, p.entrypoint) != (profile, entrypoint));
+        state.profiles.push(SupportedProfile {
+            profile,
+            entrypoint,
+            rt_formats,
+            max_picture_size: None,
+            processing_rate: None,
+            max_concurrent_frames: None,
+        });
+    }
+
+    /// Sets the maximum picture size reported via the `MaxPictureWidth` and `MaxPictureHeight`
+    /// config attributes of a supported [`Profile`] and [`Entrypoint`] combination.
+    ///
+    /// By default, these attributes are not supported.
+    pub fn set_max_picture_size(
+        &self,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        width: u32,
+        height: u32,
+    ) {
+        let mut state = self.lock();
+        if let Some(p) = state
+            .profiles
+            .iter_mut()
+            .find(|p| (p.profile, p.entrypoint) == (profile, entrypoint))
+        {
+            p.max_picture_size = Some((width, height));
+        }
+    }
+
+    /// Sets the number of frames per second reported by `vaQueryProcessingRate` for a supported
+    /// [`Profile`] and [`Entrypoint`] combination, and reports support for the query via the
+    /// `ProcessingRate` config attribute.
+    ///
+    /// By default, processing rate queries are not supported.
+    pub fn set_processing_rate(&self, profile: Profile, entrypoint: Entrypoint, rate: u32) {
+        let mut state = self.lock();
+        if let Some(p) = state
+            .profiles
+            .iter_mut()
+            .find(|p| (p.profile, p.entrypoint) == (profile, entrypoint))
+        {
+            p.processing_rate = Some(rate);
+        }
+    }
+
+    /// Sets the number of frames that can be submitted together via `vaMFSubmit`, reported via the
+    /// `MultipleFrame` config attribute of a supported [`Profile`] and [`Entrypoint`] combination.
+    ///
+    /// By default, the attribute is not supported, and contexts can't be added to multi-frame
+    /// contexts.
+    pub fn set_max_concurrent_frames(&self, profile: Profile, entrypoint: Entrypoint, frames: u32) {
+        let mut state = self.lock();
+        if let Some(p) = state
+            .profiles
+            .iter_mut()
+            .find(|p| (p.profile, p.entrypoint) == (profile, entrypoint))
+        {
+            p.max_concurrent_frames = Some(frames);
+        }
+    }
+
+    /// Sets the optional libva features the fake driver claims to implement.
+    ///
+    /// This has to be called before the [`FakeBackend`] is passed to
+    /// [`Display::with_backend`][crate::display::Display::with_backend], which queries them once.
+    /// By default, the fake claims [`LibvaFeatures::SYNC_SURFACE2`],
+    /// [`LibvaFeatures::SYNC_BUFFER`], [`LibvaFeatures::PROCESSING_RATE`] and
+    /// [`LibvaFeatures::MULTI_FRAME`].
+    pub fn set_features(&self, features: LibvaFeatures) {
+        self.lock().features = features;
+    }
+
+    /// Sets the PCI ID reported via [`DisplayAttribType::PCIID`].
+    pub fn set_pci_id(&self, id: PciId) {
+        let value = (u32::from(id.vendor_id()) << 16 | u32::from(id.device_id())) as i32;
+        let mut state = self.lock();
+        if let Some(attrib) = state
+            .display_attributes
+            .iter_mut()
+            .find(|attrib| attrib.type_ == DisplayAttribType::PCIID)
+        {
+            attrib.value = value;
+        }
+    }
+
+    /// Makes the next call to the libva function `function` fail with `error`.
+    ///
+    /// The call fails without any side effects. Subsequent calls behave normally again.
+    pub fn fail_next(&self, function: &'static str, error: VAError) {
+        self.fail_after(function, 0, error);
+    }
+
+    /// Like [`FakeBackend::fail_next`], but lets `skip` calls to `function` succeed first.
+    pub fn fail_after(&self, function: &'static str, skip: usize, error: VAError) {
+        self.lock().failures.push((function, skip, error));
+    }
+
+    /// Makes the next picture finished with `vaEndPicture` fail to decode.
+    ///
+    /// Syncing the picture's target surface will return [`VAError::ERROR_DECODING_ERROR`], and
+    /// `vaQuerySurfaceError` will report `errors`, until a new picture targeting the surface is
+    /// begun.
+    pub fn corrupt_next_picture(&self, errors: Vec<SurfaceDecodeMBErrors>) {
+        self.lock().corruption = Some(errors);
+    }
+
+    /// Makes the next picture finished with `vaEndPicture` take `delay` to complete.
+    ///
+    /// Until then, its target surface, the submitted buffers and all coded buffers are busy:
+    /// syncing them blocks, and `vaQuerySurfaceStatus` reports [`SurfaceStatus::Rendering`].
+    pub fn delay_next_picture(&self, delay: Duration) {
+        self.lock().picture_delay = Some(delay);
+    }
+
+    /// Returns the number of objects of each type that currently exist.
+    pub fn live_objects(&self) -> LiveObjects {
+        let state = self.lock();
+        LiveObjects {
+            configs: state.configs.len(),
+            contexts: state.contexts.len() + state.mf_contexts.len(),
+            surfaces: state.surfaces.len(),
+            buffers: state.buffers.len(),
+            images: state.images.len(),
+        }
+    }
+
+    /// Returns the driver name that was set via `vaSetDriverName` before the display was
+    /// initialized.
+    pub fn driver_name(&self) -> Option<String> {
+        self.lock().active_driver.clone()
+    }
+
+    /// Returns all pictures that have been submitted via `vaEndPicture` (or `vaMFSubmit` for
+    /// contexts that are part of a multi-frame context), in submission order.
+    pub fn submissions(&self) -> Vec<Submission> {
+        self.lock().submissions.clone()
+    }
+
+    /// Returns how many pictures were discarded by beginning a new picture on their context
+    /// before ending them.
+    pub fn abandoned_pictures(&self) -> usize {
+        self.lock().abandoned_pictures
+    }
+
+    /// Returns how often the libva function `function` was called so far.
+    ///
+    /// Only functions that [`FakeBackend::fail_next`] can make fail are counted.
+    pub fn call_count(&self, function: &str) -> usize {
+        self.lock().calls.get(function).copied().unwrap_or(0)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while holding the lock can't leave the state inconsistent enough to matter for
+        // a fake.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `f` unless a failure was injected for `function`, and converts the result to a
+    /// [`VAStatus`].
+    ///
+    /// Failures are reported to the error callback, like a real driver would.
+    fn call(
+        &self,
+        function: &'static str,
+        f: impl FnOnce(&mut State) -> Result<(), VAError>,
+    ) -> VAStatus {
+        let mut state = self.lock();
+        *state.calls.entry(function).or_default() += 1;
+        let result = match state
+            .failures
+            .iter_mut()
+            .position(|(name, _, _)| *name == function)
+        {
+            Some(i) if state.failures[i].1 == 0 => Err(state.failures.remove(i).2),
+            Some(i) => {
+                state.failures[i].1 -= 1;
+                f(&mut state)
+            }
+            None => f(&mut state),
+        };
+        match result {
+            Ok(()) => VAStatus::SUCCESS,
+            Err(e) => {
+                let callback = state.error_callback;
+                // The callback may call back into the fake, so don't hold the lock.
+                drop(state);
+                if let Some(callback) = callback {
+                    callback.emit(&format!("{function} failed: {e:?}"));
+                }
+                e.into()
+            }
+        }
+    }
+}
+
+/// Number of live objects of each type in a [`FakeBackend`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LiveObjects {
+    pub configs: usize,
+    pub contexts: usize,
+    pub surfaces: usize,
+    pub buffers: usize,
+    pub images: usize,
+}
+
+/// A picture submitted to a [`FakeBackend`].
+#[derive(Debug, Clone)]
+pub struct Submission {
+    context: VAContextID,
+    target: VASurfaceID,
+    buffers: Vec<SubmittedBuffer>,
+}
+
+impl Submission {
+    /// Returns the ID of the context the picture was submitted to.
+    #[inline]
+    pub fn context(&self) -> VAContextID {
+        self.context
+    }
+
+    /// Returns the ID of the render target surface.
+    #[inline]
+    pub fn target(&self) -> VASurfaceID {
+        self.target
+    }
+
+    /// Returns the buffers passed to `vaRenderPicture`, in order.
+    #[inline]
+    pub fn buffers(&self) -> &[SubmittedBuffer] {
+        &self.buffers
+    }
+
+    /// Returns the [`BufferType`]s of all submitted buffers, in order.
+    pub fn buffer_types(&self) -> Vec<BufferType> {
+        self.buffers.iter().map(|buf| buf.ty).collect()
+    }
+}
+
+/// A snapshot of a buffer, taken when it was passed to `vaRenderPicture`.
+#[derive(Debug, Clone)]
+pub struct SubmittedBuffer {
+    id: VABufferID,
+    ty: BufferType,
+    data: Vec<u8>,
+}
+
+impl SubmittedBuffer {
+    #[inline]
+    pub fn id(&self) -> VABufferID {
+        self.id
+    }
+
+    #[inline]
+    pub fn ty(&self) -> BufferType {
+        self.ty
+    }
+
+    /// Returns the buffer contents at the time of submission.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+struct SupportedProfile {
+    profile: Profile,
+    entrypoint: Entrypoint,
+    rt_formats: RTFormat,
+    max_picture_size: Option<(u32, u32)>,
+    processing_rate: Option<u32>,
+    max_concurrent_frames: Option<u32>,
+}
+
+#[derive(Default)]
+struct State {
+    vendor: CString,
+    next_id: VAGenericID,
+    profiles: Vec<SupportedProfile>,
+    features: LibvaFeatures,
+    error_callback: Option<MessageCallback>,
+    info_callback: Option<MessageCallback>,
+    driver_name: Option<String>,
+    active_driver: Option<String>,
+    /// Injected failures, with the number of calls to let through before failing.
+    failures: Vec<(&'static str, usize, VAError)>,
+    display_attributes: Vec<DisplayAttribute>,
+    corruption: Option<Vec<SurfaceDecodeMBErrors>>,
+    picture_delay: Option<Duration>,
+
+    configs: HashMap<VAConfigID, FakeConfig>,
+    contexts: HashMap<VAContextID, FakeContext>,
+    /// The contexts added to each multi-frame context.
+    mf_contexts: HashMap<VAMFContextID, Vec<VAContextID>>,
+    surfaces: HashMap<VASurfaceID, FakeSurface>,
+    buffers: HashMap<VABufferID, FakeBuffer>,
+    images: HashMap<VAImageID, VAImage>,
+    submissions: Vec<Submission>,
+    /// Number of pictures that were begun again before being ended.
+    abandoned_pictures: usize,
+    /// Number of calls to each libva function that can fail.
+    calls: HashMap<&'static str, usize>,
+}
+
+/// The display attributes of a new [`FakeBackend`]: the usual color controls, rotation, a PCI ID
+/// and two sub-devices.
+fn default_display_attributes() -> Vec<DisplayAttribute> {
+    let read_write = DisplayAttribFlags::GETTABLE | DisplayAttribFlags::SETTABLE;
+    let attrib = |ty, min_value, max_value, value, flags| {
+        let mut attrib = DisplayAttribute::new(ty, value);
+        attrib.min_value = min_value;
+        attrib.max_value = max_value;
+        attrib.flags = flags;
+        attrib
+    };
+    vec![
+        attrib(DisplayAttribType::Brightness, -100, 100, 0, read_write),
+        attrib(DisplayAttribType::Contrast, 0, 200, 100, read_write),
+        attrib(DisplayAttribType::Hue, -180, 180, 0, read_write),
+        attrib(DisplayAttribType::Saturation, 0, 200, 100, read_write),
+        attrib(DisplayAttribType::Rotation, 0, 3, 0, read_write),
+        attrib(
+            DisplayAttribType::PCIID,
+            0,
+            0,
+            0x1234_5678,
+            DisplayAttribFlags::GETTABLE,
+        ),
+        attrib(DisplayAttribType::SubDevice, 0, 0, 0x3_0020, read_write),
+    ]
+}
+
+#[derive(Clone, Copy)]
+struct MessageCallback {
+    func: VAMessageCallback,
+    /// The `user_context` pointer, stored as an integer to keep [`State`] `Send`.
+    ctx: usize,
+}
+
+impl MessageCallback {
+    fn emit(self, message: &str) {
+        let message = CString::new(message).unwrap();
+        unsafe { (self.func)(self.ctx as *mut c_void, message.as_ptr()) }
+    }
+}
+
+struct FakeConfig {
+    profile: Profile,
+    entrypoint: Entrypoint,
+    attribs: Vec<ConfigAttrib>,
+}
+
+struct FakeContext {
+    profile: Profile,
+    entrypoint: Entrypoint,
+    render_targets: Vec<VASurfaceID>,
+    picture: Option<Submission>,
+    /// A picture that was ended, but is waiting for `vaMFSubmit`.
+    pending: Option<Submission>,
+}
+
+#[allow(dead_code)]
+struct FakeSurface {
+    format: RTFormat,
+    width: u32,
+    height: u32,
+    /// Errors reported by `vaQuerySurfaceError`, including the terminating entry. Empty if the
+    /// last picture decoded successfully.
+    decode_errors: Vec<SurfaceDecodeMBErrors>,
+    /// When the last picture targeting the surface completes.
+    busy_until: Option<Instant>,
+}
+
+struct FakeBuffer {
+    ty: BufferType,
+    data: Vec<u8>,
+    /// When the last picture using the buffer completes.
+    busy_until: Option<Instant>,
+}
+
+impl State {
+    fn alloc_id(&mut self) -> VAGenericID {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn supported(&self, profile: Profile, entrypoint: Entrypoint) -> Result<RTFormat, VAError> {
+        self.supported_profile(profile, entrypoint)
+            .map(|p| p.rt_formats)
+    }
+
+    fn supported_profile(
+        &self,
+        profile: Profile,
+        entrypoint: Entrypoint,
+    ) -> Result<&SupportedProfile, VAError> {
+        if !self.profiles.iter().any(|p| p.profile == profile) {
+            return Err(VAError::ERROR_UNSUPPORTED_PROFILE);
+        }
+        self.profiles
+            .iter()
+            .find(|p| p.profile == profile && p.entrypoint == entrypoint)
+            .ok_or(VAError::ERROR_UNSUPPORTED_ENTRYPOINT)
+    }
+
+    fn unique_profiles(&self) -> Vec<Profile> {
+        let mut profiles = Vec::new();
+        for p in &self.profiles {
+            if !profiles.contains(&p.profile) {
+                profiles.push(p.profile);
+            }
+        }
+        profiles
+    }
+
+    /// Returns how long the surface `id` stays busy, if it exists.
+    fn surface_busy_for(&self, id: VASurfaceID) -> Option<Duration> {
+        busy_for(self.surfaces.get(&id)?.busy_until)
+    }
+
+    fn buffer_busy_for(&self, id: VABufferID) -> Option<Duration> {
+        busy_for(self.buffers.get(&id)?.busy_until)
+    }
+
+    fn sync_surface(&self, id: VASurfaceID) -> Result<(), VAError> {
+        let surface = self
+            .surfaces
+            .get(&id)
+            .ok_or(VAError::ERROR_INVALID_SURFACE)?;
+        if surface.decode_errors.is_empty() {
+            Ok(())
+        } else {
+            Err(VAError::ERROR_DECODING_ERROR)
+        }
+    }
+
+    fn create_buffer(&mut self, ty: BufferType, data: Vec<u8>) -> VABufferID {
+        let id = self.alloc_id();
+        self.buffers.insert(
+            id,
+            FakeBuffer {
+                ty,
+                data,
+                busy_until: None,
+            },
+        );
+        id
+    }
+}
+
+fn busy_for(until: Option<Instant>) -> Option<Duration> {
+    until?.checked_duration_since(Instant::now())
+}
+
+/// Waits for an object that is busy for `busy_for`, for at most `timeout_ns`.
+///
+/// Returns [`VAError::ERROR_TIMEDOUT`] if the object is still busy afterwards.
+fn wait_busy(busy_for: Option<Duration>, timeout_ns: u64) -> Result<(), VAError> {
+    let Some(busy_for) = busy_for else {
+        return Ok(());
+    };
+    let timeout = Duration::from_nanos(timeout_ns);
+    thread::sleep(busy_for.min(timeout));
+    if busy_for > timeout {
+        Err(VAError::ERROR_TIMEDOUT)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the number of bytes in an image, and the pitch and offset of each plane.
+fn image_layout(format: PixelFormat, width: u32, height: u32) -> (u32, u32, [u32; 3], [u32; 3]) {
+    match format {
+        PixelFormat::NV12 | PixelFormat::NV21 => {
+            let luma = width * height;
+            let chroma = 2 * width.div_ceil(2) * height.div_ceil(2);
+            (luma + chroma, 2, [width, width, 0], [0, luma, 0])
+        }
+        PixelFormat::YUY2 | PixelFormat::UYVY => (2 * width * height, 1, [2 * width, 0, 0], [0; 3]),
+        _ => (4 * width * height, 1, [4 * width, 0, 0], [0; 3]),
+    }
+}
+
+unsafe impl Backend for FakeBackend {
+    fn features(&self) -> LibvaFeatures {
+        self.lock().features
+    }
+
+    unsafe fn vaDisplayIsValid(&self, _dpy: VADisplay) -> c_int {
+        1
+    }
+
+    unsafe fn vaInitialize(
+        &self,
+        _dpy: VADisplay,
+        major_version: *mut c_int,
+        minor_version: *mut c_int,
+    ) -> VAStatus {
+        let status = self.call("vaInitialize", |state| {
+            state.active_driver = state.driver_name.clone();
+            *major_version = 1;
+            *minor_version = 20;
+            Ok(())
+        });
+        let callback = self.lock().info_callback;
+        if let (VAStatus::SUCCESS, Some(callback)) = (status, callback) {
+            callback.emit("VA-API version 1.20.0");
+        }
+        status
+    }
+
+    unsafe fn vaSetErrorCallback(
+        &self,
+        _dpy: VADisplay,
+        callback: VAMessageCallback,
+        user_context: *mut c_void,
+    ) {
+        self.lock().error_callback = Some(MessageCallback {
+            func: callback,
+            ctx: user_context as usize,
+        });
+    }
+
+    unsafe fn vaSetInfoCallback(
+        &self,
+        _dpy: VADisplay,
+        callback: VAMessageCallback,
+        user_context: *mut c_void,
+    ) {
+        self.lock().info_callback = Some(MessageCallback {
+            func: callback,
+            ctx: user_context as usize,
+        });
+    }
+
+    unsafe fn vaSetDriverName(&self, _dpy: VADisplay, driver_name: *mut c_char) -> VAStatus {
+        self.call("vaSetDriverName", |state| {
+            let name = CStr::from_ptr(driver_name)
+                .to_str()
+                .map_err(|_| VAError::ERROR_INVALID_PARAMETER)?;
+            state.driver_name = Some(name.to_string());
+            Ok(())
+        })
+    }
+
+    unsafe fn vaTerminate(&self, _dpy: VADisplay) -> VAStatus {
+        self.call("vaTerminate", |_| Ok(()))
+    }
+
+    unsafe fn vaQueryVendorString(&self, _dpy: VADisplay) -> *const c_char {
+        // The string is owned by the shared state, which outlives the `Display` using `self`.
+        self.lock().vendor.as_ptr()
+    }
+
+    unsafe fn vaMaxNumProfiles(&self, _dpy: VADisplay) -> c_int {
+        self.lock().unique_profiles().len() as c_int
+    }
+
+    unsafe fn vaMaxNumEntrypoints(&self, _dpy: VADisplay) -> c_int {
+        self.lock().profiles.len() as c_int
+    }
+
+    unsafe fn vaMaxNumConfigAttributes(&self, _dpy: VADisplay) -> c_int {
+        MAX_CONFIG_ATTRIBUTES as c_int
+    }
+
+    unsafe fn vaQueryConfigProfiles(
+        &self,
+        _dpy: VADisplay,
+        profile_list: *mut Profile,
+        num_profiles: *mut c_int,
+    ) -> VAStatus {
+        self.call("vaQueryConfigProfiles", |state| {
+            let profiles = state.unique_profiles();
+            ptr::copy_nonoverlapping(profiles.as_ptr(), profile_list, profiles.len());
+            *num_profiles = profiles.len() as c_int;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaQueryConfigEntrypoints(
+        &self,
+        _dpy: VADisplay,
+        profile: Profile,
+        entrypoint_list: *mut Entrypoint,
+        num_entrypoints: *mut c_int,
+    ) -> VAStatus {
+        self.call("vaQueryConfigEntrypoints", |state| {
+            let entrypoints = state
+                .profiles
+                .iter()
+                .filter(|p| p.profile == profile)
+                .map(|p| p.entrypoint)
+                .collect::<Vec<_>>();
+            if entrypoints.is_empty() {
+                return Err(VAError::ERROR_UNSUPPORTED_PROFILE);
+            }
+            ptr::copy_nonoverlapping(entrypoints.as_ptr(), entrypoint_list, entrypoints.len());
+            *num_entrypoints = entrypoints.len() as c_int;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaGetConfigAttributes(
+        &self,
+        _dpy: VADisplay,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        attrib_list: *mut ConfigAttrib,
+        num_attribs: c_int,
+    ) -> VAStatus {
+        self.call("vaGetConfigAttributes", |state| {
+            let supported = state.supported_profile(profile, entrypoint)?;
+            let attribs = slice::from_raw_parts_mut(attrib_list, num_attribs as usize);
+            for attrib in attribs {
+                attrib.value = match (attrib.type_, supported.max_picture_size) {
+                    (ConfigAttribType::RTFormat, _) => supported.rt_formats.bits(),
+                    (ConfigAttribType::MaxPictureWidth, Some((width, _))) => width,
+                    (ConfigAttribType::MaxPictureHeight, Some((_, height))) => height,
+                    (ConfigAttribType::MultipleFrame, _) => match supported.max_concurrent_frames {
+                        Some(frames) => MultipleFrame::default()
+                            .with_max_num_concurrent_frames(frames)
+                            .raw(),
+                        None => ATTRIB_NOT_SUPPORTED,
+                    },
+                    (ConfigAttribType::ProcessingRate, _)
+                        if supported.processing_rate.is_some() =>
+                    {
+                        if entrypoint.is_encode() {
+                            ProcessingRate::ENCODE.bits()
+                        } else {
+                            ProcessingRate::DECODE.bits()
+                        }
+                    }
+                    _ => ATTRIB_NOT_SUPPORTED,
+                };
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaCreateConfig(
+        &self,
+        _dpy: VADisplay,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        attrib_list: *mut ConfigAttrib,
+        num_attribs: c_int,
+        config_id: *mut VAConfigID,
+    ) -> VAStatus {
+        self.call("vaCreateConfig", |state| {
+            let rt_formats = state.supported(profile, entrypoint)?;
+            let requested = if num_attribs == 0 {
+                &[][..]
+            } else {
+                slice::from_raw_parts(attrib_list, num_attribs as usize)
+            };
+
+            let mut attribs = vec![ConfigAttrib {
+                type_: ConfigAttribType::RTFormat,
+                value: rt_formats.bits(),
+            }];
+            for attrib in requested {
+                match attrib.type_ {
+                    ConfigAttribType::RTFormat => {
+                        if !rt_formats.contains(RTFormat::from_bits_truncate(attrib.value)) {
+                            return Err(VAError::ERROR_UNSUPPORTED_RT_FORMAT);
+                        }
+                        attribs[0].value = attrib.value;
+                    }
+                    _ => return Err(VAError::ERROR_ATTR_NOT_SUPPORTED),
+                }
+            }
+
+            let id = state.alloc_id();
+            state.configs.insert(
+                id,
+                FakeConfig {
+                    profile,
+                    entrypoint,
+                    attribs,
+                },
+            );
+            *config_id = id;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaDestroyConfig(&self, _dpy: VADisplay, config_id: VAConfigID) -> VAStatus {
+        self.call("vaDestroyConfig", |state| {
+            state
+                .configs
+                .remove(&config_id)
+                .map(drop)
+                .ok_or(VAError::ERROR_INVALID_CONFIG)
+        })
+    }
+
+    unsafe fn vaQueryConfigAttributes(
+        &self,
+        _dpy: VADisplay,
+        config_id: VAConfigID,
+        profile: *mut Profile,
+        entrypoint: *mut Entrypoint,
+        attrib_list: *mut ConfigAttrib,
+        num_attribs: *mut c_int,
+    ) -> VAStatus {
+        self.call("vaQueryConfigAttributes", |state| {
+            let config = state
+                .configs
+                .get(&config_id)
+                .ok_or(VAError::ERROR_INVALID_CONFIG)?;
+            *profile = config.profile;
+            *entrypoint = config.entrypoint;
+            ptr::copy_nonoverlapping(config.attribs.as_ptr(), attrib_list, config.attribs.len());
+            *num_attribs = config.attribs.len() as c_int;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaQuerySurfaceAttributes(
+        &self,
+        _dpy: VADisplay,
+        config: VAConfigID,
+        attrib_list: *mut SurfaceAttrib,
+        num_attribs: *mut c_uint,
+    ) -> VAStatus {
+        self.call("vaQuerySurfaceAttributes", |state| {
+            let config = state
+                .configs
+                .get(&config)
+                .ok_or(VAError::ERROR_INVALID_CONFIG)?;
+
+            let readable = SurfaceAttribFlags::GETTABLE;
+            let attrib = |ty, flags, value| SurfaceAttrib {
+                type_: ty,
+                flags,
+                value: GenericValue::int(value),
+            };
+            let mut attribs = vec![attrib(
+                SurfaceAttribType::PixelFormat,
+                readable | SurfaceAttribFlags::SETTABLE,
+                PixelFormat::NV12.to_u32_le() as i32,
+            )];
+            if config.entrypoint == Entrypoint::VideoProc {
+                for format in [PixelFormat::RGBA, PixelFormat::BGRA] {
+                    attribs.push(attrib(
+                        SurfaceAttribType::PixelFormat,
+                        readable | SurfaceAttribFlags::SETTABLE,
+                        format.to_u32_le() as i32,
+                    ));
+                }
+            }
+            attribs.extend([
+                attrib(SurfaceAttribType::MinWidth, readable, 1),
+                attrib(SurfaceAttribType::MaxWidth, readable, MAX_DIMENSION as i32),
+                attrib(SurfaceAttribType::MinHeight, readable, 1),
+                attrib(SurfaceAttribType::MaxHeight, readable, MAX_DIMENSION as i32),
+                attrib(
+                    SurfaceAttribType::MemoryType,
+                    readable | SurfaceAttribFlags::SETTABLE,
+                    SurfaceAttribMemoryType::VA.bits() as i32,
+                ),
+            ]);
+
+            let capacity = *num_attribs as usize;
+            *num_attribs = attribs.len() as c_uint;
+            if attrib_list.is_null() {
+                return Ok(());
+            }
+            if capacity < attribs.len() {
+                return Err(VAError::ERROR_MAX_NUM_EXCEEDED);
+            }
+            ptr::copy_nonoverlapping(attribs.as_ptr(), attrib_list, attribs.len());
+            Ok(())
+        })
+    }
+
+    unsafe fn vaCreateSurfaces(
+        &self,
+        _dpy: VADisplay,
+        format: RTFormat,
+        width: c_uint,
+        height: c_uint,
+        surfaces: *mut VASurfaceID,
+        num_surfaces: c_uint,
+        _attrib_list: *mut SurfaceAttrib,
+        _num_attribs: c_uint,
+    ) -> VAStatus {
+        self.call("vaCreateSurfaces", |state| {
+            if format.bits().count_ones() != 1 {
+                return Err(VAError::ERROR_UNSUPPORTED_RT_FORMAT);
+            }
+            if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+                return Err(VAError::ERROR_RESOLUTION_NOT_SUPPORTED);
+            }
+            for i in 0..num_surfaces as usize {
+                let id = state.alloc_id();
+                state.surfaces.insert(
+                    id,
+                    FakeSurface {
+                        format,
+                        width,
+                        height,
+                        decode_errors: Vec::new(),
+                        busy_until: None,
+                    },
+                );
+                *surfaces.add(i) = id;
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaDestroySurfaces(
+        &self,
+        _dpy: VADisplay,
+        surfaces: *mut VASurfaceID,
+        num_surfaces: c_int,
+    ) -> VAStatus {
+        self.call("vaDestroySurfaces", |state| {
+            let ids = slice::from_raw_parts(surfaces, num_surfaces as usize);
+            if ids.iter().any(|id| !state.surfaces.contains_key(id)) {
+                return Err(VAError::ERROR_INVALID_SURFACE);
+            }
+            for id in ids {
+                state.surfaces.remove(id);
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaCreateContext(
+        &self,
+        _dpy: VADisplay,
+        config_id: VAConfigID,
+        picture_width: c_int,
+        picture_height: c_int,
+        _flag: c_int,
+        render_targets: *mut VASurfaceID,
+        num_render_targets: c_int,
+        context: *mut VAContextID,
+    ) -> VAStatus {
+        self.call("vaCreateContext", |state| {
+            let config = state
+                .configs
+                .get(&config_id)
+                .ok_or(VAError::ERROR_INVALID_CONFIG)?;
+            let (profile, entrypoint) = (config.profile, config.entrypoint);
+            if picture_width <= 0 || picture_height <= 0 {
+                return Err(VAError::ERROR_RESOLUTION_NOT_SUPPORTED);
+            }
+            let render_targets = if num_render_targets <= 0 {
+                Vec::new()
+            } else {
+                slice::from_raw_parts(render_targets, num_render_targets as usize).to_vec()
+            };
+            if render_targets
+                .iter()
+                .any(|id| !state.surfaces.contains_key(id))
+            {
+                return Err(VAError::ERROR_INVALID_SURFACE);
+            }
+            let id = state.alloc_id();
+            state.contexts.insert(
+                id,
+                FakeContext {
+                    profile,
+                    entrypoint,
+                    render_targets,
+                    picture: None,
+                    pending: None,
+                },
+            );
+            *context = id;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaDestroyContext(&self, _dpy: VADisplay, context: VAContextID) -> VAStatus {
+        self.call("vaDestroyContext", |state| {
+            if state.mf_contexts.remove(&context).is_some() {
+                return Ok(());
+            }
+            state
+                .contexts
+                .remove(&context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            for contexts in state.mf_contexts.values_mut() {
+                contexts.retain(|&id| id != context);
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaCreateBuffer(
+        &self,
+        _dpy: VADisplay,
+        context: VAContextID,
+        type_: BufferType,
+        size: c_uint,
+        num_elements: c_uint,
+        data: *mut c_void,
+        buf_id: *mut VABufferID,
+    ) -> VAStatus {
+        self.call("vaCreateBuffer", |state| {
+            if !state.contexts.contains_key(&context) {
+                return Err(VAError::ERROR_INVALID_CONTEXT);
+            }
+            let len = size as usize * num_elements as usize;
+            let contents = if data.is_null() {
+                vec![0; len]
+            } else {
+                slice::from_raw_parts(data.cast::<u8>(), len).to_vec()
+            };
+            *buf_id = state.create_buffer(type_, contents);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaCreateBuffer2(
+        &self,
+        _dpy: VADisplay,
+        context: VAContextID,
+        type_: BufferType,
+        width: c_uint,
+        height: c_uint,
+        unit_size: *mut c_uint,
+        pitch: *mut c_uint,
+        buf_id: *mut VABufferID,
+    ) -> VAStatus {
+        self.call("vaCreateBuffer2", |state| {
+            if !state.contexts.contains_key(&context) {
+                return Err(VAError::ERROR_INVALID_CONTEXT);
+            }
+            // Rows are tightly packed bytes.
+            *unit_size = 1;
+            *pitch = width;
+            *buf_id = state.create_buffer(type_, vec![0; width as usize * height as usize]);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaMapBuffer(
+        &self,
+        _dpy: VADisplay,
+        buf_id: VABufferID,
+        pbuf: *mut *mut c_void,
+    ) -> VAStatus {
+        self.call("vaMapBuffer", |state| {
+            // The `Vec`'s heap allocation stays in place until the buffer is destroyed.
+            let buffer = state
+                .buffers
+                .get_mut(&buf_id)
+                .ok_or(VAError::ERROR_INVALID_BUFFER)?;
+            *pbuf = buffer.data.as_mut_ptr().cast();
+            Ok(())
+        })
+    }
+
+    unsafe fn vaUnmapBuffer(&self, _dpy: VADisplay, buf_id: VABufferID) -> VAStatus {
+        self.call("vaUnmapBuffer", |state| {
+            if state.buffers.contains_key(&buf_id) {
+                Ok(())
+            } else {
+                Err(VAError::ERROR_INVALID_BUFFER)
+            }
+        })
+    }
+
+    unsafe fn vaDestroyBuffer(&self, _dpy: VADisplay, buffer_id: VABufferID) -> VAStatus {
+        self.call("vaDestroyBuffer", |state| {
+            state
+                .buffers
+                .remove(&buffer_id)
+                .map(drop)
+                .ok_or(VAError::ERROR_INVALID_BUFFER)
+        })
+    }
+
+    unsafe fn vaBeginPicture(
+        &self,
+        _dpy: VADisplay,
+        context: VAContextID,
+        render_target: VASurfaceID,
+    ) -> VAStatus {
+        self.call("vaBeginPicture", |state| {
+            let surface = state
+                .surfaces
+                .get_mut(&render_target)
+                .ok_or(VAError::ERROR_INVALID_SURFACE)?;
+            surface.decode_errors.clear();
+            let cx = state
+                .contexts
+                .get_mut(&context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            if !cx.render_targets.is_empty() && !cx.render_targets.contains(&render_target) {
+                return Err(VAError::ERROR_INVALID_SURFACE);
+            }
+            // Like most drivers, discard any picture that wasn't finished with `vaEndPicture`.
+            let abandoned = cx.picture.replace(Submission {
+                context,
+                target: render_target,
+                buffers: Vec::new(),
+            });
+            if abandoned.is_some() {
+                state.abandoned_pictures += 1;
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaRenderPicture(
+        &self,
+        _dpy: VADisplay,
+        context: VAContextID,
+        buffers: *mut VABufferID,
+        num_buffers: c_int,
+    ) -> VAStatus {
+        self.call("vaRenderPicture", |state| {
+            let ids = slice::from_raw_parts(buffers, num_buffers as usize);
+            let mut snapshots = Vec::with_capacity(ids.len());
+            for id in ids {
+                let buffer = state.buffers.get(id).ok_or(VAError::ERROR_INVALID_BUFFER)?;
+                snapshots.push(SubmittedBuffer {
+                    id: *id,
+                    ty: buffer.ty,
+                    data: buffer.data.clone(),
+                });
+            }
+
+            let picture = state
+                .contexts
+                .get_mut(&context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?
+                .picture
+                .as_mut()
+                .ok_or(VAError::ERROR_OPERATION_FAILED)?;
+            picture.buffers.extend(snapshots);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaEndPicture(&self, _dpy: VADisplay, context: VAContextID) -> VAStatus {
+        self.call("vaEndPicture", |state| {
+            let in_mf_context = state
+                .mf_contexts
+                .values()
+                .any(|contexts| contexts.contains(&context));
+            let cx = state
+                .contexts
+                .get_mut(&context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            let picture = cx.picture.take().ok_or(VAError::ERROR_OPERATION_FAILED)?;
+            if let Some(delay) = state.picture_delay.take() {
+                let until = Some(Instant::now() + delay);
+                if let Some(surface) = state.surfaces.get_mut(&picture.target) {
+                    surface.busy_until = until;
+                }
+                for (id, buffer) in &mut state.buffers {
+                    let submitted = picture.buffers.iter().any(|b| b.id == *id);
+                    if submitted || buffer.ty == BufferType::EncCoded {
+                        buffer.busy_until = until;
+                    }
+                }
+            }
+            if let Some(mut errors) = state.corruption.take() {
+                errors.push(SurfaceDecodeMBErrors::terminator());
+                if let Some(surface) = state.surfaces.get_mut(&picture.target) {
+                    surface.decode_errors = errors;
+                }
+            }
+            if in_mf_context {
+                // Wait for `vaMFSubmit`.
+                if let Some(cx) = state.contexts.get_mut(&context) {
+                    cx.pending = Some(picture);
+                }
+            } else {
+                state.submissions.push(picture);
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaSyncSurface(&self, _dpy: VADisplay, render_target: VASurfaceID) -> VAStatus {
+        // Don't hold the lock while waiting.
+        let busy_for = self.lock().surface_busy_for(render_target);
+        if let Some(busy_for) = busy_for {
+            thread::sleep(busy_for);
+        }
+        self.call("vaSyncSurface", |state| state.sync_surface(render_target))
+    }
+
+    unsafe fn vaQueryProcessingRate(
+        &self,
+        _dpy: VADisplay,
+        config: VAConfigID,
+        _proc_buf: *mut VAProcessingRateParameter,
+        processing_rate: *mut c_uint,
+    ) -> VAStatus {
+        self.call("vaQueryProcessingRate", |state| {
+            let config = state
+                .configs
+                .get(&config)
+                .ok_or(VAError::ERROR_INVALID_CONFIG)?;
+            let supported = state.supported_profile(config.profile, config.entrypoint)?;
+            *processing_rate = supported
+                .processing_rate
+                .ok_or(VAError::ERROR_UNIMPLEMENTED)?;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaSyncSurface2(
+        &self,
+        _dpy: VADisplay,
+        surface: VASurfaceID,
+        timeout_ns: u64,
+    ) -> VAStatus {
+        let busy_for = self.lock().surface_busy_for(surface);
+        let waited = wait_busy(busy_for, timeout_ns);
+        self.call("vaSyncSurface2", |state| {
+            waited?;
+            state.sync_surface(surface)
+        })
+    }
+
+    unsafe fn vaQuerySurfaceStatus(
+        &self,
+        _dpy: VADisplay,
+        render_target: VASurfaceID,
+        status: *mut SurfaceStatus,
+    ) -> VAStatus {
+        self.call("vaQuerySurfaceStatus", |state| {
+            if !state.surfaces.contains_key(&render_target) {
+                return Err(VAError::ERROR_INVALID_SURFACE);
+            }
+            *status = match state.surface_busy_for(render_target) {
+                Some(_) => SurfaceStatus::Rendering,
+                None => SurfaceStatus::Ready,
+            };
+            Ok(())
+        })
+    }
+
+    unsafe fn vaQuerySurfaceError(
+        &self,
+        _dpy: VADisplay,
+        surface: VASurfaceID,
+        error_status: VAStatus,
+        error_info: *mut *mut c_void,
+    ) -> VAStatus {
+        self.call("vaQuerySurfaceError", |state| {
+            let surface = state
+                .surfaces
+                .get_mut(&surface)
+                .ok_or(VAError::ERROR_INVALID_SURFACE)?;
+            if error_status != VAError::ERROR_DECODING_ERROR {
+                return Err(VAError::ERROR_UNIMPLEMENTED);
+            }
+            *error_info = if surface.decode_errors.is_empty() {
+                ptr::null_mut()
+            } else {
+                surface.decode_errors.as_mut_ptr().cast()
+            };
+            Ok(())
+        })
+    }
+
+    unsafe fn vaSyncBuffer(
+        &self,
+        _dpy: VADisplay,
+        buf_id: VABufferID,
+        timeout_ns: u64,
+    ) -> VAStatus {
+        let busy_for = self.lock().buffer_busy_for(buf_id);
+        let waited = wait_busy(busy_for, timeout_ns);
+        self.call("vaSyncBuffer", |state| {
+            waited?;
+            if state.buffers.contains_key(&buf_id) {
+                Ok(())
+            } else {
+                Err(VAError::ERROR_INVALID_BUFFER)
+            }
+        })
+    }
+
+    unsafe fn vaCreateMFContext(
+        &self,
+        _dpy: VADisplay,
+        mf_context: *mut VAMFContextID,
+    ) -> VAStatus {
+        self.call("vaCreateMFContext", |state| {
+            let id = state.alloc_id();
+            state.mf_contexts.insert(id, Vec::new());
+            *mf_context = id;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaMFAddContext(
+        &self,
+        _dpy: VADisplay,
+        mf_context: VAMFContextID,
+        context: VAContextID,
+    ) -> VAStatus {
+        self.call("vaMFAddContext", |state| {
+            let cx = state
+                .contexts
+                .get(&context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            let max_frames = state
+                .supported_profile(cx.profile, cx.entrypoint)?
+                .max_concurrent_frames
+                .ok_or(VAError::ERROR_UNSUPPORTED_ENTRYPOINT)?;
+            let contexts = state
+                .mf_contexts
+                .get_mut(&mf_context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            if contexts.contains(&context) || contexts.len() >= max_frames as usize {
+                return Err(VAError::ERROR_OPERATION_FAILED);
+            }
+            contexts.push(context);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaMFReleaseContext(
+        &self,
+        _dpy: VADisplay,
+        mf_context: VAMFContextID,
+        context: VAContextID,
+    ) -> VAStatus {
+        self.call("vaMFReleaseContext", |state| {
+            let contexts = state
+                .mf_contexts
+                .get_mut(&mf_context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            let index = contexts
+                .iter()
+                .position(|&id| id == context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            contexts.remove(index);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaMFSubmit(
+        &self,
+        _dpy: VADisplay,
+        mf_context: VAMFContextID,
+        contexts: *mut VAContextID,
+        num_contexts: c_int,
+    ) -> VAStatus {
+        self.call("vaMFSubmit", |state| {
+            let ids = slice::from_raw_parts(contexts, num_contexts as usize);
+            let added = state
+                .mf_contexts
+                .get(&mf_context)
+                .ok_or(VAError::ERROR_INVALID_CONTEXT)?;
+            if ids.iter().any(|id| !added.contains(id)) {
+                return Err(VAError::ERROR_INVALID_CONTEXT);
+            }
+            if ids.iter().any(|id| state.contexts[id].pending.is_none()) {
+                return Err(VAError::ERROR_INVALID_PARAMETER);
+            }
+            for id in ids {
+                if let Some(picture) = state.contexts.get_mut(id).and_then(|cx| cx.pending.take()) {
+                    state.submissions.push(picture);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaMaxNumDisplayAttributes(&self, _dpy: VADisplay) -> c_int {
+        self.lock().display_attributes.len() as c_int
+    }
+
+    unsafe fn vaQueryDisplayAttributes(
+        &self,
+        _dpy: VADisplay,
+        attr_list: *mut DisplayAttribute,
+        num_attributes: *mut c_int,
+    ) -> VAStatus {
+        self.call("vaQueryDisplayAttributes", |state| {
+            let attribs = &state.display_attributes;
+            ptr::copy_nonoverlapping(attribs.as_ptr(), attr_list, attribs.len());
+            *num_attributes = attribs.len() as c_int;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaGetDisplayAttributes(
+        &self,
+        _dpy: VADisplay,
+        attr_list: *mut DisplayAttribute,
+        num_attributes: c_int,
+    ) -> VAStatus {
+        self.call("vaGetDisplayAttributes", |state| {
+            for attrib in slice::from_raw_parts_mut(attr_list, num_attributes as usize) {
+                *attrib = *state
+                    .display_attributes
+                    .iter()
+                    .find(|a| a.type_ == attrib.type_)
+                    .ok_or(VAError::ERROR_ATTR_NOT_SUPPORTED)?;
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaSetDisplayAttributes(
+        &self,
+        _dpy: VADisplay,
+        attr_list: *mut DisplayAttribute,
+        num_attributes: c_int,
+    ) -> VAStatus {
+        self.call("vaSetDisplayAttributes", |state| {
+            for new in slice::from_raw_parts(attr_list, num_attributes as usize) {
+                let attrib = state
+                    .display_attributes
+                    .iter_mut()
+                    .find(|a| a.type_ == new.type_)
+                    .filter(|a| a.flags.contains(DisplayAttribFlags::SETTABLE))
+                    .ok_or(VAError::ERROR_ATTR_NOT_SUPPORTED)?;
+                if attrib.type_ == DisplayAttribType::SubDevice {
+                    // Only the current sub-device index is writable.
+                    attrib.value = (attrib.value & !0xf) | (new.value & 0xf);
+                } else if (attrib.min_value..=attrib.max_value).contains(&new.value) {
+                    attrib.value = new.value;
+                } else {
+                    return Err(VAError::ERROR_INVALID_PARAMETER);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaMaxNumImageFormats(&self, _dpy: VADisplay) -> c_int {
+        IMAGE_FORMATS.len() as c_int
+    }
+
+    unsafe fn vaQueryImageFormats(
+        &self,
+        _dpy: VADisplay,
+        format_list: *mut ImageFormat,
+        num_formats: *mut c_int,
+    ) -> VAStatus {
+        self.call("vaQueryImageFormats", |_| {
+            for (i, &format) in IMAGE_FORMATS.iter().enumerate() {
+                let mut fmt = ImageFormat::new(format);
+                match format {
+                    PixelFormat::NV12 => fmt.bits_per_pixel = 12,
+                    _ => {
+                        fmt.bits_per_pixel = 32;
+                        fmt.depth = 32;
+                    }
+                }
+                *format_list.add(i) = fmt;
+            }
+            *num_formats = IMAGE_FORMATS.len() as c_int;
+            Ok(())
+        })
+    }
+
+    unsafe fn vaCreateImage(
+        &self,
+        _dpy: VADisplay,
+        format: *mut ImageFormat,
+        width: c_int,
+        height: c_int,
+        image: *mut VAImage,
+    ) -> VAStatus {
+        self.call("vaCreateImage", |state| {
+            let format = *format;
+            if !IMAGE_FORMATS.contains(&format.fourcc) {
+                return Err(VAError::ERROR_INVALID_IMAGE_FORMAT);
+            }
+            if width <= 0 || height <= 0 {
+                return Err(VAError::ERROR_RESOLUTION_NOT_SUPPORTED);
+            }
+
+            let (width, height) = (width as u32, height as u32);
+            let (data_size, num_planes, pitches, offsets) =
+                image_layout(format.fourcc, width, height);
+
+            let mut raw = VAImage::zeroed();
+            raw.image_id = state.alloc_id();
+            raw.format = format;
+            raw.buf = state.create_buffer(BufferType::Image, vec![0; data_size as usize]);
+            raw.width = width as u16;
+            raw.height = height as u16;
+            raw.data_size = data_size;
+            raw.num_planes = num_planes;
+            raw.pitches = pitches;
+            raw.offsets = offsets;
+
+            image.write(raw);
+            state.images.insert(raw.image_id, raw);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaDestroyImage(&self, _dpy: VADisplay, image: VAImageID) -> VAStatus {
+        self.call("vaDestroyImage", |state| {
+            let image = state
+                .images
+                .remove(&image)
+                .ok_or(VAError::ERROR_INVALID_IMAGE)?;
+            state.buffers.remove(&image.buf);
+            Ok(())
+        })
+    }
+
+    unsafe fn vaGetImage(
+        &self,
+        _dpy: VADisplay,
+        surface: VASurfaceID,
+        _x: c_int,
+        _y: c_int,
+        _width: c_uint,
+        _height: c_uint,
+        image: VAImageID,
+    ) -> VAStatus {
+        self.call("vaGetImage", |state| {
+            if !state.surfaces.contains_key(&surface) {
+                return Err(VAError::ERROR_INVALID_SURFACE);
+            }
+            if !state.images.contains_key(&image) {
+                return Err(VAError::ERROR_INVALID_IMAGE);
+            }
+            Ok(())
+        })
+    }
+
+    unsafe fn vaDeriveImage(
+        &self,
+        _dpy: VADisplay,
+        _surface: VASurfaceID,
+        _image: *mut VAImage,
+    ) -> VAStatus {
+        // Like many real drivers, don't support this, which forces users to use `vaGetImage`.
+        self.call("vaDeriveImage", |_| Err(VAError::ERROR_OPERATION_FAILED))
+    }
+
+    unsafe fn vaQueryVideoProcFilters(
+        &self,
+        _dpy: VADisplay,
+        context: VAContextID,
+        _filters: *mut FilterType,
+        num_filters: *mut c_uint,
+    ) -> VAStatus {
+        self.call("vaQueryVideoProcFilters", |state| {
+            if !state.contexts.contains_key(&context) {
+                return Err(VAError::ERROR_INVALID_CONTEXT);
+            }
+            *num_filters = 0;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    buffer::{Buffer, BufferType},
+    config::Config,
+    context::Context,
+    display::{Display, DisplayApi, LibvaFeatures},
+    error::{ErrorKind, Object, VAError},
+    image::Image,
+    surface::{RTFormat, Surface},
+    Entrypoint, PixelFormat, Profile,
+};
+
+use super::{FakeBackend, LiveObjects};
+
+#[test]
+fn display_info() {
+    let display = Display::with_backend(FakeBackend::new()).unwrap();
+    assert_eq!(display.display_api(), DisplayApi::Custom);
+    assert_eq!(
+        display.query_vendor_string().unwrap(),
+        "v-ayylmao fake driver"
+    );
+
+    let profiles = display.query_profiles().unwrap();
+    assert!(profiles.contains(Profile::JPEGBaseline));
+    assert!(profiles.contains(Profile::None));
+    assert!(!profiles.contains(Profile::HEVCMain));
+    assert!(display
+        .query_entrypoints(Profile::JPEGBaseline)
+        .unwrap()
+        .contains(Entrypoint::VLD));
+}
+
+#[test]
+fn object_lifecycle() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let context = Context::new(&config, 64, 64).unwrap();
+    let surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let buffer = Buffer::new_data(&context, BufferType::SliceData, &[1, 2, 3]).unwrap();
+    let image = Image::new(&display, PixelFormat::NV12.into(), 64, 64).unwrap();
+
+    assert_eq!(
+        fake.live_objects(),
+        LiveObjects {
+            configs: 1,
+            contexts: 1,
+            surfaces: 1,
+            // The image's data is stored in a buffer too.
+            buffers: 2,
+            images: 1,
+        }
+    );
+
+    drop((config, context, surface, buffer, image));
+    assert_eq!(fake.live_objects(), LiveObjects::default());
+
+    // Objects keep the display alive.
+    let config = Config::new(&display, Profile::None, Entrypoint::VideoProc).unwrap();
+    drop(display);
+    assert_eq!(fake.live_objects().configs, 1);
+    drop(config);
+    assert_eq!(fake.live_objects(), LiveObjects::default());
+}
+
+#[test]
+fn error_propagation() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+
+    let err = Config::new(&display, Profile::HEVCMain, Entrypoint::VLD)
+        .err()
+        .unwrap();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_UNSUPPORTED_PROFILE));
+
+    let err = Config::new(&display, Profile::JPEGBaseline, Entrypoint::EncPicture)
+        .err()
+        .unwrap();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_UNSUPPORTED_ENTRYPOINT));
+
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    fake.fail_next("vaCreateContext", VAError::ERROR_HW_BUSY);
+    let err = Context::new(&config, 64, 64).err().unwrap();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_HW_BUSY));
+    assert_eq!(fake.live_objects().contexts, 0);
+
+    // Only the next call is affected.
+    Context::new(&config, 64, 64).unwrap();
+}
+
+#[test]
+fn error_context() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+
+    fake.fail_next("vaBeginPicture", VAError::ERROR_INVALID_PARAMETER);
+    let err = context.begin_picture(&surface).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Libva);
+    assert_eq!(err.function(), Some("vaBeginPicture"));
+    assert_eq!(
+        err.objects(),
+        [Object::Context(context.id), Object::Surface(surface.id())]
+    );
+    assert_eq!(err.surface(), Some(surface.id()));
+    assert_eq!(err.context(), Some(context.id));
+    assert_eq!(err.buffer(), None);
+    // The description doesn't come from libva, so this works without it.
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "vaBeginPicture failed (context {}, surface {}): ERROR_INVALID_PARAMETER \
+            (invalid parameter)",
+            context.id,
+            surface.id()
+        )
+    );
+    assert!(std::error::Error::source(&err).is_none());
+
+    let err = Image::new(&display, PixelFormat::NV12.into(), u32::MAX, 64)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::IntConversion);
+    assert_eq!(err.function(), None);
+    let source = std::error::Error::source(&err).unwrap();
+    assert!(source.is::<std::num::TryFromIntError>());
+}
+
+#[test]
+fn submission() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+
+    let buf = Buffer::new_param(&context, BufferType::PictureParameter, 0xdeadbeef_u32).unwrap();
+    let mut picture = context.begin_picture(&surface).unwrap();
+    picture.render_picture(buf).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+
+    let submissions = fake.submissions();
+    assert_eq!(submissions.len(), 1);
+    assert_eq!(
+        submissions[0].buffer_types(),
+        [BufferType::PictureParameter]
+    );
+    assert_eq!(
+        submissions[0].buffers()[0].data(),
+        0xdeadbeef_u32.to_ne_bytes()
+    );
+}
+
+#[test]
+fn missing_optional_function() {
+    let fake = FakeBackend::new();
+    fake.set_features(LibvaFeatures::SYNC_SURFACE2);
+    let display = Display::with_backend(fake.clone()).unwrap();
+    assert_eq!(display.features(), LibvaFeatures::SYNC_SURFACE2);
+
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let context = Context::new(&config, 64, 64).unwrap();
+    let mut buffer = Buffer::new_data(&context, BufferType::SliceData, &[1, 2, 3]).unwrap();
+    let err = buffer.sync().unwrap_err();
+    assert_eq!(err.missing_function(), Some("vaSyncBuffer"));
+    assert_eq!(err.as_libva(), None);
+    assert_eq!(
+        err.to_string(),
+        "`vaSyncBuffer` is not supported by this libva version"
+    );
+}
+
+#[test]
+fn display_builder() {
+    let fake = FakeBackend::new();
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let errors = messages.clone();
+    let infos = messages.clone();
+    let display = Display::builder()
+        .driver_name("fake")
+        .error_handler(move |msg| errors.lock().unwrap().push(format!("error: {msg}")))
+        .info_handler(move |msg| infos.lock().unwrap().push(format!("info: {msg}")))
+        .min_version(1, 20)
+        .open_backend(fake.clone())
+        .unwrap();
+    assert_eq!(fake.driver_name().as_deref(), Some("fake"));
+
+    fake.fail_next("vaCreateConfig", VAError::ERROR_ALLOCATION_FAILED);
+    assert!(Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).is_err());
+    assert_eq!(
+        *messages.lock().unwrap(),
+        [
+            "info: VA-API version 1.20.0",
+            "error: vaCreateConfig failed: ERROR_ALLOCATION_FAILED",
+        ]
+    );
+
+    let err = Display::builder()
+        .min_version(1, 21)
+        .open_backend(FakeBackend::new())
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "VA-API version 1.20 is older than the required version 1.21"
+    );
+
+    // Setting the driver name after initialization has no effect.
+    let fake = FakeBackend::new();
+    let mut display = Display::with_backend(fake.clone()).unwrap();
+    display.set_driver_name("fake").unwrap();
+    assert_eq!(fake.driver_name(), None);
+}
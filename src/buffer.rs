@@ -2,20 +2,26 @@
 
 use std::{
     ffi::{c_int, c_uint, c_void},
+    future::Future,
     marker::PhantomData,
     mem,
     ops::{Deref, DerefMut},
+    pin::Pin,
     ptr,
     sync::Arc,
+    task::{self, Poll},
+    time::Duration,
 };
 
 use bytemuck::{AnyBitPattern, NoUninit, Pod};
 
 use crate::{
     check, check_log,
-    context::Context,
-    display::DisplayOwner,
+    context::{Context, ContextOwner},
+    display::{DisplayOwner, LibvaFeatures},
+    error::Object,
     raw::{VABufferID, VA_TIMEOUT_INFINITE},
+    wait::{self, Waiter},
     Result,
 };
 
@@ -75,12 +81,17 @@ ffi_enum! {
 }
 
 /// A buffer that holds arbitrary data.
+#[derive(Debug)]
 pub struct RawBuffer {
     d: Arc<DisplayOwner>,
     id: VABufferID,
+    ty: BufferType,
     #[allow(dead_code)]
     elem_size: usize,
     capacity: usize,
+    /// Buffers have to be destroyed before their context, and may outlive the [`Context`] while
+    /// the driver is still reading them.
+    _context: Arc<ContextOwner>,
 }
 
 impl Drop for RawBuffer {
@@ -88,12 +99,56 @@ impl Drop for RawBuffer {
         unsafe {
             check_log(
                 self.d.libva.vaDestroyBuffer(self.d.raw, self.id),
-                "vaDestroyBuffer call in drop",
+                "vaDestroyBuffer",
+                &[Object::Buffer(self.id)],
             );
         }
     }
 }
 
+impl RawBuffer {
+    /// Creates a buffer holding `num_elements` elements of `size` bytes each, initialized with
+    /// `data`.
+    pub(crate) fn with_data(
+        cx: &Context,
+        buf_ty: BufferType,
+        size: c_uint,
+        num_elements: c_uint,
+        data: &mut [u8],
+    ) -> Result<Self> {
+        assert_eq!(data.len(), size as usize * num_elements as usize);
+        let mut buf_id = 0;
+        unsafe {
+            check(
+                cx.d.libva.vaCreateBuffer(
+                    cx.d.raw,
+                    cx.id,
+                    buf_ty,
+                    size,
+                    num_elements,
+                    data.as_mut_ptr().cast(),
+                    &mut buf_id,
+                ),
+                "vaCreateBuffer",
+                &[Object::Context(cx.id)],
+            )?;
+        }
+        Ok(RawBuffer {
+            d: cx.d.clone(),
+            id: buf_id,
+            ty: buf_ty,
+            elem_size: size as usize,
+            capacity: num_elements as usize,
+            _context: cx.owner().clone(),
+        })
+    }
+
+    #[inline]
+    pub(crate) fn id(&self) -> VABufferID {
+        self.id
+    }
+}
+
 impl<T> From<Buffer<T>> for RawBuffer {
     fn from(buf: Buffer<T>) -> Self {
         buf.raw
@@ -111,22 +166,28 @@ impl Buffer<u8> {
     pub fn new_data(cx: &Context, buf_ty: BufferType, data: &[u8]) -> Result<Buffer<u8>> {
         let mut buf_id = 0;
         unsafe {
-            check(cx.d.libva.vaCreateBuffer(
-                cx.d.raw,
-                cx.id,
-                buf_ty,
-                c_uint::try_from(data.len()).unwrap(),
-                1,
-                data.as_ptr() as *mut _,
-                &mut buf_id,
-            ))?;
+            check(
+                cx.d.libva.vaCreateBuffer(
+                    cx.d.raw,
+                    cx.id,
+                    buf_ty,
+                    c_uint::try_from(data.len()).unwrap(),
+                    1,
+                    data.as_ptr() as *mut _,
+                    &mut buf_id,
+                ),
+                "vaCreateBuffer",
+                &[Object::Context(cx.id)],
+            )?;
         }
         Ok(Buffer {
             raw: RawBuffer {
                 d: cx.d.clone(),
                 id: buf_id,
+                ty: buf_ty,
                 elem_size: 1,
                 capacity: data.len(),
+                _context: cx.owner().clone(),
             },
             _p: PhantomData,
         })
@@ -140,22 +201,28 @@ impl<T> Buffer<T> {
     {
         let mut buf_id = 0;
         unsafe {
-            check(cx.d.libva.vaCreateBuffer(
-                cx.d.raw,
-                cx.id,
-                buf_ty,
-                mem::size_of::<T>() as c_uint,
-                c_uint::try_from(num_elements).unwrap(),
-                ptr::null_mut(),
-                &mut buf_id,
-            ))?;
+            check(
+                cx.d.libva.vaCreateBuffer(
+                    cx.d.raw,
+                    cx.id,
+                    buf_ty,
+                    mem::size_of::<T>() as c_uint,
+                    c_uint::try_from(num_elements).unwrap(),
+                    ptr::null_mut(),
+                    &mut buf_id,
+                ),
+                "vaCreateBuffer",
+                &[Object::Context(cx.id)],
+            )?;
         }
         Ok(Buffer {
             raw: RawBuffer {
                 d: cx.d.clone(),
                 id: buf_id,
+                ty: buf_ty,
                 elem_size: mem::size_of::<T>(),
                 capacity: num_elements,
+                _context: cx.owner().clone(),
             },
             _p: PhantomData,
         })
@@ -170,22 +237,28 @@ impl<T> Buffer<T> {
     {
         let mut buf_id = 0;
         unsafe {
-            check(cx.d.libva.vaCreateBuffer(
-                cx.d.raw,
-                cx.id,
-                buf_ty,
-                mem::size_of::<T>() as c_uint,
-                1,
-                &mut content as *mut _ as *mut c_void,
-                &mut buf_id,
-            ))?;
+            check(
+                cx.d.libva.vaCreateBuffer(
+                    cx.d.raw,
+                    cx.id,
+                    buf_ty,
+                    mem::size_of::<T>() as c_uint,
+                    1,
+                    &mut content as *mut _ as *mut c_void,
+                    &mut buf_id,
+                ),
+                "vaCreateBuffer",
+                &[Object::Context(cx.id)],
+            )?;
         }
         Ok(Buffer {
             raw: RawBuffer {
                 d: cx.d.clone(),
                 id: buf_id,
+                ty: buf_ty,
                 elem_size: mem::size_of::<T>(),
                 capacity: 1,
+                _context: cx.owner().clone(),
             },
             _p: PhantomData,
         })
@@ -196,6 +269,12 @@ impl<T> Buffer<T> {
         self.raw.id
     }
 
+    /// Returns the [`BufferType`] the buffer was created with.
+    #[inline]
+    pub fn buffer_type(&self) -> BufferType {
+        self.raw.ty
+    }
+
     pub fn map(&mut self) -> Result<Mapping<'_, T>> {
         let mut ptr = ptr::null_mut();
         unsafe {
@@ -204,6 +283,8 @@ impl<T> Buffer<T> {
                     .d
                     .libva
                     .vaMapBuffer(self.raw.d.raw, self.raw.id, &mut ptr),
+                "vaMapBuffer",
+                &[Object::Buffer(self.raw.id)],
             )?;
         }
         Ok(Mapping {
@@ -214,18 +295,86 @@ impl<T> Buffer<T> {
         })
     }
 
+    /// Blocks until all pending operations writing to or reading from the buffer have finished.
+    ///
+    /// This requires [`LibvaFeatures::SYNC_BUFFER`].
     pub fn sync(&mut self) -> Result<()> {
-        unsafe {
-            check(
-                self.raw
-                    .d
-                    .libva
-                    .vaSyncBuffer(self.raw.d.raw, self.raw.id, VA_TIMEOUT_INFINITE),
-            )
+        sync_buffer(&self.raw.d, self.raw.id, VA_TIMEOUT_INFINITE)
+    }
+
+    /// Blocks until all pending operations on the buffer have finished, or until `timeout` has
+    /// elapsed.
+    ///
+    /// If the operations are still in progress after `timeout`, this fails with
+    /// [`VAError::ERROR_TIMEDOUT`][crate::error::VAError::ERROR_TIMEDOUT] (which is a
+    /// [transient][crate::error::Error::is_transient] error).
+    ///
+    /// This requires [`LibvaFeatures::SYNC_BUFFER`].
+    pub fn sync_timeout(&mut self, timeout: Duration) -> Result<()> {
+        sync_buffer(&self.raw.d, self.raw.id, wait::timeout_ns(timeout))
+    }
+
+    /// Returns a [`Future`] that completes once all pending operations on the buffer have
+    /// finished, without blocking the thread that polls it.
+    ///
+    /// This is typically used to wait for the output of an encode operation in a coded buffer.
+    /// This requires [`LibvaFeatures::SYNC_BUFFER`].
+    pub fn sync_async(&mut self) -> BufferSync<'_, T> {
+        BufferSync {
+            buffer: self,
+            waiter: None,
         }
     }
 }
 
+fn sync_buffer(d: &DisplayOwner, id: VABufferID, timeout_ns: u64) -> Result<()> {
+    d.require(LibvaFeatures::SYNC_BUFFER, "vaSyncBuffer")?;
+    unsafe {
+        check(
+            d.libva.vaSyncBuffer(d.raw, id, timeout_ns),
+            "vaSyncBuffer",
+            &[Object::Buffer(id)],
+        )
+    }
+}
+
+/// A [`Future`] that completes once all pending operations on a [`Buffer`] have finished.
+///
+/// Returned by [`Buffer::sync_async`]. If the operations are still in progress when the future is
+/// first polled, it waits on a helper thread, using `vaSyncBuffer` with a timeout. The thread is
+/// shared with the other pending [`BufferSync`] and [`SurfaceSync`][crate::surface::SurfaceSync]
+/// futures of the display, and dropping a future doesn't block.
+#[must_use = "futures do nothing unless polled"]
+pub struct BufferSync<'a, T> {
+    buffer: &'a mut Buffer<T>,
+    waiter: Option<Waiter>,
+}
+
+impl<T> Future for BufferSync<'_, T> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let waiter = match &mut this.waiter {
+            Some(waiter) => waiter,
+            None => {
+                let (d, id) = (this.buffer.raw.d.clone(), this.buffer.raw.id);
+                if wait::finished(sync_buffer(&d, id, 0))? {
+                    return Poll::Ready(Ok(()));
+                }
+                // If the future is dropped and the buffer destroyed while the helper thread waits
+                // for it, the wait fails with `ERROR_INVALID_BUFFER`, which is ignored since the
+                // waiter was cancelled.
+                this.waiter
+                    .insert(Waiter::spawn(&this.buffer.raw.d.waits, move |timeout| {
+                        wait::finished(sync_buffer(&d, id, wait::timeout_ns(timeout)))
+                    })?)
+            }
+        };
+        waiter.poll(cx)
+    }
+}
+
 /// A handle to the memory-mapped data of a [`Buffer`].
 ///
 /// A [`Mapping`] can be accessed in 3 ways:
@@ -285,7 +434,8 @@ impl<'a, T> Drop for Mapping<'a, T> {
         unsafe {
             check_log(
                 self.d.libva.vaUnmapBuffer(self.d.raw, self.id),
-                "vaUnmapBuffer call in drop",
+                "vaUnmapBuffer",
+                &[Object::Buffer(self.id)],
             );
         }
     }
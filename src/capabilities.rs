@@ -0,0 +1,653 @@
+//! A snapshot of everything a [`Display`] supports.
+//!
+//! [`Display::query_capabilities`] gathers the results of all capability queries into a single
+//! [`Capabilities`] value. With the `serde` feature enabled, all types in this module implement
+//! `Serialize` and `Deserialize`, so that capability reports can be stored and loaded again (for
+//! example, to test code against the capabilities of a machine that isn't available).
+//!
+//! Enumerations and flags are serialized as the raw integers libva uses for them.
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, ConfigAttribEnum, ConfigAttribType},
+    context::Context,
+    display::{Display, DisplayAttribFlags, DisplayAttribType},
+    error::Repr,
+    image::{ByteOrder, ImageFormat},
+    subpicture::SubpictureFlags,
+    surface::{RTFormat, SurfaceAttrib, SurfaceAttribFlags, SurfaceAttribType},
+    vpp::{ColorStandardType, FilterFlags, FilterType, Filters, PipelineFlags, ProcPipelineCaps},
+    Entrypoint, Error, PixelFormat, Profile, Result,
+};
+
+/// Size of the video processing context used to query the video processing capabilities.
+const VPP_CONTEXT_SIZE: u32 = 512;
+
+impl Display {
+    /// Queries everything this display supports.
+    ///
+    /// This creates a [`Config`] for every supported [`Profile`] and [`Entrypoint`] combination
+    /// to query its attributes, and a video processing [`Context`] if video processing is
+    /// supported. Capabilities that the driver or the libva version in use can't report (as
+    /// indicated by [`Error::is_unsupported`][crate::error::Error::is_unsupported]) are left out
+    /// of the snapshot instead of failing the query.
+    pub fn query_capabilities(&self) -> Result<Capabilities> {
+        let mut profiles = Vec::new();
+        for profile in self.query_profiles()? {
+            let mut entrypoints = Vec::new();
+            for entrypoint in self.query_entrypoints(profile)? {
+                entrypoints.push(EntrypointCaps::query(self, profile, entrypoint)?);
+            }
+            profiles.push(ProfileCaps {
+                profile,
+                entrypoints,
+            });
+        }
+
+        let image_formats = self
+            .query_image_formats()?
+            .into_iter()
+            .map(ImageFormatCaps::from)
+            .collect();
+        let subpicture_formats = optional(self.query_subpicture_format())?
+            .into_iter()
+            .flatten()
+            .map(|format| SubpictureFormatCaps {
+                format: ImageFormatCaps::from(*format.image_format()),
+                flags: format.flags(),
+            })
+            .collect();
+        let display_attributes = optional(self.query_display_attributes())?
+            .into_iter()
+            .flatten()
+            .map(|attrib| DisplayAttribCaps {
+                ty: attrib.ty(),
+                flags: attrib.flags(),
+                min_value: attrib.min_value(),
+                max_value: attrib.max_value(),
+                value: attrib.value(),
+            })
+            .collect();
+
+        let supports_vpp = profiles
+            .iter()
+            .any(|p| p.profile == Profile::None && p.entrypoint(Entrypoint::VideoProc).is_some());
+        let video_processing = if supports_vpp {
+            optional(VideoProcCaps::query(self))?
+        } else {
+            None
+        };
+
+        Ok(Capabilities {
+            vendor: self.query_vendor_string()?.to_string(),
+            version: (self.version_major(), self.version_minor()),
+            profiles,
+            image_formats,
+            subpicture_formats,
+            display_attributes,
+            video_processing,
+        })
+    }
+}
+
+/// Turns errors indicating that something isn't supported into [`None`].
+fn optional<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.is_unsupported() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Everything a [`Display`] supports, as returned by [`Display::query_capabilities`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Capabilities {
+    /// The driver's vendor string.
+    pub vendor: String,
+    /// The major and minor VA-API version.
+    pub version: (u32, u32),
+    pub profiles: Vec<ProfileCaps>,
+    pub image_formats: Vec<ImageFormatCaps>,
+    pub subpicture_formats: Vec<SubpictureFormatCaps>,
+    pub display_attributes: Vec<DisplayAttribCaps>,
+    /// The video processing capabilities, or [`None`] if video processing isn't supported.
+    pub video_processing: Option<VideoProcCaps>,
+}
+
+impl Capabilities {
+    /// Returns the capabilities of `profile`, if it is supported.
+    pub fn profile(&self, profile: Profile) -> Option<&ProfileCaps> {
+        self.profiles.iter().find(|p| p.profile == profile)
+    }
+
+    /// Returns the capabilities of the `profile` and `entrypoint` combination, if it is supported.
+    pub fn entrypoint(&self, profile: Profile, entrypoint: Entrypoint) -> Option<&EntrypointCaps> {
+        self.profile(profile)?.entrypoint(entrypoint)
+    }
+}
+
+/// A supported [`Profile`] and its [`Entrypoint`]s.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProfileCaps {
+    pub profile: Profile,
+    pub entrypoints: Vec<EntrypointCaps>,
+}
+
+impl ProfileCaps {
+    /// Returns the capabilities of `entrypoint`, if it is supported for this profile.
+    pub fn entrypoint(&self, entrypoint: Entrypoint) -> Option<&EntrypointCaps> {
+        self.entrypoints.iter().find(|e| e.entrypoint == entrypoint)
+    }
+}
+
+/// The attributes of a [`Config`] for a supported [`Profile`] and [`Entrypoint`] combination.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EntrypointCaps {
+    pub entrypoint: Entrypoint,
+    /// The raw values of all config attributes the driver supports, as returned by
+    /// [`Display::get_config_attributes`].
+    pub config_attributes: Vec<(ConfigAttribType, u32)>,
+    /// The surface attributes, or [`None`] if they couldn't be queried.
+    pub surface_attributes: Option<Vec<SurfaceAttribCaps>>,
+}
+
+impl EntrypointCaps {
+    fn query(display: &Display, profile: Profile, entrypoint: Entrypoint) -> Result<Self> {
+        let config_attributes = display
+            .get_config_attributes(profile, entrypoint, ConfigAttribType::ALL)?
+            .into_iter()
+            .filter(|attrib| attrib.is_supported())
+            .map(|attrib| (attrib.attrib_type(), attrib.raw_value()))
+            .collect();
+        let surface_attributes = match optional(Config::new(display, profile, entrypoint))? {
+            Some(config) => optional(config.query_surface_attributes())?.map(|attribs| {
+                attribs
+                    .into_iter()
+                    .map(|attrib| SurfaceAttribCaps::from(&attrib))
+                    .collect()
+            }),
+            None => None,
+        };
+        Ok(Self {
+            entrypoint,
+            config_attributes,
+            surface_attributes,
+        })
+    }
+
+    /// Returns the raw value of the config attribute `ty`, if the driver reported it.
+    pub fn config_attribute(&self, ty: ConfigAttribType) -> Option<u32> {
+        self.config_attributes
+            .iter()
+            .find(|(t, _)| *t == ty)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// A surface attribute supported by a [`Config`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SurfaceAttribCaps {
+    pub ty: SurfaceAttribType,
+    pub flags: SurfaceAttribFlags,
+    pub value: AttribValue,
+}
+
+impl From<&SurfaceAttrib> for SurfaceAttribCaps {
+    fn from(attrib: &SurfaceAttrib) -> Self {
+        let raw = attrib.raw_value();
+        let value = match (raw.as_int(), raw.as_float()) {
+            (Some(i), _) => AttribValue::Int(i),
+            (_, Some(f)) => AttribValue::Float(f),
+            _ => AttribValue::Other,
+        };
+        Self {
+            ty: attrib.ty(),
+            flags: attrib.flags(),
+            value,
+        }
+    }
+}
+
+/// The value of a [`SurfaceAttribCaps`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AttribValue {
+    Int(i32),
+    Float(f32),
+    /// A pointer or function value, which can't be meaningfully stored.
+    Other,
+}
+
+/// A supported [`ImageFormat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ImageFormatCaps {
+    pub pixel_format: PixelFormat,
+    pub byte_order: ByteOrder,
+    pub bits_per_pixel: u32,
+    pub depth: u32,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub alpha_mask: u32,
+}
+
+impl From<ImageFormat> for ImageFormatCaps {
+    fn from(format: ImageFormat) -> Self {
+        Self {
+            pixel_format: format.pixel_format(),
+            byte_order: format.byte_order(),
+            bits_per_pixel: format.bits_per_pixel(),
+            depth: format.depth(),
+            red_mask: format.red_mask(),
+            green_mask: format.green_mask(),
+            blue_mask: format.blue_mask(),
+            alpha_mask: format.alpha_mask(),
+        }
+    }
+}
+
+/// A supported subpicture format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SubpictureFormatCaps {
+    pub format: ImageFormatCaps,
+    pub flags: SubpictureFlags,
+}
+
+/// A supported display attribute, and its value at the time of the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DisplayAttribCaps {
+    pub ty: DisplayAttribType,
+    pub flags: DisplayAttribFlags,
+    pub min_value: i32,
+    pub max_value: i32,
+    pub value: i32,
+}
+
+/// Video processing capabilities.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VideoProcCaps {
+    pub filters: Vec<FilterType>,
+    /// Capabilities of a pipeline without any filters, or [`None`] if they couldn't be queried.
+    pub pipeline: Option<PipelineCaps>,
+}
+
+impl VideoProcCaps {
+    fn query(display: &Display) -> Result<Self> {
+        let config = Config::new(display, Profile::None, Entrypoint::VideoProc)?;
+        let context = Context::new(&config, VPP_CONTEXT_SIZE, VPP_CONTEXT_SIZE)?;
+        let filters = context
+            .query_video_processing_filters()?
+            .into_iter()
+            .collect();
+        let pipeline = optional(context.query_video_processing_pipeline_caps(&mut Filters::new()))?
+            .map(|caps| PipelineCaps::from(&caps));
+        Ok(Self { filters, pipeline })
+    }
+}
+
+/// The capabilities of a video processing pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PipelineCaps {
+    pub pipeline_flags: PipelineFlags,
+    pub filter_flags: FilterFlags,
+    pub num_forward_references: u32,
+    pub num_backward_references: u32,
+    pub input_color_standards: Vec<ColorStandardType>,
+    pub output_color_standards: Vec<ColorStandardType>,
+    pub input_pixel_formats: Vec<PixelFormat>,
+    pub output_pixel_formats: Vec<PixelFormat>,
+}
+
+impl From<&ProcPipelineCaps> for PipelineCaps {
+    fn from(caps: &ProcPipelineCaps) -> Self {
+        Self {
+            pipeline_flags: caps.pipeline_flags(),
+            filter_flags: caps.filter_flags(),
+            num_forward_references: caps.num_forward_references(),
+            num_backward_references: caps.num_backward_references(),
+            input_color_standards: caps.input_color_standards().to_vec(),
+            output_color_standards: caps.output_color_standards().to_vec(),
+            input_pixel_formats: caps.input_pixel_formats().to_vec(),
+            output_pixel_formats: caps.output_pixel_formats().to_vec(),
+        }
+    }
+}
+
+impl Display {
+    /// Finds a [`Config`] that can process pictures in the given format and size.
+    ///
+    /// Only the [`Profile`] and [`Entrypoint`]s named by `request` are queried. If the request
+    /// can't be satisfied, the returned error's [`Error::unsupported_config`] method returns the
+    /// reasons.
+    ///
+    /// [`Error::unsupported_config`]: crate::error::Error::unsupported_config
+    pub fn find_config(&self, request: &ConfigRequest) -> Result<ConfigMatch> {
+        let profile = request.profile;
+        let caps = if self.query_profiles()?.contains(profile) {
+            let mut entrypoints = Vec::new();
+            for entrypoint in self.query_entrypoints(profile)? {
+                if request.entrypoint.is_none_or(|e| e == entrypoint) {
+                    entrypoints.push(EntrypointCaps::query(self, profile, entrypoint)?);
+                }
+            }
+            Some(ProfileCaps {
+                profile,
+                entrypoints,
+            })
+        } else {
+            None
+        };
+        request
+            .find_in(caps.as_ref())
+            .map_err(|e| Error::from(Repr::Unsupported(Box::new(e))))
+    }
+}
+
+impl Capabilities {
+    /// Finds a config that can process pictures in the given format and size.
+    ///
+    /// This performs the same checks as [`Display::find_config`], but on a snapshot.
+    pub fn find_config(&self, request: &ConfigRequest) -> Result<ConfigMatch, Unsupported> {
+        request.find_in(self.profile(request.profile))
+    }
+}
+
+/// Describes the pictures a [`Config`] needs to be able to process.
+///
+/// Pass this to [`Display::find_config`] or [`Capabilities::find_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigRequest {
+    profile: Profile,
+    entrypoint: Option<Entrypoint>,
+    rt_format: RTFormat,
+    width: u32,
+    height: u32,
+}
+
+impl ConfigRequest {
+    /// Requests a config for `profile` that renders `width`×`height` pictures to surfaces with
+    /// the given [`RTFormat`].
+    ///
+    /// By default, the first entrypoint the driver lists that satisfies the request is used.
+    pub fn new(profile: Profile, rt_format: RTFormat, width: u32, height: u32) -> Self {
+        Self {
+            profile,
+            entrypoint: None,
+            rt_format,
+            width,
+            height,
+        }
+    }
+
+    /// Only considers the given [`Entrypoint`].
+    #[inline]
+    pub fn with_entrypoint(mut self, entrypoint: Entrypoint) -> Self {
+        self.entrypoint = Some(entrypoint);
+        self
+    }
+
+    #[inline]
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    #[inline]
+    pub fn entrypoint(&self) -> Option<Entrypoint> {
+        self.entrypoint
+    }
+
+    #[inline]
+    pub fn rt_format(&self) -> RTFormat {
+        self.rt_format
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn find_in(&self, profile: Option<&ProfileCaps>) -> Result<ConfigMatch, Unsupported> {
+        let unsupported = |reasons| Unsupported {
+            request: *self,
+            reasons,
+        };
+        let Some(profile) = profile else {
+            return Err(unsupported(vec![Reason::Profile]));
+        };
+
+        let mut reasons = Vec::new();
+        let candidates = profile
+            .entrypoints
+            .iter()
+            .filter(|caps| self.entrypoint.is_none_or(|e| e == caps.entrypoint));
+        for caps in candidates {
+            match self.check(caps) {
+                Ok(()) => {
+                    return Ok(ConfigMatch {
+                        profile: self.profile,
+                        entrypoint: caps.entrypoint,
+                        rt_format: self.rt_format,
+                    })
+                }
+                Err(reason) => reasons.push(reason),
+            }
+        }
+        if reasons.is_empty() {
+            // Only happens when a specific entrypoint was requested.
+            reasons.extend(self.entrypoint.map(Reason::Entrypoint));
+        }
+        Err(unsupported(reasons))
+    }
+
+    fn check(&self, caps: &EntrypointCaps) -> Result<(), Reason> {
+        let entrypoint = caps.entrypoint;
+
+        let supported = caps
+            .config_attribute(ConfigAttribType::RTFormat)
+            .map_or(RTFormat::empty(), RTFormat::from_bits_truncate);
+        if self.rt_format.is_empty() || !supported.contains(self.rt_format) {
+            return Err(Reason::RTFormat {
+                entrypoint,
+                supported,
+            });
+        }
+
+        // The picture size is limited by both the config attributes and the surface attributes.
+        let surface_attrib = |ty| {
+            caps.surface_attributes
+                .iter()
+                .flatten()
+                .find(|attrib| attrib.ty == ty)
+                .and_then(|attrib| match attrib.value {
+                    AttribValue::Int(value) => u32::try_from(value).ok(),
+                    _ => None,
+                })
+        };
+        let max = |config_ty, surface_ty| {
+            [caps.config_attribute(config_ty), surface_attrib(surface_ty)]
+                .into_iter()
+                .flatten()
+                .min()
+        };
+        let max_width = max(
+            ConfigAttribType::MaxPictureWidth,
+            SurfaceAttribType::MaxWidth,
+        );
+        let max_height = max(
+            ConfigAttribType::MaxPictureHeight,
+            SurfaceAttribType::MaxHeight,
+        );
+        if max_width.is_some_and(|max| self.width > max)
+            || max_height.is_some_and(|max| self.height > max)
+        {
+            return Err(Reason::TooLarge {
+                entrypoint,
+                max_width: max_width.unwrap_or(u32::MAX),
+                max_height: max_height.unwrap_or(u32::MAX),
+            });
+        }
+
+        let min_width = surface_attrib(SurfaceAttribType::MinWidth).unwrap_or(1);
+        let min_height = surface_attrib(SurfaceAttribType::MinHeight).unwrap_or(1);
+        if self.width < min_width || self.height < min_height {
+            return Err(Reason::TooSmall {
+                entrypoint,
+                min_width,
+                min_height,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// A supported config, as found by [`Display::find_config`] or [`Capabilities::find_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigMatch {
+    profile: Profile,
+    entrypoint: Entrypoint,
+    rt_format: RTFormat,
+}
+
+impl ConfigMatch {
+    #[inline]
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    #[inline]
+    pub fn entrypoint(&self) -> Entrypoint {
+        self.entrypoint
+    }
+
+    #[inline]
+    pub fn rt_format(&self) -> RTFormat {
+        self.rt_format
+    }
+
+    /// Creates the [`Config`], requesting the matched [`RTFormat`].
+    pub fn create(&self, display: &Display) -> Result<Config> {
+        Config::with_attribs(
+            display,
+            self.profile,
+            self.entrypoint,
+            &mut [ConfigAttribEnum::RTFormat(self.rt_format).into()],
+        )
+    }
+}
+
+/// Explains why a [`ConfigRequest`] can't be satisfied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    request: ConfigRequest,
+    reasons: Vec<Reason>,
+}
+
+impl Unsupported {
+    /// Returns the request that couldn't be satisfied.
+    #[inline]
+    pub fn request(&self) -> &ConfigRequest {
+        &self.request
+    }
+
+    /// Returns the reasons why the request can't be satisfied, one per considered entrypoint.
+    #[inline]
+    pub fn reasons(&self) -> &[Reason] {
+        &self.reasons
+    }
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.request;
+        write!(
+            f,
+            "{:?} at {}x{} with RT format {:?} is not supported",
+            r.profile, r.width, r.height, r.rt_format
+        )?;
+        for (i, reason) in self.reasons.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { "; " })?;
+            reason.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Why a [`ConfigRequest`] can't be satisfied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Reason {
+    /// The [`Profile`] isn't supported.
+    Profile,
+    /// The requested [`Entrypoint`] isn't supported for the profile.
+    Entrypoint(Entrypoint),
+    /// The entrypoint doesn't support the requested [`RTFormat`].
+    RTFormat {
+        entrypoint: Entrypoint,
+        supported: RTFormat,
+    },
+    /// The requested picture size exceeds the maximum supported by the entrypoint.
+    TooLarge {
+        entrypoint: Entrypoint,
+        max_width: u32,
+        max_height: u32,
+    },
+    /// The requested picture size is below the minimum supported by the entrypoint.
+    TooSmall {
+        entrypoint: Entrypoint,
+        min_width: u32,
+        min_height: u32,
+    },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Profile => f.write_str("profile is not supported"),
+            Reason::Entrypoint(entrypoint) => write!(f, "{entrypoint:?} is not supported"),
+            Reason::RTFormat {
+                entrypoint,
+                supported,
+            } => write!(f, "{entrypoint:?} only supports RT formats {supported:?}"),
+            Reason::TooLarge {
+                entrypoint,
+                max_width,
+                max_height,
+            } => write!(
+                f,
+                "{entrypoint:?} supports at most {max_width}x{max_height}"
+            ),
+            Reason::TooSmall {
+                entrypoint,
+                min_width,
+                min_height,
+            } => write!(
+                f,
+                "{entrypoint:?} requires at least {min_width}x{min_height}"
+            ),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{
+    backend::FakeBackend,
+    config::ConfigAttribType,
+    display::{Display, DisplayAttribType, LibvaFeatures},
+    error::{ErrorKind, VAError},
+    surface::{RTFormat, SurfaceAttribType},
+    Entrypoint, PixelFormat, Profile,
+};
+
+use super::{AttribValue, ConfigRequest, Reason};
+
+#[test]
+fn query_capabilities() {
+    let display = Display::with_backend(FakeBackend::new()).unwrap();
+    let caps = display.query_capabilities().unwrap();
+
+    assert_eq!(caps.vendor, "v-ayylmao fake driver");
+    assert_eq!(caps.version, (1, 20));
+    let jpeg = caps
+        .entrypoint(Profile::JPEGBaseline, Entrypoint::VLD)
+        .unwrap();
+    let rt_format = jpeg.config_attribute(ConfigAttribType::RTFormat).unwrap();
+    assert!(RTFormat::from_bits_truncate(rt_format).contains(RTFormat::YUV420));
+    let pixel_format = jpeg
+        .surface_attributes
+        .as_ref()
+        .unwrap()
+        .iter()
+        .find(|attrib| attrib.ty == SurfaceAttribType::PixelFormat)
+        .unwrap();
+    assert_eq!(
+        pixel_format.value,
+        AttribValue::Int(PixelFormat::NV12.to_u32_le() as i32)
+    );
+    assert!(caps
+        .entrypoint(Profile::HEVCMain, Entrypoint::VLD)
+        .is_none());
+
+    assert!(caps
+        .image_formats
+        .iter()
+        .any(|format| format.pixel_format == PixelFormat::NV12));
+    assert!(caps
+        .display_attributes
+        .iter()
+        .any(|attrib| attrib.ty == DisplayAttribType::Brightness));
+
+    // The fake supports video processing without any filters, and doesn't implement
+    // `vaQueryVideoProcPipelineCaps`, which is left out.
+    let vpp = caps.video_processing.unwrap();
+    assert_eq!(vpp.filters, []);
+    assert_eq!(vpp.pipeline, None);
+}
+
+#[test]
+fn query_capabilities_errors() {
+    let fake = FakeBackend::new();
+    fake.set_features(LibvaFeatures::empty());
+    let display = Display::with_backend(fake.clone()).unwrap();
+
+    // Errors that don't indicate missing support are propagated.
+    fake.fail_next("vaQueryImageFormats", VAError::ERROR_OPERATION_FAILED);
+    let err = display.query_capabilities().unwrap_err();
+    assert_eq!(err.function(), Some("vaQueryImageFormats"));
+
+    fake.fail_next("vaQuerySurfaceAttributes", VAError::ERROR_UNIMPLEMENTED);
+    let caps = display.query_capabilities().unwrap();
+    let unsupported = caps
+        .profiles
+        .iter()
+        .flat_map(|p| &p.entrypoints)
+        .filter(|e| e.surface_attributes.is_none())
+        .count();
+    assert_eq!(unsupported, 1);
+}
+
+#[test]
+fn find_config() {
+    let fake = FakeBackend::new();
+    fake.set_max_picture_size(Profile::JPEGBaseline, Entrypoint::VLD, 8192, 4096);
+    let display = Display::with_backend(fake).unwrap();
+
+    let request = ConfigRequest::new(Profile::JPEGBaseline, RTFormat::YUV422, 1920, 1080);
+    let found = display.find_config(&request).unwrap();
+    assert_eq!(found.entrypoint(), Entrypoint::VLD);
+    assert_eq!(found.rt_format(), RTFormat::YUV422);
+    let config = found.create(&display).unwrap();
+    let attribs = config.query_config_attributes().unwrap();
+    assert_eq!(attribs.entrypoint(), Entrypoint::VLD);
+
+    // The max. picture size config attributes are stricter than the surface size limits.
+    let err = display
+        .find_config(&ConfigRequest::new(
+            Profile::JPEGBaseline,
+            RTFormat::YUV420,
+            8192,
+            8192,
+        ))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnsupportedConfig);
+    assert!(err.is_unsupported());
+    assert_eq!(
+        err.unsupported_config().unwrap().reasons(),
+        [Reason::TooLarge {
+            entrypoint: Entrypoint::VLD,
+            max_width: 8192,
+            max_height: 4096,
+        }]
+    );
+    assert_eq!(
+        err.to_string(),
+        "JPEGBaseline at 8192x8192 with RT format YUV420 is not supported: \
+         VLD supports at most 8192x4096"
+    );
+
+    let err = display
+        .find_config(&ConfigRequest::new(
+            Profile::JPEGBaseline,
+            RTFormat::RGB32,
+            64,
+            64,
+        ))
+        .unwrap_err();
+    assert!(matches!(
+        err.unsupported_config().unwrap().reasons(),
+        [Reason::RTFormat { supported, .. }] if supported.contains(RTFormat::YUV444)
+    ));
+
+    let request = ConfigRequest::new(Profile::HEVCMain, RTFormat::YUV420, 64, 64);
+    let err = display.find_config(&request).unwrap_err();
+    assert_eq!(
+        err.unsupported_config().unwrap().reasons(),
+        [Reason::Profile]
+    );
+
+    let request = ConfigRequest::new(Profile::JPEGBaseline, RTFormat::YUV420, 64, 64)
+        .with_entrypoint(Entrypoint::EncPicture);
+    let err = display.find_config(&request).unwrap_err();
+    assert_eq!(
+        err.unsupported_config().unwrap().reasons(),
+        [Reason::Entrypoint(Entrypoint::EncPicture)]
+    );
+
+    // Snapshots give the same answers.
+    let caps = display.query_capabilities().unwrap();
+    assert_eq!(
+        caps.find_config(&request).unwrap_err(),
+        *display
+            .find_config(&request)
+            .unwrap_err()
+            .unsupported_config()
+            .unwrap()
+    );
+    let request = ConfigRequest::new(Profile::JPEGBaseline, RTFormat::YUV400, 0, 64);
+    assert_eq!(
+        caps.find_config(&request).unwrap_err().reasons(),
+        [Reason::TooSmall {
+            entrypoint: Entrypoint::VLD,
+            min_width: 1,
+            min_height: 1,
+        }]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let display = Display::with_backend(FakeBackend::new()).unwrap();
+    let caps = display.query_capabilities().unwrap();
+
+    let json = serde_json::to_string(&caps).unwrap();
+    let loaded: super::Capabilities = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, caps);
+
+    // Enums are stored as their raw value.
+    let value = serde_json::to_value(&caps).unwrap();
+    assert_eq!(
+        value["profiles"][0]["profile"],
+        serde_json::json!(caps.profiles[0].profile.0)
+    );
+}
@@ -1,13 +1,28 @@
 //! Configuration objects.
 
-use std::{ffi::c_int, mem, ptr, sync::Arc, vec};
+mod attrib;
+#[cfg(test)]
+mod tests;
+
+use std::{ffi::c_int, fmt, mem, ptr, sync::Arc, vec};
 
 use crate::{
     check, check_log,
-    display::{Display, DisplayOwner},
-    raw::VAConfigID,
+    display::{Display, DisplayOwner, LibvaFeatures},
+    error::{Object, Repr},
+    raw::{VAConfigID, VAProcessingRateParameter},
     surface::{RTFormat, SurfaceAttributes},
-    Entrypoint, Profile, Result, VAError, VAStatus,
+    Entrypoint, Error, Profile, Result, VAError, VAStatus,
+};
+
+use self::attrib::Bits;
+pub use self::attrib::{
+    CipherAlgorithm, CipherBlockSize, CipherMode, CipherSampleType, ContextPriority,
+    DecAv1Features, DecJpeg, DecSliceMode, EncAv1, EncAv1Ext1, EncAv1Ext2, EncHevcBlockSizes,
+    EncHevcFeatures, EncInterlaced, EncIntraRefresh, EncJpeg, EncMaxRefFrames, EncPackedHeaders,
+    EncPerBlockControl, EncQuantization, EncRateControlExt, EncRoi, EncRoundingControl,
+    EncSliceStructure, FeatureSupport, FeiFunction, MaxFrameSize, MultipleFrame,
+    PredictionDirection, ProcessingRate, RateControl, Stats,
 };
 
 ffi_enum! {
@@ -71,15 +86,37 @@ ffi_enum! {
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct ConfigAttrib {
-    type_: ConfigAttribType,
-    value: u32,
+    pub(crate) type_: ConfigAttribType,
+    pub(crate) value: u32,
 }
 
+impl fmt::Debug for ConfigAttrib {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_enum() {
+            Some(ConfigAttribEnum::Other(ty, value)) => write!(f, "{ty:?}({value:#010x})"),
+            Some(value) => value.fmt(f),
+            None => write!(f, "{:?}(not supported)", self.type_),
+        }
+    }
+}
+
+/// Value of a config attribute that the driver doesn't support (`VA_ATTRIB_NOT_SUPPORTED`).
+pub(crate) const ATTRIB_NOT_SUPPORTED: u32 = 0x80000000;
+
 impl ConfigAttrib {
-    fn zeroed() -> Self {
+    pub(crate) fn zeroed() -> Self {
         unsafe { mem::zeroed() }
     }
 
+    /// Returns whether the driver supports this attribute.
+    ///
+    /// Attributes returned by [`Display::get_config_attributes`] that the driver doesn't support
+    /// have a special value that indicates this.
+    #[inline]
+    pub fn is_supported(&self) -> bool {
+        self.value != ATTRIB_NOT_SUPPORTED
+    }
+
     #[inline]
     pub fn attrib_type(&self) -> ConfigAttribType {
         self.type_
@@ -89,28 +126,446 @@ impl ConfigAttrib {
     pub fn raw_value(&self) -> u32 {
         self.value
     }
+
+    /// Decodes the value of this attribute according to its [`ConfigAttribType`].
+    ///
+    /// Returns [`None`] if the driver doesn't support the attribute.
+    pub fn as_enum(&self) -> Option<ConfigAttribEnum> {
+        self.is_supported()
+            .then(|| ConfigAttribEnum::from_raw(self.type_, self.value))
+    }
 }
 
-#[non_exhaustive]
-pub enum ConfigAttribEnum {
+macro_rules! config_attrib_enum {
+    ( $( $variant:ident($ty:ty), )+ ) => {
+        /// A config attribute with a typed value.
+        ///
+        /// Each variant corresponds to the [`ConfigAttribType`] of the same name.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[non_exhaustive]
+        pub enum ConfigAttribEnum {
+            $( $variant($ty), )+
+            /// An attribute whose value has no defined layout, or whose type is unknown.
+            Other(ConfigAttribType, u32),
+        }
+
+        impl ConfigAttribEnum {
+            /// Returns the type of this attribute.
+            pub fn attrib_type(&self) -> ConfigAttribType {
+                match self {
+                    $( Self::$variant(_) => ConfigAttribType::$variant, )+
+                    Self::Other(ty, _) => *ty,
+                }
+            }
+
+            fn from_raw(ty: ConfigAttribType, value: u32) -> Self {
+                match ty {
+                    $( ConfigAttribType::$variant => Self::$variant(Bits::from_bits(value)), )+
+                    _ => Self::Other(ty, value),
+                }
+            }
+
+            fn into_raw(self) -> u32 {
+                match self {
+                    $( Self::$variant(value) => value.into_bits(), )+
+                    Self::Other(_, value) => value,
+                }
+            }
+        }
+    };
+}
+
+config_attrib_enum! {
     RTFormat(RTFormat),
+    RateControl(RateControl),
+    DecSliceMode(DecSliceMode),
+    DecJPEG(DecJpeg),
+    DecProcessing(bool),
+    EncPackedHeaders(EncPackedHeaders),
+    EncInterlaced(EncInterlaced),
+    EncMaxRefFrames(EncMaxRefFrames),
+    EncMaxSlices(u32),
+    EncSliceStructure(EncSliceStructure),
+    EncMacroblockInfo(bool),
+    MaxPictureWidth(u32),
+    MaxPictureHeight(u32),
+    EncJPEG(EncJpeg),
+    EncQualityRange(u32),
+    EncQuantization(EncQuantization),
+    EncIntraRefresh(EncIntraRefresh),
+    EncSkipFrame(bool),
+    EncROI(EncRoi),
+    EncRateControlExt(EncRateControlExt),
+    ProcessingRate(ProcessingRate),
+    EncDirtyRect(u32),
+    EncParallelRateControl(u32),
+    EncDynamicScaling(bool),
+    FrameSizeToleranceSupport(bool),
+    FEIFunctionType(FeiFunction),
+    FEIMVPredictors(u32),
+    Stats(Stats),
+    EncTileSupport(bool),
+    CustomRoundingControl(EncRoundingControl),
+    QPBlockSize(u32),
+    MaxFrameSize(MaxFrameSize),
+    PredictionDirection(PredictionDirection),
+    MultipleFrame(MultipleFrame),
+    ContextPriority(ContextPriority),
+    DecAV1Features(DecAv1Features),
+    TEEType(u32),
+    TEETypeClient(u32),
+    ProtectedContentCipherAlgorithm(CipherAlgorithm),
+    ProtectedContentCipherBlockSize(CipherBlockSize),
+    ProtectedContentCipherMode(CipherMode),
+    ProtectedContentCipherSampleType(CipherSampleType),
+    ProtectedContentUsage(u32),
+    EncHEVCFeatures(EncHevcFeatures),
+    EncHEVCBlockSizes(EncHevcBlockSizes),
+    EncAV1(EncAv1),
+    EncAV1Ext1(EncAv1Ext1),
+    EncAV1Ext2(EncAv1Ext2),
+    EncPerBlockControl(EncPerBlockControl),
 }
 
 impl From<ConfigAttribEnum> for ConfigAttrib {
     fn from(value: ConfigAttribEnum) -> Self {
-        match value {
-            ConfigAttribEnum::RTFormat(fmt) => ConfigAttrib {
-                type_: ConfigAttribType::RTFormat,
-                value: fmt.bits(),
-            },
+        ConfigAttrib {
+            type_: value.attrib_type(),
+            value: value.into_raw(),
+        }
+    }
+}
+
+impl Display {
+    /// Queries the values the driver supports for the config attributes `types`, for the given
+    /// [`Profile`] and [`Entrypoint`] combination.
+    ///
+    /// Attributes the driver doesn't support are included in the result, but
+    /// [`ConfigAttrib::is_supported`] returns `false` for them.
+    pub fn get_config_attributes(
+        &self,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        types: &[ConfigAttribType],
+    ) -> Result<Vec<ConfigAttrib>> {
+        get_config_attributes(&self.d, profile, entrypoint, types)
+    }
+
+    /// Queries the values the driver supports for all config attributes, for the given
+    /// [`Profile`] and [`Entrypoint`] combination.
+    pub fn query_supported_config_attributes(
+        &self,
+        profile: Profile,
+        entrypoint: Entrypoint,
+    ) -> Result<SupportedConfigAttributes> {
+        SupportedConfigAttributes::query(&self.d, profile, entrypoint, ConfigAttribType::ALL)
+    }
+}
+
+fn get_config_attributes(
+    d: &DisplayOwner,
+    profile: Profile,
+    entrypoint: Entrypoint,
+    types: &[ConfigAttribType],
+) -> Result<Vec<ConfigAttrib>> {
+    let mut attribs = types
+        .iter()
+        .map(|&type_| ConfigAttrib { type_, value: 0 })
+        .collect::<Vec<_>>();
+    unsafe {
+        check(
+            d.libva.vaGetConfigAttributes(
+                d.raw,
+                profile,
+                entrypoint,
+                attribs.as_mut_ptr(),
+                attribs.len().try_into().map_err(Error::from)?,
+            ),
+            "vaGetConfigAttributes",
+            &[],
+        )?;
+    }
+    Ok(attribs)
+}
+
+/// The config attribute values a driver supports for a [`Profile`] and [`Entrypoint`]
+/// combination.
+///
+/// Returned by [`Display::query_supported_config_attributes`].
+#[derive(Debug, Clone)]
+pub struct SupportedConfigAttributes {
+    profile: Profile,
+    entrypoint: Entrypoint,
+    attribs: Vec<ConfigAttrib>,
+}
+
+impl SupportedConfigAttributes {
+    pub(crate) fn query(
+        d: &DisplayOwner,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        types: &[ConfigAttribType],
+    ) -> Result<Self> {
+        Ok(Self {
+            profile,
+            entrypoint,
+            attribs: get_config_attributes(d, profile, entrypoint, types)?,
+        })
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    pub fn entrypoint(&self) -> Entrypoint {
+        self.entrypoint
+    }
+
+    /// Returns the supported values of the attribute `ty`, or [`None`] if the driver doesn't
+    /// support the attribute.
+    pub fn get(&self, ty: ConfigAttribType) -> Option<ConfigAttrib> {
+        self.attribs
+            .iter()
+            .find(|attrib| attrib.type_ == ty && attrib.is_supported())
+            .copied()
+    }
+
+    /// Returns whether the driver supports the value of `attrib`.
+    ///
+    /// For attributes that are a set of flags (like [`ConfigAttribType::RTFormat`]), all flags
+    /// in `attrib` have to be supported. For attributes that are a limit (like
+    /// [`ConfigAttribType::MaxPictureWidth`]), `attrib` must not exceed it. Attributes made of
+    /// several fields (like [`EncJpeg`]) are checked field by field, in the same way. Coding
+    /// tools ([`FeatureSupport`]) that are requested have to be supported, and ones that are not
+    /// requested must not be required.
+    ///
+    /// The values of the remaining attributes (like [`ConfigAttribType::QPBlockSize`]) aren't
+    /// checked, so they only have to be supported at all.
+    pub fn supports(&self, attrib: &ConfigAttrib) -> bool {
+        use ConfigAttribType as T;
+
+        let Some(supported) = self.get(attrib.type_) else {
+            return false;
+        };
+        let (requested, supported) = (attrib.value, supported.value);
+        match attrib.type_ {
+            T::RTFormat
+            | T::RateControl
+            | T::DecSliceMode
+            | T::DecJPEG
+            | T::DecProcessing
+            | T::EncPackedHeaders
+            | T::EncInterlaced
+            | T::EncSliceStructure
+            | T::EncMacroblockInfo
+            | T::EncQuantization
+            | T::EncIntraRefresh
+            | T::EncSkipFrame
+            | T::ProcessingRate
+            | T::EncDynamicScaling
+            | T::FrameSizeToleranceSupport
+            | T::FEIFunctionType
+            | T::EncTileSupport
+            | T::MaxFrameSize
+            | T::PredictionDirection
+            | T::ProtectedContentCipherAlgorithm
+            | T::ProtectedContentCipherBlockSize
+            | T::ProtectedContentCipherMode
+            | T::ProtectedContentCipherSampleType
+            | T::EncPerBlockControl => requested & !supported == 0,
+            T::EncMaxSlices
+            | T::MaxPictureWidth
+            | T::MaxPictureHeight
+            | T::EncQualityRange
+            | T::EncDirtyRect
+            | T::EncParallelRateControl
+            | T::FEIMVPredictors
+            | T::ContextPriority => requested <= supported,
+            T::EncMaxRefFrames => {
+                let (requested, supported) = (
+                    EncMaxRefFrames::from_raw(requested),
+                    EncMaxRefFrames::from_raw(supported),
+                );
+                requested.list0() <= supported.list0() && requested.list1() <= supported.list1()
+            }
+            T::MultipleFrame => {
+                let (requested, supported) = (
+                    MultipleFrame::from_raw(requested),
+                    MultipleFrame::from_raw(supported),
+                );
+                requested.max_num_concurrent_frames() <= supported.max_num_concurrent_frames()
+                    && (!requested.mixed_quality_level() || supported.mixed_quality_level())
+            }
+            T::EncJPEG => EncJpeg::from_raw(supported).allows(EncJpeg::from_raw(requested)),
+            T::EncROI => EncRoi::from_raw(supported).allows(EncRoi::from_raw(requested)),
+            T::EncRateControlExt => EncRateControlExt::from_raw(supported)
+                .allows(EncRateControlExt::from_raw(requested)),
+            T::Stats => Stats::from_raw(supported).allows(Stats::from_raw(requested)),
+            T::CustomRoundingControl => EncRoundingControl::from_raw(supported)
+                .allows(EncRoundingControl::from_raw(requested)),
+            T::DecAV1Features => {
+                DecAv1Features::from_raw(supported).allows(DecAv1Features::from_raw(requested))
+            }
+            T::EncHEVCFeatures => {
+                EncHevcFeatures::from_raw(supported).allows(EncHevcFeatures::from_raw(requested))
+            }
+            T::EncHEVCBlockSizes => EncHevcBlockSizes::from_raw(supported)
+                .allows(EncHevcBlockSizes::from_raw(requested)),
+            T::EncAV1 => EncAv1::from_raw(supported).allows(EncAv1::from_raw(requested)),
+            T::EncAV1Ext1 => {
+                EncAv1Ext1::from_raw(supported).allows(EncAv1Ext1::from_raw(requested))
+            }
+            T::EncAV1Ext2 => {
+                EncAv1Ext2::from_raw(supported).allows(EncAv1Ext2::from_raw(requested))
+            }
+            _ => true,
+        }
+    }
+
+    /// Checks that the driver supports all of `attribs`.
+    ///
+    /// The returned error identifies the first unsupported attribute (see
+    /// [`Error::unsupported_attrib`][crate::error::Error::unsupported_attrib]).
+    pub fn check(&self, attribs: &[ConfigAttrib]) -> Result<()> {
+        match attribs.iter().find(|attrib| !self.supports(attrib)) {
+            Some(attrib) => Err(self.unsupported(*attrib)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the first value in `candidates` that the driver supports.
+    ///
+    /// `candidates` should be ordered from most to least preferred.
+    pub fn negotiate(&self, candidates: &[ConfigAttribEnum]) -> Option<ConfigAttribEnum> {
+        candidates
+            .iter()
+            .copied()
+            .find(|&candidate| self.supports(&candidate.into()))
+    }
+
+    /// Returns the error for an unsupported `requested` attribute.
+    pub(crate) fn unsupported(&self, requested: ConfigAttrib) -> Error {
+        Error::from(Repr::UnsupportedAttrib(Box::new(UnsupportedAttrib {
+            profile: self.profile,
+            entrypoint: self.entrypoint,
+            requested,
+            supported: self.get(requested.type_),
+        })))
+    }
+}
+
+/// A config attribute value that the driver doesn't support.
+///
+/// See [`Error::unsupported_attrib`][crate::error::Error::unsupported_attrib].
+#[derive(Debug, Clone)]
+pub struct UnsupportedAttrib {
+    profile: Profile,
+    entrypoint: Entrypoint,
+    requested: ConfigAttrib,
+    supported: Option<ConfigAttrib>,
+}
+
+impl UnsupportedAttrib {
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    pub fn entrypoint(&self) -> Entrypoint {
+        self.entrypoint
+    }
+
+    /// Returns the attribute that was requested.
+    pub fn requested(&self) -> ConfigAttrib {
+        self.requested
+    }
+
+    /// Returns the values of the attribute the driver supports, or [`None`] if it doesn't support
+    /// the attribute at all.
+    pub fn supported(&self) -> Option<ConfigAttrib> {
+        self.supported
+    }
+}
+
+impl fmt::Display for UnsupportedAttrib {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} with entrypoint {:?} does not support ",
+            self.profile, self.entrypoint
+        )?;
+        match self.supported {
+            Some(supported) => write!(f, "{:?} (supported: {:?})", self.requested, supported),
+            None => write!(f, "the config attribute {:?}", self.requested.type_),
         }
     }
 }
 
+impl std::error::Error for UnsupportedAttrib {}
+
+/// Stream parameters for [`Config::query_encode_processing_rate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeRateParams {
+    level_idc: u8,
+    quality_level: u32,
+    intra_period: u32,
+    ip_period: u32,
+}
+
+impl EncodeRateParams {
+    /// Creates parameters for encoding a stream of the given level (`level_idc`, as coded in the
+    /// bitstream).
+    ///
+    /// All other parameters default to 0, which lets the driver pick its defaults.
+    pub fn new(level_idc: u8) -> Self {
+        Self {
+            level_idc,
+            quality_level: 0,
+            intra_period: 0,
+            ip_period: 0,
+        }
+    }
+
+    /// Sets the quality level, in the range given by [`ConfigAttribType::EncQualityRange`].
+    pub fn with_quality_level(mut self, quality_level: u32) -> Self {
+        self.quality_level = quality_level;
+        self
+    }
+
+    /// Sets the distance between two I-frames.
+    pub fn with_intra_period(mut self, intra_period: u32) -> Self {
+        self.intra_period = intra_period;
+        self
+    }
+
+    /// Sets the distance between two I- or P-frames (1 if there are no B-frames).
+    pub fn with_ip_period(mut self, ip_period: u32) -> Self {
+        self.ip_period = ip_period;
+        self
+    }
+
+    pub fn level_idc(&self) -> u8 {
+        self.level_idc
+    }
+
+    pub fn quality_level(&self) -> u32 {
+        self.quality_level
+    }
+
+    pub fn intra_period(&self) -> u32 {
+        self.intra_period
+    }
+
+    pub fn ip_period(&self) -> u32 {
+        self.ip_period
+    }
+}
+
 /// A codec configuration for a specific [`Entrypoint`] and [`Profile`].
 pub struct Config {
     pub(crate) d: Arc<DisplayOwner>,
     pub(crate) id: VAConfigID,
+    profile: Profile,
+    entrypoint: Entrypoint,
 }
 
 impl Config {
@@ -118,29 +573,149 @@ impl Config {
         Self::with_attribs(display, profile, entrypoint, &mut [])
     }
 
+    /// Creates a [`Config`] with the given attributes.
+    ///
+    /// The attributes are first checked against the values the driver supports (see
+    /// [`SupportedConfigAttributes::check`]), so that an unsupported attribute results in an error
+    /// that identifies it.
     pub fn with_attribs(
         display: &Display,
         profile: Profile,
         entrypoint: Entrypoint,
         attribs: &mut [ConfigAttrib],
+    ) -> Result<Self> {
+        if !attribs.is_empty() {
+            let types = attribs.iter().map(|a| a.type_).collect::<Vec<_>>();
+            SupportedConfigAttributes::query(&display.d, profile, entrypoint, &types)?
+                .check(attribs)?;
+        }
+        Self::create(display, profile, entrypoint, attribs)
+    }
+
+    /// Creates a [`Config`], choosing the value of each attribute from a list of candidates.
+    ///
+    /// Each entry of `preferences` lists the candidate values of one attribute, from most to least
+    /// preferred. The first value the driver supports is used (see
+    /// [`SupportedConfigAttributes::negotiate`]). If the driver supports none of them, an error
+    /// for the most preferred value is returned.
+    ///
+    /// The chosen values can be retrieved with [`Config::query_config_attributes`].
+    pub fn negotiate(
+        display: &Display,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        preferences: &[&[ConfigAttribEnum]],
+    ) -> Result<Self> {
+        let types = preferences
+            .iter()
+            .filter_map(|candidates| Some(candidates.first()?.attrib_type()))
+            .collect::<Vec<_>>();
+        let supported = SupportedConfigAttributes::query(&display.d, profile, entrypoint, &types)?;
+        let mut attribs = Vec::with_capacity(preferences.len());
+        for candidates in preferences {
+            let Some(&preferred) = candidates.first() else {
+                continue;
+            };
+            match supported.negotiate(candidates) {
+                Some(value) => attribs.push(value.into()),
+                None => return Err(supported.unsupported(preferred.into())),
+            }
+        }
+        Self::create(display, profile, entrypoint, &mut attribs)
+    }
+
+    fn create(
+        display: &Display,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        attribs: &mut [ConfigAttrib],
     ) -> Result<Self> {
         unsafe {
             let mut config_id = 0;
-            check(display.d.libva.vaCreateConfig(
-                display.d.raw,
-                profile,
-                entrypoint,
-                attribs.as_mut_ptr(),
-                attribs.len().try_into().unwrap(),
-                &mut config_id,
-            ))?;
+            check(
+                display.d.libva.vaCreateConfig(
+                    display.d.raw,
+                    profile,
+                    entrypoint,
+                    attribs.as_mut_ptr(),
+                    attribs.len().try_into().unwrap(),
+                    &mut config_id,
+                ),
+                "vaCreateConfig",
+                &[],
+            )?;
             Ok(Config {
                 d: display.d.clone(),
                 id: config_id,
+                profile,
+                entrypoint,
             })
         }
     }
 
+    #[inline]
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    #[inline]
+    pub fn entrypoint(&self) -> Entrypoint {
+        self.entrypoint
+    }
+
+    /// Queries how many frames per second the driver can encode with this config, given the
+    /// stream parameters in `params`.
+    ///
+    /// Returns an error if the driver doesn't support the query for this config (see
+    /// [`ConfigAttribType::ProcessingRate`]).
+    pub fn query_encode_processing_rate(&self, params: &EncodeRateParams) -> Result<u32> {
+        let mut param: VAProcessingRateParameter = unsafe { mem::zeroed() };
+        param.proc_buf_enc.level_idc = params.level_idc;
+        param.proc_buf_enc.quality_level = params.quality_level;
+        param.proc_buf_enc.intra_period = params.intra_period;
+        param.proc_buf_enc.ip_period = params.ip_period;
+        self.query_processing_rate(ProcessingRate::ENCODE, param)
+    }
+
+    /// Queries how many frames per second the driver can decode with this config, for a stream
+    /// of the given level (`level_idc`, as coded in the bitstream).
+    ///
+    /// Returns an error if the driver doesn't support the query for this config (see
+    /// [`ConfigAttribType::ProcessingRate`]).
+    pub fn query_decode_processing_rate(&self, level_idc: u8) -> Result<u32> {
+        let mut param: VAProcessingRateParameter = unsafe { mem::zeroed() };
+        param.proc_buf_dec.level_idc = level_idc;
+        self.query_processing_rate(ProcessingRate::DECODE, param)
+    }
+
+    fn query_processing_rate(
+        &self,
+        operation: ProcessingRate,
+        mut param: VAProcessingRateParameter,
+    ) -> Result<u32> {
+        SupportedConfigAttributes::query(
+            &self.d,
+            self.profile,
+            self.entrypoint,
+            &[ConfigAttribType::ProcessingRate],
+        )?
+        .check(&[ConfigAttribEnum::ProcessingRate(operation).into()])?;
+        self.d
+            .require(LibvaFeatures::PROCESSING_RATE, "vaQueryProcessingRate")?;
+
+        let mut rate = 0;
+        unsafe {
+            check(
+                self.d
+                    .libva
+                    .vaQueryProcessingRate(self.d.raw, self.id, &mut param, &mut rate),
+                "vaQueryProcessingRate",
+                &[Object::Config(self.id)],
+            )?;
+        }
+        Ok(rate)
+    }
+
     pub fn query_surface_attributes(&self) -> Result<SurfaceAttributes> {
         unsafe {
             let mut num_attribs = 0;
@@ -151,16 +726,24 @@ impl Config {
                 &mut num_attribs,
             );
             if status != VAStatus::SUCCESS && status != VAError::ERROR_MAX_NUM_EXCEEDED {
-                return Err(check(status).unwrap_err());
+                return Err(Error::libva(
+                    VAError(status.0),
+                    "vaQuerySurfaceAttributes",
+                    &[Object::Config(self.id)],
+                ));
             }
 
             let mut attribs = Vec::with_capacity(num_attribs as usize);
-            check(self.d.libva.vaQuerySurfaceAttributes(
-                self.d.raw,
-                self.id,
-                attribs.as_mut_ptr(),
-                &mut num_attribs,
-            ))?;
+            check(
+                self.d.libva.vaQuerySurfaceAttributes(
+                    self.d.raw,
+                    self.id,
+                    attribs.as_mut_ptr(),
+                    &mut num_attribs,
+                ),
+                "vaQuerySurfaceAttributes",
+                &[Object::Config(self.id)],
+            )?;
             attribs.set_len(num_attribs as usize);
             Ok(SurfaceAttributes { vec: attribs })
         }
@@ -174,14 +757,18 @@ impl Config {
         let mut attrib_list = vec![ConfigAttrib::zeroed(); num_attribs];
         let mut num_attribs = 0;
         unsafe {
-            check(self.d.libva.vaQueryConfigAttributes(
-                self.d.raw,
-                self.id,
-                &mut profile,
-                &mut entrypoint,
-                attrib_list.as_mut_ptr(),
-                &mut num_attribs,
-            ))?;
+            check(
+                self.d.libva.vaQueryConfigAttributes(
+                    self.d.raw,
+                    self.id,
+                    &mut profile,
+                    &mut entrypoint,
+                    attrib_list.as_mut_ptr(),
+                    &mut num_attribs,
+                ),
+                "vaQueryConfigAttributes",
+                &[Object::Config(self.id)],
+            )?;
         }
         attrib_list.truncate(num_attribs as usize);
         attrib_list.shrink_to_fit();
@@ -199,7 +786,8 @@ impl Drop for Config {
         unsafe {
             check_log(
                 self.d.libva.vaDestroyConfig(self.d.raw, self.id),
-                "vaDestroyConfig call in drop",
+                "vaDestroyConfig",
+                &[Object::Config(self.id)],
             );
         }
     }
@@ -0,0 +1,678 @@
+//! Typed values of [`ConfigAttrib`][super::ConfigAttrib]s.
+//!
+//! Most attributes are either a plain number, a set of flags, or a bitfield whose layout is
+//! defined by one of the `VAConfigAttribVal*` unions in `va.h`. The bitfields are represented by
+//! structs that wrap the raw `u32`, with a getter and a `with_*` setter per field.
+
+use std::fmt;
+
+use crate::{surface::RTFormat, vpp::RotationFlags};
+
+/// Conversion between a typed value and the raw bits of a config attribute (or a field of one).
+pub(crate) trait Bits: Sized {
+    fn from_bits(bits: u32) -> Self;
+    fn into_bits(self) -> u32;
+}
+
+impl Bits for u32 {
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+
+    fn into_bits(self) -> u32 {
+        self
+    }
+}
+
+impl Bits for bool {
+    fn from_bits(bits: u32) -> Self {
+        bits != 0
+    }
+
+    fn into_bits(self) -> u32 {
+        self.into()
+    }
+}
+
+impl Bits for FeatureSupport {
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn into_bits(self) -> u32 {
+        self.0
+    }
+}
+
+macro_rules! impl_bits_for_flags {
+    ($($name:ident),+ $(,)?) => {
+        $(
+            impl Bits for $name {
+                fn from_bits(bits: u32) -> Self {
+                    Self::from_bits_truncate(bits)
+                }
+
+                fn into_bits(self) -> u32 {
+                    self.bits()
+                }
+            }
+        )+
+    };
+}
+
+impl_bits_for_flags!(
+    RTFormat,
+    RotationFlags,
+    RateControl,
+    DecSliceMode,
+    EncPackedHeaders,
+    EncInterlaced,
+    EncSliceStructure,
+    EncQuantization,
+    EncIntraRefresh,
+    ProcessingRate,
+    FeiFunction,
+    PredictionDirection,
+    CipherAlgorithm,
+    CipherBlockSize,
+    CipherMode,
+    CipherSampleType,
+);
+
+const fn mask(lo: u32, hi: u32) -> u32 {
+    (u32::MAX >> (32 - (hi - lo))) << lo
+}
+
+/// Defines a struct wrapping one of the `VAConfigAttribVal*` bitfield unions.
+///
+/// Each field is declared as `getter, setter: Type = [lo..hi]`, where `lo..hi` is the range of
+/// bits the field occupies.
+macro_rules! bitfield {
+    (
+        $( #[$attrs:meta] )*
+        pub struct $name:ident {
+            $(
+                $( #[$field_attrs:meta] )*
+                $field:ident, $with:ident: $ty:ty = [$lo:literal..$hi:literal],
+            )+
+        }
+    ) => {
+        $( #[$attrs] )*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+        pub struct $name {
+            raw: u32,
+        }
+
+        impl $name {
+            /// Creates a value from its raw bit representation.
+            #[inline]
+            pub const fn from_raw(raw: u32) -> Self {
+                Self { raw }
+            }
+
+            /// Returns the raw bit representation of the value.
+            #[inline]
+            pub const fn raw(self) -> u32 {
+                self.raw
+            }
+
+            $(
+                $( #[$field_attrs] )*
+                #[inline]
+                pub fn $field(self) -> $ty {
+                    Bits::from_bits((self.raw & mask($lo, $hi)) >> $lo)
+                }
+
+                #[doc = concat!("Sets the value returned by [`", stringify!($name), "::", stringify!($field), "`].")]
+                ///
+                /// # Panics
+                ///
+                #[doc = concat!("Panics if `value` doesn't fit into ", stringify!($hi - $lo), " bits.")]
+                pub fn $with(mut self, value: $ty) -> Self {
+                    let bits = Bits::into_bits(value) << $lo;
+                    assert!(
+                        bits & !mask($lo, $hi) == 0,
+                        "value of `{}` is out of range",
+                        stringify!($field),
+                    );
+                    self.raw = (self.raw & !mask($lo, $hi)) | bits;
+                    self
+                }
+            )+
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    $( .field(stringify!($field), &self.$field()) )+
+                    .finish()
+            }
+        }
+
+        impl Bits for $name {
+            fn from_bits(bits: u32) -> Self {
+                Self::from_raw(bits)
+            }
+
+            fn into_bits(self) -> u32 {
+                self.raw
+            }
+        }
+
+        impl_serde!($name: u32, |this| this.raw, |raw| Self::from_raw(raw));
+    };
+}
+
+bitflags! {
+    /// Rate control modes supported by an encoder.
+    pub struct RateControl: u32 {
+        const NONE            = 0x00000001;
+        const CBR             = 0x00000002;
+        const VBR             = 0x00000004;
+        const VCM             = 0x00000008;
+        const CQP             = 0x00000010;
+        const VBR_CONSTRAINED = 0x00000020;
+        const ICQ             = 0x00000040;
+        const MB              = 0x00000080;
+        const CFS             = 0x00000100;
+        const PARALLEL        = 0x00000200;
+        const QVBR            = 0x00000400;
+        const AVBR            = 0x00000800;
+        const TCBRC           = 0x00001000;
+    }
+}
+
+bitflags! {
+    /// Slice decoding modes.
+    pub struct DecSliceMode: u32 {
+        /// Slices are submitted with their complete data.
+        const NORMAL = 0x00000001;
+        /// Only the base of each slice is parsed by the driver.
+        const BASE   = 0x00000002;
+    }
+}
+
+bitfield! {
+    /// JPEG decoding features (`VAConfigAttribValDecJPEG`).
+    pub struct DecJpeg {
+        /// The rotations that can be applied while decoding.
+        rotation, with_rotation: RotationFlags = [0..4],
+        /// Whether cropping and partial decoding is supported.
+        crop, with_crop: bool = [4..5],
+    }
+}
+
+bitflags! {
+    /// Packed headers the application may submit to an encoder.
+    pub struct EncPackedHeaders: u32 {
+        const NONE     = 0x00000000;
+        const SEQUENCE = 0x00000001;
+        const PICTURE  = 0x00000002;
+        const SLICE    = 0x00000004;
+        const MISC     = 0x00000008;
+        const RAW_DATA = 0x00000010;
+    }
+}
+
+bitflags! {
+    /// Interlaced encoding modes.
+    pub struct EncInterlaced: u32 {
+        const NONE  = 0x00000000;
+        const FRAME = 0x00000001;
+        const FIELD = 0x00000002;
+        const MBAFF = 0x00000004;
+        const PAFF  = 0x00000008;
+    }
+}
+
+bitfield! {
+    /// Maximum number of reference frames of an encoder.
+    pub struct EncMaxRefFrames {
+        /// Maximum number of reference frames in reference picture list 0.
+        list0, with_list0: u32 = [0..16],
+        /// Maximum number of reference frames in reference picture list 1.
+        list1, with_list1: u32 = [16..32],
+    }
+}
+
+bitflags! {
+    /// Slice structures supported by an encoder.
+    pub struct EncSliceStructure: u32 {
+        const POWER_OF_TWO_ROWS     = 0x00000001;
+        const ARBITRARY_MACROBLOCKS = 0x00000002;
+        const EQUAL_ROWS            = 0x00000004;
+        const MAX_SLICE_SIZE        = 0x00000008;
+        const ARBITRARY_ROWS        = 0x00000010;
+        const EQUAL_MULTI_ROWS      = 0x00000020;
+    }
+}
+
+bitfield! {
+    /// JPEG encoding features (`VAConfigAttribValEncJPEG`).
+    pub struct EncJpeg {
+        arithmetic_coding_mode, with_arithmetic_coding_mode: bool = [0..1],
+        progressive_dct_mode, with_progressive_dct_mode: bool = [1..2],
+        non_interleaved_mode, with_non_interleaved_mode: bool = [2..3],
+        differential_mode, with_differential_mode: bool = [3..4],
+        max_num_components, with_max_num_components: u32 = [4..7],
+        max_num_scans, with_max_num_scans: u32 = [7..11],
+        max_num_huffman_tables, with_max_num_huffman_tables: u32 = [11..14],
+        max_num_quantiser_tables, with_max_num_quantiser_tables: u32 = [14..17],
+    }
+}
+
+bitflags! {
+    /// Quantization features of an encoder.
+    pub struct EncQuantization: u32 {
+        const NONE              = 0x00000000;
+        const TRELLIS_SUPPORTED = 0x00000001;
+    }
+}
+
+bitflags! {
+    /// Intra refresh modes of an encoder.
+    pub struct EncIntraRefresh: u32 {
+        const NONE           = 0x00000000;
+        const ROLLING_COLUMN = 0x00000001;
+        const ROLLING_ROW    = 0x00000002;
+        const ADAPTIVE       = 0x00000010;
+        const CYCLIC         = 0x00000020;
+        const P_FRAME        = 0x00010000;
+        const B_FRAME        = 0x00020000;
+        const MULTI_REF      = 0x00040000;
+    }
+}
+
+bitfield! {
+    /// Region-of-interest encoding features (`VAConfigAttribValEncROI`).
+    pub struct EncRoi {
+        /// Maximum number of regions of interest per frame.
+        num_roi_regions, with_num_roi_regions: u32 = [0..8],
+        /// Whether ROI priorities are supported with rate control other than CQP.
+        roi_rc_priority_support, with_roi_rc_priority_support: bool = [8..9],
+        /// Whether ROI QP deltas are supported with rate control other than CQP.
+        roi_rc_qp_delta_support, with_roi_rc_qp_delta_support: bool = [9..10],
+    }
+}
+
+bitfield! {
+    /// Extended rate control features (`VAConfigAttribValEncRateControlExt`).
+    pub struct EncRateControlExt {
+        max_num_temporal_layers_minus1, with_max_num_temporal_layers_minus1: u32 = [0..8],
+        /// Whether the bitrate of each temporal layer can be controlled separately.
+        temporal_layer_bitrate_control_flag, with_temporal_layer_bitrate_control_flag: bool = [8..9],
+    }
+}
+
+bitflags! {
+    /// Operations whose processing rate can be queried.
+    pub struct ProcessingRate: u32 {
+        const NONE   = 0x00000000;
+        const ENCODE = 0x00000001;
+        const DECODE = 0x00000002;
+    }
+}
+
+bitflags! {
+    /// Flexible Encoding Infrastructure functions.
+    pub struct FeiFunction: u32 {
+        const ENC     = 0x00000001;
+        const PAK     = 0x00000002;
+        const ENC_PAK = 0x00000004;
+    }
+}
+
+bitfield! {
+    /// Statistics features (`VAConfigAttribValStats`).
+    pub struct Stats {
+        max_num_past_references, with_max_num_past_references: u32 = [0..4],
+        max_num_future_references, with_max_num_future_references: u32 = [4..8],
+        num_outputs, with_num_outputs: u32 = [8..11],
+        interlaced, with_interlaced: bool = [11..12],
+    }
+}
+
+bitfield! {
+    /// Ranges of the custom rounding offsets (`VAConfigAttribValEncRoundingControl`).
+    pub struct EncRoundingControl {
+        rounding_offset_intra_range, with_rounding_offset_intra_range: u32 = [0..7],
+        rounding_offset_inter_range, with_rounding_offset_inter_range: u32 = [7..14],
+    }
+}
+
+bitfield! {
+    /// Maximum frame size features (`VAConfigAttribValMaxFrameSize`).
+    pub struct MaxFrameSize {
+        /// Whether a maximum frame size can be set.
+        max_frame_size, with_max_frame_size: bool = [0..1],
+        /// Whether multiple passes with different delta QPs are supported.
+        multiple_pass, with_multiple_pass: bool = [1..2],
+    }
+}
+
+bitflags! {
+    /// Reference directions of an encoder.
+    pub struct PredictionDirection: u32 {
+        const PREVIOUS     = 0x00000001;
+        const FUTURE       = 0x00000002;
+        const BI_NOT_EMPTY = 0x00000004;
+    }
+}
+
+bitfield! {
+    /// Multi-frame processing features (`VAConfigAttribValMultipleFrame`).
+    pub struct MultipleFrame {
+        /// Maximum number of frames that can be processed at once.
+        max_num_concurrent_frames, with_max_num_concurrent_frames: u32 = [0..8],
+        /// Whether frames with different quality levels can be processed together.
+        mixed_quality_level, with_mixed_quality_level: bool = [8..9],
+    }
+}
+
+bitfield! {
+    /// Context priority (`VAConfigAttribValContextPriority`).
+    ///
+    /// When querying, this is the maximum priority supported by the driver.
+    pub struct ContextPriority {
+        priority, with_priority: u32 = [0..16],
+    }
+}
+
+bitfield! {
+    /// AV1 decoding features (`VAConfigAttribValDecAV1Features`).
+    pub struct DecAv1Features {
+        /// Large scale tile decoding support: 0 if unsupported, 1 if supported in the driver
+        /// and 2 if supported by the hardware.
+        lst_support, with_lst_support: u32 = [0..2],
+    }
+}
+
+bitflags! {
+    /// Protected content cipher algorithms.
+    pub struct CipherAlgorithm: u32 {
+        const AES = 0x00000001;
+    }
+}
+
+bitflags! {
+    /// Protected content cipher block sizes.
+    pub struct CipherBlockSize: u32 {
+        const B128 = 0x00000001;
+        const B192 = 0x00000002;
+        const B256 = 0x00000004;
+    }
+}
+
+bitflags! {
+    /// Protected content cipher modes.
+    pub struct CipherMode: u32 {
+        const ECB = 0x00000001;
+        const CBC = 0x00000002;
+        const CTR = 0x00000004;
+    }
+}
+
+bitflags! {
+    /// Protected content sample types.
+    pub struct CipherSampleType: u32 {
+        const FULL_SAMPLE = 0x00000001;
+        const SUBSAMPLE   = 0x00000002;
+    }
+}
+
+ffi_enum! {
+    /// Whether an optional coding tool is supported (`VA_FEATURE_*`).
+    pub enum FeatureSupport: u32 {
+        NotSupported = 0,
+        Supported = 1,
+        /// The tool is always used, and can't be turned off.
+        Required = 2,
+    }
+}
+
+bitfield! {
+    /// HEVC encoding tools (`VAConfigAttribValEncHEVCFeatures`).
+    pub struct EncHevcFeatures {
+        separate_colour_planes, with_separate_colour_planes: FeatureSupport = [0..2],
+        scaling_lists, with_scaling_lists: FeatureSupport = [2..4],
+        amp, with_amp: FeatureSupport = [4..6],
+        sao, with_sao: FeatureSupport = [6..8],
+        pcm, with_pcm: FeatureSupport = [8..10],
+        temporal_mvp, with_temporal_mvp: FeatureSupport = [10..12],
+        strong_intra_smoothing, with_strong_intra_smoothing: FeatureSupport = [12..14],
+        dependent_slices, with_dependent_slices: FeatureSupport = [14..16],
+        sign_data_hiding, with_sign_data_hiding: FeatureSupport = [16..18],
+        constrained_intra_pred, with_constrained_intra_pred: FeatureSupport = [18..20],
+        transform_skip, with_transform_skip: FeatureSupport = [20..22],
+        cu_qp_delta, with_cu_qp_delta: FeatureSupport = [22..24],
+        weighted_prediction, with_weighted_prediction: FeatureSupport = [24..26],
+        transquant_bypass, with_transquant_bypass: FeatureSupport = [26..28],
+        deblocking_filter_disable, with_deblocking_filter_disable: FeatureSupport = [28..30],
+    }
+}
+
+bitfield! {
+    /// HEVC encoding block sizes (`VAConfigAttribValEncHEVCBlockSizes`).
+    pub struct EncHevcBlockSizes {
+        log2_max_coding_tree_block_size_minus3, with_log2_max_coding_tree_block_size_minus3: u32 = [0..2],
+        log2_min_coding_tree_block_size_minus3, with_log2_min_coding_tree_block_size_minus3: u32 = [2..4],
+        log2_min_luma_coding_block_size_minus3, with_log2_min_luma_coding_block_size_minus3: u32 = [4..6],
+        log2_max_luma_transform_block_size_minus2, with_log2_max_luma_transform_block_size_minus2: u32 = [6..8],
+        log2_min_luma_transform_block_size_minus2, with_log2_min_luma_transform_block_size_minus2: u32 = [8..10],
+        max_max_transform_hierarchy_depth_inter, with_max_max_transform_hierarchy_depth_inter: u32 = [10..12],
+        min_max_transform_hierarchy_depth_inter, with_min_max_transform_hierarchy_depth_inter: u32 = [12..14],
+        max_max_transform_hierarchy_depth_intra, with_max_max_transform_hierarchy_depth_intra: u32 = [14..16],
+        min_max_transform_hierarchy_depth_intra, with_min_max_transform_hierarchy_depth_intra: u32 = [16..18],
+        log2_max_pcm_coding_block_size_minus3, with_log2_max_pcm_coding_block_size_minus3: u32 = [18..20],
+        log2_min_pcm_coding_block_size_minus3, with_log2_min_pcm_coding_block_size_minus3: u32 = [20..22],
+    }
+}
+
+bitfield! {
+    /// AV1 encoding tools (`VAConfigAttribValEncAV1`).
+    pub struct EncAv1 {
+        support_128x128_superblock, with_support_128x128_superblock: FeatureSupport = [0..2],
+        support_filter_intra, with_support_filter_intra: FeatureSupport = [2..4],
+        support_intra_edge_filter, with_support_intra_edge_filter: FeatureSupport = [4..6],
+        support_interintra_compound, with_support_interintra_compound: FeatureSupport = [6..8],
+        support_masked_compound, with_support_masked_compound: FeatureSupport = [8..10],
+        support_warped_motion, with_support_warped_motion: FeatureSupport = [10..12],
+        support_palette_mode, with_support_palette_mode: FeatureSupport = [12..14],
+        support_dual_filter, with_support_dual_filter: FeatureSupport = [14..16],
+        support_jnt_comp, with_support_jnt_comp: FeatureSupport = [16..18],
+        support_ref_frame_mvs, with_support_ref_frame_mvs: FeatureSupport = [18..20],
+        support_superres, with_support_superres: FeatureSupport = [20..22],
+        support_restoration, with_support_restoration: FeatureSupport = [22..24],
+        support_allow_intrabc, with_support_allow_intrabc: FeatureSupport = [24..26],
+        support_cdef_channel_strength, with_support_cdef_channel_strength: FeatureSupport = [26..28],
+    }
+}
+
+bitfield! {
+    /// More AV1 encoding features (`VAConfigAttribValEncAV1Ext1`).
+    pub struct EncAv1Ext1 {
+        /// Bitmask of the supported interpolation filters.
+        interpolation_filter, with_interpolation_filter: u32 = [0..5],
+        min_segid_block_size_accepted, with_min_segid_block_size_accepted: u32 = [5..13],
+        /// Bitmask of the supported segmentation features.
+        segment_feature_support, with_segment_feature_support: u32 = [13..21],
+    }
+}
+
+bitfield! {
+    /// More AV1 encoding features (`VAConfigAttribValEncAV1Ext2`).
+    pub struct EncAv1Ext2 {
+        tile_size_bytes_minus1, with_tile_size_bytes_minus1: u32 = [0..2],
+        obu_size_bytes_minus1, with_obu_size_bytes_minus1: u32 = [2..4],
+        /// Bitmask of the supported transform modes.
+        tx_mode_support, with_tx_mode_support: u32 = [4..7],
+        max_tile_num_minus1, with_max_tile_num_minus1: u32 = [7..20],
+    }
+}
+
+bitfield! {
+    /// Per-block encoding controls (`VAConfigAttribValEncPerBlockControl`).
+    pub struct EncPerBlockControl {
+        delta_qp_support, with_delta_qp_support: bool = [0..1],
+    }
+}
+
+// Checks of requested attribute values against the ones reported by the driver, used by
+// `SupportedConfigAttributes::supports`. `self` is the supported value.
+
+/// Whether a requested flag is available.
+fn flag(requested: bool, supported: bool) -> bool {
+    !requested || supported
+}
+
+/// Whether the requested bitmask only contains supported bits.
+fn subset(requested: u32, supported: u32) -> bool {
+    requested & !supported == 0
+}
+
+impl FeatureSupport {
+    fn allows(self, requested: Self) -> bool {
+        match requested {
+            // Required tools can't be turned off.
+            Self::NotSupported => self != Self::Required,
+            _ => self != Self::NotSupported,
+        }
+    }
+}
+
+/// Checks `count` [`FeatureSupport`] fields packed into consecutive bit pairs.
+fn features_allow(requested: u32, supported: u32, count: u32) -> bool {
+    (0..count).all(|i| {
+        let field = |raw: u32| FeatureSupport::from_bits((raw >> (2 * i)) & 0b11);
+        field(supported).allows(field(requested))
+    })
+}
+
+impl EncJpeg {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        flag(
+            requested.arithmetic_coding_mode(),
+            self.arithmetic_coding_mode(),
+        ) && flag(
+            requested.progressive_dct_mode(),
+            self.progressive_dct_mode(),
+        ) && flag(
+            requested.non_interleaved_mode(),
+            self.non_interleaved_mode(),
+        ) && flag(requested.differential_mode(), self.differential_mode())
+            && requested.max_num_components() <= self.max_num_components()
+            && requested.max_num_scans() <= self.max_num_scans()
+            && requested.max_num_huffman_tables() <= self.max_num_huffman_tables()
+            && requested.max_num_quantiser_tables() <= self.max_num_quantiser_tables()
+    }
+}
+
+impl EncRoi {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        requested.num_roi_regions() <= self.num_roi_regions()
+            && flag(
+                requested.roi_rc_priority_support(),
+                self.roi_rc_priority_support(),
+            )
+            && flag(
+                requested.roi_rc_qp_delta_support(),
+                self.roi_rc_qp_delta_support(),
+            )
+    }
+}
+
+impl EncRateControlExt {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        requested.max_num_temporal_layers_minus1() <= self.max_num_temporal_layers_minus1()
+            && flag(
+                requested.temporal_layer_bitrate_control_flag(),
+                self.temporal_layer_bitrate_control_flag(),
+            )
+    }
+}
+
+impl Stats {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        requested.max_num_past_references() <= self.max_num_past_references()
+            && requested.max_num_future_references() <= self.max_num_future_references()
+            && requested.num_outputs() <= self.num_outputs()
+            && flag(requested.interlaced(), self.interlaced())
+    }
+}
+
+impl EncRoundingControl {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        requested.rounding_offset_intra_range() <= self.rounding_offset_intra_range()
+            && requested.rounding_offset_inter_range() <= self.rounding_offset_inter_range()
+    }
+}
+
+impl DecAv1Features {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        // Hardware support (2) also satisfies a request for driver support (1).
+        requested.lst_support() <= self.lst_support()
+    }
+}
+
+impl EncHevcFeatures {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        features_allow(requested.raw(), self.raw(), 15)
+    }
+}
+
+impl EncHevcBlockSizes {
+    /// The requested sizes and depths have to be within the supported range.
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        let (r, s) = (requested, self);
+        r.log2_max_coding_tree_block_size_minus3() <= s.log2_max_coding_tree_block_size_minus3()
+            && r.log2_min_coding_tree_block_size_minus3()
+                >= s.log2_min_coding_tree_block_size_minus3()
+            && r.log2_min_luma_coding_block_size_minus3()
+                >= s.log2_min_luma_coding_block_size_minus3()
+            && r.log2_max_luma_transform_block_size_minus2()
+                <= s.log2_max_luma_transform_block_size_minus2()
+            && r.log2_min_luma_transform_block_size_minus2()
+                >= s.log2_min_luma_transform_block_size_minus2()
+            && r.max_max_transform_hierarchy_depth_inter()
+                <= s.max_max_transform_hierarchy_depth_inter()
+            && r.min_max_transform_hierarchy_depth_inter()
+                >= s.min_max_transform_hierarchy_depth_inter()
+            && r.max_max_transform_hierarchy_depth_intra()
+                <= s.max_max_transform_hierarchy_depth_intra()
+            && r.min_max_transform_hierarchy_depth_intra()
+                >= s.min_max_transform_hierarchy_depth_intra()
+            && r.log2_max_pcm_coding_block_size_minus3()
+                <= s.log2_max_pcm_coding_block_size_minus3()
+            && r.log2_min_pcm_coding_block_size_minus3()
+                >= s.log2_min_pcm_coding_block_size_minus3()
+    }
+}
+
+impl EncAv1 {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        features_allow(requested.raw(), self.raw(), 14)
+    }
+}
+
+impl EncAv1Ext1 {
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        subset(
+            requested.interpolation_filter(),
+            self.interpolation_filter(),
+        ) && requested.min_segid_block_size_accepted() >= self.min_segid_block_size_accepted()
+            && subset(
+                requested.segment_feature_support(),
+                self.segment_feature_support(),
+            )
+    }
+}
+
+impl EncAv1Ext2 {
+    /// The tile and OBU size field widths are chosen by the driver, so they have to match.
+    pub(crate) fn allows(self, requested: Self) -> bool {
+        requested.tile_size_bytes_minus1() == self.tile_size_bytes_minus1()
+            && requested.obu_size_bytes_minus1() == self.obu_size_bytes_minus1()
+            && subset(requested.tx_mode_support(), self.tx_mode_support())
+            && requested.max_tile_num_minus1() <= self.max_tile_num_minus1()
+    }
+}
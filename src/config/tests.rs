@@ -0,0 +1,245 @@
+use crate::{
+    backend::FakeBackend,
+    display::{Display, LibvaFeatures},
+    error::ErrorKind,
+    surface::RTFormat,
+    vpp::RotationFlags,
+    Entrypoint, Profile,
+};
+
+use super::{
+    Config, ConfigAttrib, ConfigAttribEnum, ConfigAttribType, DecJpeg, EncHevcFeatures,
+    EncMaxRefFrames, EncRoi, EncodeRateParams, FeatureSupport, RateControl,
+    SupportedConfigAttributes,
+};
+
+#[test]
+fn typed_attribs() {
+    let fake = FakeBackend::new();
+    fake.set_max_picture_size(Profile::JPEGBaseline, Entrypoint::VLD, 8192, 4096);
+    let display = Display::with_backend(fake).unwrap();
+
+    let attribs = display
+        .get_config_attributes(
+            Profile::JPEGBaseline,
+            Entrypoint::VLD,
+            &[
+                ConfigAttribType::RTFormat,
+                ConfigAttribType::MaxPictureWidth,
+                ConfigAttribType::DecJPEG,
+            ],
+        )
+        .unwrap();
+    let values = attribs.iter().map(|a| a.as_enum()).collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        [
+            Some(ConfigAttribEnum::RTFormat(
+                RTFormat::YUV420 | RTFormat::YUV422 | RTFormat::YUV444 | RTFormat::YUV400
+            )),
+            Some(ConfigAttribEnum::MaxPictureWidth(8192)),
+            None,
+        ]
+    );
+
+    // Attributes without a defined value layout are passed through.
+    let attrib = ConfigAttrib {
+        type_: ConfigAttribType::Encryption,
+        value: 5,
+    };
+    assert_eq!(
+        attrib.as_enum(),
+        Some(ConfigAttribEnum::Other(ConfigAttribType::Encryption, 5))
+    );
+
+    let attrib = ConfigAttrib::from(ConfigAttribEnum::RateControl(
+        RateControl::CBR | RateControl::VBR,
+    ));
+    assert_eq!(attrib.attrib_type(), ConfigAttribType::RateControl);
+    assert_eq!(attrib.raw_value(), 0x6);
+}
+
+#[test]
+fn bitfields() {
+    let refs = EncMaxRefFrames::from_raw(0x0001_0003);
+    assert_eq!(refs.list0(), 3);
+    assert_eq!(refs.list1(), 1);
+    assert_eq!(refs.with_list1(2).raw(), 0x0002_0003);
+    assert_eq!(
+        format!("{refs:?}"),
+        "EncMaxRefFrames { list0: 3, list1: 1 }"
+    );
+
+    let jpeg = DecJpeg::default()
+        .with_rotation(RotationFlags::NONE | RotationFlags::R180)
+        .with_crop(true);
+    assert_eq!(jpeg.raw(), 0b1_0101);
+    assert_eq!(jpeg.rotation(), RotationFlags::NONE | RotationFlags::R180);
+    assert!(jpeg.crop());
+
+    let hevc = EncHevcFeatures::from_raw(0b10_01 << 4);
+    assert_eq!(hevc.amp(), FeatureSupport::Supported);
+    assert_eq!(hevc.sao(), FeatureSupport::Required);
+    assert_eq!(hevc.pcm(), FeatureSupport::NotSupported);
+
+    let attrib = ConfigAttrib::from(ConfigAttribEnum::EncHEVCFeatures(hevc));
+    assert_eq!(
+        attrib.as_enum(),
+        Some(ConfigAttribEnum::EncHEVCFeatures(hevc))
+    );
+}
+
+#[test]
+#[should_panic = "value of `list0` is out of range"]
+fn bitfield_overflow() {
+    EncMaxRefFrames::default().with_list0(0x10000);
+}
+
+#[test]
+fn validate_attribs() {
+    let display = Display::with_backend(FakeBackend::new()).unwrap();
+
+    let supported = display
+        .query_supported_config_attributes(Profile::JPEGBaseline, Entrypoint::VLD)
+        .unwrap();
+    assert!(supported.get(ConfigAttribType::RTFormat).is_some());
+    assert!(supported.get(ConfigAttribType::RateControl).is_none());
+    assert!(supported.supports(&ConfigAttribEnum::RTFormat(RTFormat::YUV422).into()));
+    assert!(!supported.supports(&ConfigAttribEnum::RTFormat(RTFormat::RGB32).into()));
+
+    let err = Config::with_attribs(
+        &display,
+        Profile::JPEGBaseline,
+        Entrypoint::VLD,
+        &mut [ConfigAttribEnum::RTFormat(RTFormat::YUV420 | RTFormat::RGB32).into()],
+    )
+    .err()
+    .unwrap();
+    assert_eq!(err.kind(), ErrorKind::UnsupportedConfig);
+    assert!(err.is_unsupported());
+    let unsupported = err.unsupported_attrib().unwrap();
+    assert_eq!(
+        unsupported.requested().raw_value(),
+        (RTFormat::YUV420 | RTFormat::RGB32).bits()
+    );
+    assert_eq!(
+        err.to_string(),
+        "JPEGBaseline with entrypoint VLD does not support RTFormat(YUV420 | RGB32) \
+         (supported: RTFormat(YUV420 | YUV422 | YUV444 | YUV400))"
+    );
+
+    let err = Config::with_attribs(
+        &display,
+        Profile::JPEGBaseline,
+        Entrypoint::VLD,
+        &mut [ConfigAttribEnum::RateControl(RateControl::CQP).into()],
+    )
+    .err()
+    .unwrap();
+    assert!(err.unsupported_attrib().unwrap().supported().is_none());
+    assert_eq!(
+        err.to_string(),
+        "JPEGBaseline with entrypoint VLD does not support the config attribute RateControl"
+    );
+
+    let config = Config::negotiate(
+        &display,
+        Profile::JPEGBaseline,
+        Entrypoint::VLD,
+        &[&[
+            ConfigAttribEnum::RTFormat(RTFormat::RGB32),
+            ConfigAttribEnum::RTFormat(RTFormat::YUV422),
+            ConfigAttribEnum::RTFormat(RTFormat::YUV420),
+        ]],
+    )
+    .unwrap();
+    let rt_format = config
+        .query_config_attributes()
+        .unwrap()
+        .into_iter()
+        .find(|attrib| attrib.attrib_type() == ConfigAttribType::RTFormat)
+        .unwrap();
+    assert_eq!(rt_format.raw_value(), RTFormat::YUV422.bits());
+
+    let err = Config::negotiate(
+        &display,
+        Profile::JPEGBaseline,
+        Entrypoint::VLD,
+        &[&[ConfigAttribEnum::RTFormat(RTFormat::RGB32)]],
+    )
+    .err()
+    .unwrap();
+    assert!(err.unsupported_attrib().is_some());
+}
+
+#[test]
+fn processing_rate() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let err = config.query_decode_processing_rate(0).unwrap_err();
+    assert!(err.unsupported_attrib().is_some());
+
+    fake.set_processing_rate(Profile::JPEGBaseline, Entrypoint::VLD, 240);
+    assert_eq!(config.query_decode_processing_rate(0).unwrap(), 240);
+
+    // This is a decoding config, so encoding rates can't be queried.
+    let err = config
+        .query_encode_processing_rate(&EncodeRateParams::new(0).with_ip_period(1))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "JPEGBaseline with entrypoint VLD does not support ProcessingRate(ENCODE) \
+         (supported: ProcessingRate(DECODE))"
+    );
+
+    let fake = FakeBackend::new();
+    fake.set_features(LibvaFeatures::empty());
+    fake.set_processing_rate(Profile::JPEGBaseline, Entrypoint::VLD, 240);
+    let display = Display::with_backend(fake).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let err = config.query_decode_processing_rate(0).unwrap_err();
+    assert_eq!(err.missing_function(), Some("vaQueryProcessingRate"));
+}
+
+#[test]
+fn validate_bitfield_attribs() {
+    let roi = EncRoi::default()
+        .with_num_roi_regions(8)
+        .with_roi_rc_qp_delta_support(true);
+    let hevc = EncHevcFeatures::default()
+        .with_amp(FeatureSupport::Supported)
+        .with_sao(FeatureSupport::Required);
+    let supported = SupportedConfigAttributes {
+        profile: Profile::HEVCMain,
+        entrypoint: Entrypoint::EncSlice,
+        attribs: vec![
+            ConfigAttribEnum::EncROI(roi).into(),
+            ConfigAttribEnum::EncHEVCFeatures(hevc).into(),
+        ],
+    };
+    let supports = |attrib: ConfigAttribEnum| supported.supports(&attrib.into());
+
+    // Limits must not be exceeded, and requested flags have to be supported.
+    assert!(supports(ConfigAttribEnum::EncROI(
+        EncRoi::default().with_num_roi_regions(4)
+    )));
+    assert!(!supports(ConfigAttribEnum::EncROI(
+        EncRoi::default().with_num_roi_regions(9)
+    )));
+    assert!(!supports(ConfigAttribEnum::EncROI(
+        EncRoi::default().with_roi_rc_priority_support(true)
+    )));
+
+    // Requested tools have to be supported, and required ones can't be turned off.
+    let requested = EncHevcFeatures::default().with_sao(FeatureSupport::Supported);
+    assert!(supports(ConfigAttribEnum::EncHEVCFeatures(
+        requested.with_amp(FeatureSupport::Supported)
+    )));
+    assert!(!supports(ConfigAttribEnum::EncHEVCFeatures(
+        requested.with_pcm(FeatureSupport::Supported)
+    )));
+    assert!(!supports(ConfigAttribEnum::EncHEVCFeatures(
+        EncHevcFeatures::default()
+    )));
+}
@@ -1,76 +1,274 @@
 //! Codec contexts.
 
-use std::{ptr, sync::Arc};
+#[cfg(test)]
+mod tests;
+
+use std::{ffi::c_int, marker::PhantomData, mem, sync::Arc};
 
 use crate::{
-    buffer::Buffer, check, check_log, config::Config, display::DisplayOwner, raw::VAContextID,
-    surface::Surface, Result,
+    buffer::{Buffer, BufferType, RawBuffer},
+    check, check_log,
+    config::{
+        Config, ConfigAttribEnum, ConfigAttribType, MultipleFrame, SupportedConfigAttributes,
+    },
+    display::{Display, DisplayOwner, LibvaFeatures},
+    error::Object,
+    raw::{VAContextID, VAMFContextID, VASurfaceID},
+    surface::{Surface, SurfaceOwner},
+    Entrypoint, Error, Profile, Result,
 };
 
+/// The largest frame count the `MultipleFrame` config attribute can hold.
+const MAX_CONCURRENT_FRAMES: usize = 0xff;
+
+bitflags! {
+    /// Flags passed to `vaCreateContext`.
+    pub struct ContextFlags: u32 {
+        /// Only progressive frames will be processed.
+        const PROGRESSIVE = 0x00000001;
+    }
+}
+
 /// A codec, configured for a video operation.
 ///
 /// Submit work to a context by calling [`Context::begin_picture`].
 pub struct Context {
     pub(crate) d: Arc<DisplayOwner>,
     pub(crate) id: VAContextID,
+    pub(crate) profile: Profile,
+    pub(crate) entrypoint: Entrypoint,
+    /// The surfaces declared when creating the context. If this is empty, any surface may be
+    /// rendered to.
+    pub(crate) render_targets: Vec<Arc<SurfaceOwner>>,
+    owner: Arc<ContextOwner>,
+}
+
+/// Destroys the libva context once the [`Context`] and every object using it are dropped.
+#[derive(Debug)]
+pub(crate) struct ContextOwner {
+    d: Arc<DisplayOwner>,
+    id: VAContextID,
+}
+
+impl Drop for ContextOwner {
+    fn drop(&mut self) {
+        unsafe {
+            check_log(
+                self.d.libva.vaDestroyContext(self.d.raw, self.id),
+                "vaDestroyContext",
+                &[Object::Context(self.id)],
+            );
+        }
+    }
 }
 
 impl Context {
+    /// Creates a [`Context`] without declaring its render targets up front.
+    ///
+    /// Any [`Surface`] can be passed to [`Context::begin_picture`]. Some drivers need to know the
+    /// render targets when creating the context, in which case [`Context::builder`] should be
+    /// used instead.
     pub fn new(config: &Config, picture_width: u32, picture_height: u32) -> Result<Self> {
-        unsafe {
-            let mut context_id = 0;
-            check(config.d.libva.vaCreateContext(
-                config.d.raw,
-                config.id,
-                picture_width as _,
-                picture_height as _,
-                0,
-                ptr::null_mut(),
-                0,
-                &mut context_id,
-            ))?;
-            Ok(Context {
+        Self::builder(config, picture_width, picture_height).build()
+    }
+
+    /// Returns a [`ContextBuilder`] for creating a [`Context`] with render targets and flags.
+    pub fn builder(config: &Config, picture_width: u32, picture_height: u32) -> ContextBuilder<'_> {
+        ContextBuilder {
+            config,
+            picture_width,
+            picture_height,
+            flags: ContextFlags::empty(),
+            render_targets: Vec::new(),
+        }
+    }
+
+    /// Wraps the context `id`, which was created from `config` with the given render targets.
+    pub(crate) fn from_raw(
+        config: &Config,
+        id: VAContextID,
+        render_targets: Vec<Arc<SurfaceOwner>>,
+    ) -> Self {
+        Self {
+            d: config.d.clone(),
+            id,
+            profile: config.profile(),
+            entrypoint: config.entrypoint(),
+            render_targets,
+            owner: Arc::new(ContextOwner {
                 d: config.d.clone(),
-                id: context_id,
-            })
+                id,
+            }),
         }
     }
 
+    /// Returns a handle that keeps the libva context alive, even after `self` is dropped.
+    #[inline]
+    pub(crate) fn owner(&self) -> &Arc<ContextOwner> {
+        &self.owner
+    }
+
+    /// Returns the [`Profile`] of the [`Config`] the context was created from.
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Returns the [`Entrypoint`] of the [`Config`] the context was created from.
+    pub fn entrypoint(&self) -> Entrypoint {
+        self.entrypoint
+    }
+
+    /// Returns whether `surface` may be passed to [`Context::begin_picture`].
+    ///
+    /// If the context was created without render targets, this returns `true` for every surface.
+    pub fn is_render_target(&self, surface: &Surface) -> bool {
+        self.check_render_target(surface.id()).is_ok()
+    }
+
+    /// Starts building a picture whose buffers are checked against the [`PictureLayout`] `L`.
+    ///
+    /// Unlike [`Context::begin_picture`], this doesn't call into libva until the picture is
+    /// submitted, and the submitted picture is checked to contain all required buffers, so it
+    /// doesn't need `unsafe` code.
+    pub fn build_picture<L: PictureLayout>(
+        &mut self,
+        target: &Surface,
+    ) -> Result<PictureBuilder<'_, L>> {
+        if !L::supports(self.profile, self.entrypoint) {
+            return Err(Error::invalid_input(format!(
+                "context {} ({:?} with entrypoint {:?}) doesn't support this picture layout",
+                self.id, self.profile, self.entrypoint
+            )));
+        }
+        self.check_render_target(target.id())?;
+
+        Ok(PictureBuilder {
+            context: self,
+            target: target.owner().clone(),
+            buffers: Vec::new(),
+            last: None,
+            _p: PhantomData,
+        })
+    }
+
     /// Begins a libva operation that will render to (or encode from) the given [`Surface`].
-    pub fn begin_picture<'a>(
-        &'a mut self,
-        target: &'a mut Surface,
-    ) -> Result<InProgressPicture<'a>> {
+    ///
+    /// If the context was created with render targets, `target` has to be one of them. The
+    /// returned [`InProgressPicture`] keeps `target` alive.
+    ///
+    /// Ending the picture requires `unsafe` code. [`Context::build_picture`] checks the submitted
+    /// buffers instead.
+    pub fn begin_picture(&mut self, target: &Surface) -> Result<InProgressPicture<'_>> {
+        self.check_render_target(target.id())?;
+        self.begin(target.owner().clone())
+    }
+
+    fn check_render_target(&self, target: VASurfaceID) -> Result<()> {
+        if !self.render_targets.is_empty()
+            && !self.render_targets.iter().any(|owner| owner.id() == target)
+        {
+            return Err(Error::invalid_input(format!(
+                "surface {} is not a render target of context {}",
+                target, self.id
+            )));
+        }
+        Ok(())
+    }
+
+    fn begin(&mut self, target: Arc<SurfaceOwner>) -> Result<InProgressPicture<'_>> {
+        target.release_finished();
         unsafe {
             check(
                 self.d
                     .libva
                     .vaBeginPicture(self.d.raw, self.id, target.id()),
+                "vaBeginPicture",
+                &[Object::Context(self.id), Object::Surface(target.id())],
             )?;
         }
 
         Ok(InProgressPicture {
             d: self.d.clone(),
             context: self,
+            target,
+            buffers: Vec::new(),
         })
     }
 }
 
-impl Drop for Context {
-    fn drop(&mut self) {
+/// Configures and creates a [`Context`].
+///
+/// Returned by [`Context::builder`].
+pub struct ContextBuilder<'a> {
+    config: &'a Config,
+    picture_width: u32,
+    picture_height: u32,
+    flags: ContextFlags,
+    render_targets: Vec<Arc<SurfaceOwner>>,
+}
+
+impl<'a> ContextBuilder<'a> {
+    /// Sets the flags passed to `vaCreateContext`.
+    pub fn flags(mut self, flags: ContextFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Adds a [`Surface`] to the render targets of the context.
+    ///
+    /// The [`Context`] keeps all of its render targets alive, and only accepts them in
+    /// [`Context::begin_picture`].
+    pub fn render_target(mut self, surface: &Surface) -> Self {
+        self.render_targets.push(surface.owner().clone());
+        self
+    }
+
+    /// Adds several [`Surface`]s to the render targets of the context.
+    pub fn render_targets<'s>(mut self, surfaces: impl IntoIterator<Item = &'s Surface>) -> Self {
+        self.render_targets
+            .extend(surfaces.into_iter().map(|surface| surface.owner().clone()));
+        self
+    }
+
+    /// Creates the [`Context`].
+    pub fn build(self) -> Result<Context> {
+        let config = self.config;
+        let mut ids = self
+            .render_targets
+            .iter()
+            .map(|target| target.id())
+            .collect::<Vec<VASurfaceID>>();
+        let mut context_id = 0;
         unsafe {
-            check_log(
-                self.d.libva.vaDestroyContext(self.d.raw, self.id),
-                "vaDestroyContext call in drop",
-            );
+            check(
+                config.d.libva.vaCreateContext(
+                    config.d.raw,
+                    config.id,
+                    self.picture_width as _,
+                    self.picture_height as _,
+                    self.flags.bits() as c_int,
+                    ids.as_mut_ptr(),
+                    ids.len().try_into().map_err(Error::from)?,
+                    &mut context_id,
+                ),
+                "vaCreateContext",
+                &[Object::Config(config.id)],
+            )?;
         }
+        Ok(Context::from_raw(config, context_id, self.render_targets))
     }
 }
 
 /// An operation whose submission is still in progress.
+///
+/// The picture owns the submitted buffers. Once it is ended, they are kept alive until its target
+/// [`Surface`] has been synced or reports that it's no longer rendering, since the driver may read
+/// from them until the operation has finished. The buffers also keep their [`Context`] alive.
 pub struct InProgressPicture<'a> {
     d: Arc<DisplayOwner>,
     context: &'a mut Context,
+    target: Arc<SurfaceOwner>,
+    buffers: Vec<RawBuffer>,
 }
 
 impl<'a> InProgressPicture<'a> {
@@ -78,12 +276,21 @@ impl<'a> InProgressPicture<'a> {
     ///
     /// Typically, libva does not document which buffer types are required for any given entry
     /// point, so good luck!
-    pub fn render_picture<T>(&mut self, buffer: &mut Buffer<T>) -> Result<()> {
+    pub fn render_picture<T>(&mut self, buffer: Buffer<T>) -> Result<()> {
+        self.render_raw(buffer.into())
+    }
+
+    fn render_raw(&mut self, buffer: RawBuffer) -> Result<()> {
+        let mut id = buffer.id();
+        // The driver may hold on to the buffer even if the call fails.
+        self.buffers.push(buffer);
         unsafe {
             check(
                 self.d
                     .libva
-                    .vaRenderPicture(self.d.raw, self.context.id, &mut buffer.id(), 1),
+                    .vaRenderPicture(self.d.raw, self.context.id, &mut id, 1),
+                "vaRenderPicture",
+                &[Object::Context(self.context.id), Object::Buffer(id)],
             )
         }
     }
@@ -99,6 +306,378 @@ impl<'a> InProgressPicture<'a> {
     ///
     /// So, basically, the safety invariant of this method is "fuck if I know". Good luck, Loser.
     pub unsafe fn end_picture(self) -> Result<()> {
-        check(self.d.libva.vaEndPicture(self.d.raw, self.context.id))
+        let result = check(
+            self.d.libva.vaEndPicture(self.d.raw, self.context.id),
+            "vaEndPicture",
+            &[Object::Context(self.context.id)],
+        );
+        self.target.retain_until_synced(self.buffers);
+        result
+    }
+}
+
+/// A buffer that a [`PictureLayout`] expects in a picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferSlot {
+    ty: BufferType,
+    required: bool,
+    repeated: bool,
+}
+
+impl BufferSlot {
+    /// A buffer of type `ty` that has to be submitted exactly once.
+    pub const fn required(ty: BufferType) -> Self {
+        Self {
+            ty,
+            required: true,
+            repeated: false,
+        }
+    }
+
+    /// A buffer of type `ty` that may be submitted at most once.
+    pub const fn optional(ty: BufferType) -> Self {
+        Self {
+            ty,
+            required: false,
+            repeated: false,
+        }
+    }
+
+    /// Allows submitting several buffers of this type in a row.
+    pub const fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+
+    pub fn buffer_type(&self) -> BufferType {
+        self.ty
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    pub fn is_repeated(&self) -> bool {
+        self.repeated
+    }
+}
+
+/// Describes the buffers that make up a picture for a codec and entrypoint.
+///
+/// Implementors are used with [`Context::build_picture`], which only allows submitting the
+/// buffers listed in [`PictureLayout::BUFFERS`], in that order.
+///
+/// # Safety
+///
+/// [`PictureBuilder`] ends pictures without `unsafe` code, based on this description alone (see
+/// [`InProgressPicture::end_picture`]). Implementors have to ensure that ending a picture is
+/// sound whenever it consists of buffers that match [`PictureLayout::BUFFERS`] and contains all
+/// required ones, for every profile and entrypoint that [`PictureLayout::supports`] accepts. In
+/// particular, every buffer the driver needs to process the picture has to be required.
+pub unsafe trait PictureLayout {
+    /// The buffers of a picture, in submission order.
+    const BUFFERS: &'static [BufferSlot];
+
+    /// Returns whether pictures of this layout can be submitted to contexts created with
+    /// `profile` and `entrypoint`.
+    fn supports(profile: Profile, entrypoint: Entrypoint) -> bool;
+}
+
+/// Collects the buffers of a picture and checks them against a [`PictureLayout`].
+///
+/// Returned by [`Context::build_picture`]. Nothing is passed to libva until the picture is
+/// submitted, so a picture that fails the checks leaves the [`Context`] untouched.
+pub struct PictureBuilder<'a, L: PictureLayout> {
+    context: &'a mut Context,
+    target: Arc<SurfaceOwner>,
+    buffers: Vec<RawBuffer>,
+    /// Index of the last slot in [`PictureLayout::BUFFERS`] that a buffer was added for.
+    last: Option<usize>,
+    _p: PhantomData<L>,
+}
+
+impl<'a, L: PictureLayout> PictureBuilder<'a, L> {
+    /// Adds a [`Buffer`] to the picture.
+    ///
+    /// Fails if the layout doesn't allow a buffer of its type at this point, either because it
+    /// isn't part of the layout, because it was already added, or because a required buffer that
+    /// comes before it is still missing.
+    pub fn add_buffer<T>(&mut self, buffer: Buffer<T>) -> Result<()> {
+        let ty = buffer.buffer_type();
+        let (first, next) = match self.last {
+            Some(last) if L::BUFFERS[last].repeated => (last, last + 1),
+            Some(last) => (last + 1, last + 1),
+            None => (0, 0),
+        };
+        let Some(index) = L::BUFFERS[first..]
+            .iter()
+            .position(|slot| slot.ty == ty)
+            .map(|i| first + i)
+        else {
+            let msg = match (L::BUFFERS.iter().position(|slot| slot.ty == ty), self.last) {
+                (Some(i), Some(last)) if i == last => {
+                    format!("{ty:?} buffer was already submitted")
+                }
+                (Some(_), Some(last)) => format!(
+                    "{ty:?} buffer has to be submitted before {:?}",
+                    L::BUFFERS[last].ty
+                ),
+                _ => format!("{ty:?} buffers are not part of the picture"),
+            };
+            return Err(Error::invalid_input(msg));
+        };
+        if let Some(missing) = L::BUFFERS[next.min(index)..index]
+            .iter()
+            .find(|slot| slot.required)
+        {
+            return Err(Error::invalid_input(format!(
+                "{:?} buffer has to be submitted before {ty:?}",
+                missing.ty
+            )));
+        }
+
+        self.buffers.push(buffer.into());
+        self.last = Some(index);
+        Ok(())
+    }
+
+    /// Returns the buffer types that are required, but haven't been added yet.
+    pub fn missing(&self) -> Vec<BufferType> {
+        let next = self.last.map_or(0, |last| last + 1);
+        L::BUFFERS[next..]
+            .iter()
+            .filter(|slot| slot.required)
+            .map(|slot| slot.ty)
+            .collect()
+    }
+
+    /// Checks that all required buffers were added, and returns the complete [`Picture`].
+    pub fn finish(self) -> Result<Picture<'a>> {
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(Error::invalid_input(format!(
+                "picture is missing buffers: {missing:?}"
+            )));
+        }
+
+        Ok(Picture {
+            context: self.context,
+            target: self.target,
+            buffers: self.buffers,
+        })
+    }
+
+    /// Checks that all required buffers were added, and submits the picture.
+    ///
+    /// See [`Picture::submit`].
+    pub fn submit(self) -> Result<()> {
+        self.finish()?.submit()
+    }
+}
+
+/// A picture whose buffers were checked against a [`PictureLayout`], and that is ready to be
+/// submitted.
+///
+/// Returned by [`PictureBuilder::finish`]. Submit it on its own with [`Picture::submit`], or
+/// together with pictures of other contexts with [`MFContext::submit`].
+pub struct Picture<'a> {
+    context: &'a mut Context,
+    target: Arc<SurfaceOwner>,
+    buffers: Vec<RawBuffer>,
+}
+
+impl Picture<'_> {
+    /// Begins the picture, submits its buffers and ends it, which starts the libva operation.
+    ///
+    /// If submitting a buffer fails, the incomplete picture is not ended. libva has no way of
+    /// aborting it, but beginning the next picture on the [`Context`] discards it. The buffers
+    /// are kept alive until the target [`Surface`] has been synced.
+    pub fn submit(self) -> Result<()> {
+        let mut picture = self.context.begin(self.target)?;
+        for buffer in self.buffers {
+            if let Err(e) = picture.render_raw(buffer) {
+                // The driver may still reference the buffers that were submitted so far.
+                picture
+                    .target
+                    .retain_until_synced(mem::take(&mut picture.buffers));
+                return Err(e);
+            }
+        }
+        // Safety: all buffers required by a `PictureLayout` were submitted, which its
+        // implementation guarantees to be sufficient.
+        unsafe { picture.end_picture() }
+    }
+}
+
+/// A multi-frame context, which submits the pictures of several [`Context`]s at once.
+///
+/// Submitting pictures together allows the driver to schedule them together, for example when
+/// encoding several streams on the same engine. The [`Context`]s have to be added to the
+/// [`MFContext`] with [`MFContext::add_context`] before their pictures can be submitted via
+/// [`MFContext::submit`].
+///
+/// This requires [`LibvaFeatures::MULTI_FRAME`], and the configs of all added contexts have to
+/// support [`ConfigAttribType::MultipleFrame`]. Added contexts are kept alive until they are
+/// released or the [`MFContext`] is dropped.
+pub struct MFContext {
+    d: Arc<DisplayOwner>,
+    id: VAMFContextID,
+    contexts: Vec<Arc<ContextOwner>>,
+}
+
+impl MFContext {
+    /// Creates an empty [`MFContext`].
+    pub fn new(display: &Display) -> Result<Self> {
+        display
+            .d
+            .require(LibvaFeatures::MULTI_FRAME, "vaCreateMFContext")?;
+        let mut id = 0;
+        unsafe {
+            check(
+                display.d.libva.vaCreateMFContext(display.d.raw, &mut id),
+                "vaCreateMFContext",
+                &[],
+            )?;
+        }
+        Ok(Self {
+            d: display.d.clone(),
+            id,
+            contexts: Vec::new(),
+        })
+    }
+
+    /// Adds a [`Context`] to this [`MFContext`].
+    ///
+    /// Fails if the config of `context` doesn't support the `MultipleFrame` attribute, or if the
+    /// driver can't process as many concurrent frames as there would be contexts.
+    pub fn add_context(&mut self, context: &Context) -> Result<()> {
+        if self.contains(context) {
+            return Err(Error::invalid_input(format!(
+                "context {} was already added to multi-frame context {}",
+                context.id, self.id
+            )));
+        }
+
+        let supported = SupportedConfigAttributes::query(
+            &self.d,
+            context.profile,
+            context.entrypoint,
+            &[ConfigAttribType::MultipleFrame],
+        )?;
+        // The frame count is compared numerically, since it may not fit into the attribute.
+        let frames = self.contexts.len() + 1;
+        let max = supported
+            .get(ConfigAttribType::MultipleFrame)
+            .map_or(0, |attrib| {
+                MultipleFrame::from_raw(attrib.raw_value()).max_num_concurrent_frames()
+            });
+        if frames > max as usize {
+            // Larger counts are reported as the largest one the attribute can hold.
+            let requested = MultipleFrame::default()
+                .with_max_num_concurrent_frames(frames.min(MAX_CONCURRENT_FRAMES) as u32);
+            return Err(supported.unsupported(ConfigAttribEnum::MultipleFrame(requested).into()));
+        }
+
+        unsafe {
+            check(
+                self.d.libva.vaMFAddContext(self.d.raw, self.id, context.id),
+                "vaMFAddContext",
+                &[Object::Context(self.id), Object::Context(context.id)],
+            )?;
+        }
+        self.contexts.push(context.owner.clone());
+        Ok(())
+    }
+
+    /// Removes a [`Context`] that was added with [`MFContext::add_context`].
+    pub fn release_context(&mut self, context: &Context) -> Result<()> {
+        let Some(index) = self
+            .contexts
+            .iter()
+            .position(|owner| Arc::ptr_eq(owner, &context.owner))
+        else {
+            return Err(Error::invalid_input(format!(
+                "context {} is not part of multi-frame context {}",
+                context.id, self.id
+            )));
+        };
+
+        unsafe {
+            check(
+                self.d
+                    .libva
+                    .vaMFReleaseContext(self.d.raw, self.id, context.id),
+                "vaMFReleaseContext",
+                &[Object::Context(self.id), Object::Context(context.id)],
+            )?;
+        }
+        self.contexts.remove(index);
+        Ok(())
+    }
+
+    /// Returns whether `context` was added to this [`MFContext`].
+    pub fn contains(&self, context: &Context) -> bool {
+        self.contexts
+            .iter()
+            .any(|owner| Arc::ptr_eq(owner, &context.owner))
+    }
+
+    /// Submits the given pictures like [`Picture::submit`], and then to the driver together.
+    ///
+    /// The [`Context`]s of all pictures have to be added to this [`MFContext`] first. This is
+    /// checked before any picture is begun. If submitting a picture fails, the remaining
+    /// pictures are dropped, the ones that were ended before it are still passed to the driver,
+    /// and the error is returned.
+    pub fn submit(&mut self, pictures: Vec<Picture<'_>>) -> Result<()> {
+        for picture in &pictures {
+            if !self.contains(picture.context) {
+                return Err(Error::invalid_input(format!(
+                    "context {} is not part of multi-frame context {}",
+                    picture.context.id, self.id
+                )));
+            }
+        }
+
+        let mut ids = Vec::with_capacity(pictures.len());
+        let mut result = Ok(());
+        for picture in pictures {
+            let id = picture.context.id;
+            match picture.submit() {
+                Ok(()) => ids.push(id),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if !ids.is_empty() {
+            let submitted = unsafe {
+                check(
+                    self.d.libva.vaMFSubmit(
+                        self.d.raw,
+                        self.id,
+                        ids.as_mut_ptr(),
+                        ids.len().try_into().map_err(Error::from)?,
+                    ),
+                    "vaMFSubmit",
+                    &[Object::Context(self.id)],
+                )
+            };
+            result = result.and(submitted);
+        }
+        result
+    }
+}
+
+impl Drop for MFContext {
+    fn drop(&mut self) {
+        // Multi-frame contexts are destroyed like regular contexts.
+        unsafe {
+            check_log(
+                self.d.libva.vaDestroyContext(self.d.raw, self.id),
+                "vaDestroyContext",
+                &[Object::Context(self.id)],
+            );
+        }
     }
 }
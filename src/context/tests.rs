@@ -0,0 +1,264 @@
+use crate::{
+    backend::FakeBackend,
+    buffer::{Buffer, BufferType},
+    config::Config,
+    display::{Display, LibvaFeatures},
+    error::{ErrorKind, VAError},
+    jpeg::JpegDecodePicture,
+    surface::{RTFormat, Surface},
+    vpp::VideoProcPicture,
+    Entrypoint, Profile,
+};
+
+use super::{BufferSlot, Context, ContextFlags, MFContext, Picture, PictureLayout};
+
+#[test]
+fn context_render_targets() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let target = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let other = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+
+    let mut context = Context::builder(&config, 64, 64)
+        .flags(ContextFlags::PROGRESSIVE)
+        .render_target(&target)
+        .build()
+        .unwrap();
+    assert!(context.is_render_target(&target));
+    assert!(!context.is_render_target(&other));
+
+    let err = context.begin_picture(&other).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    context.begin_picture(&target).unwrap();
+
+    // The context keeps its render targets alive.
+    drop((target, other));
+    assert_eq!(fake.live_objects().surfaces, 1);
+    drop(context);
+    assert_eq!(fake.live_objects().surfaces, 0);
+}
+
+/// Builds a complete [`JpegDecodePicture`] of zeroed buffers.
+fn jpeg_picture<'a>(context: &'a mut Context, target: &Surface) -> Picture<'a> {
+    let buffers = JpegDecodePicture::BUFFERS
+        .iter()
+        .map(|slot| Buffer::new_param(context, slot.buffer_type(), 0u32).unwrap())
+        .collect::<Vec<_>>();
+    let mut picture = context.build_picture::<JpegDecodePicture>(target).unwrap();
+    for buffer in buffers {
+        picture.add_buffer(buffer).unwrap();
+    }
+    picture.finish().unwrap()
+}
+
+#[test]
+fn multi_frame_context() {
+    let fake = FakeBackend::new();
+    fake.set_max_concurrent_frames(Profile::JPEGBaseline, Entrypoint::VLD, 2);
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut contexts = (0..3)
+        .map(|_| Context::new(&config, 64, 64).unwrap())
+        .collect::<Vec<_>>();
+    let targets = (0..2)
+        .map(|_| Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap())
+        .collect::<Vec<_>>();
+
+    let mut mf = MFContext::new(&display).unwrap();
+    mf.add_context(&contexts[0]).unwrap();
+    mf.add_context(&contexts[1]).unwrap();
+    assert!(mf.contains(&contexts[1]));
+    let err = mf.add_context(&contexts[2]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnsupportedConfig);
+    assert!(!mf.contains(&contexts[2]));
+
+    let (first, rest) = contexts.split_at_mut(1);
+    let pictures = vec![
+        jpeg_picture(&mut first[0], &targets[0]),
+        jpeg_picture(&mut rest[0], &targets[1]),
+    ];
+    mf.submit(pictures).unwrap();
+    let submissions = fake.submissions();
+    assert_eq!(submissions.len(), 2);
+    assert_eq!(submissions[0].context(), contexts[0].id);
+    assert_eq!(submissions[1].context(), contexts[1].id);
+
+    // Pictures of contexts that weren't added can't be submitted, and none of the pictures are
+    // begun.
+    mf.release_context(&contexts[1]).unwrap();
+    let (first, rest) = contexts.split_at_mut(1);
+    let pictures = vec![
+        jpeg_picture(&mut first[0], &targets[0]),
+        jpeg_picture(&mut rest[0], &targets[1]),
+    ];
+    let err = mf.submit(pictures).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(fake.submissions().len(), 2);
+
+    // Pictures that were ended before a failure are still passed to the driver.
+    mf.add_context(&contexts[1]).unwrap();
+    // The first picture consists of 5 buffers.
+    fake.fail_after("vaRenderPicture", 5, VAError::ERROR_HW_BUSY);
+    let (first, rest) = contexts.split_at_mut(1);
+    let pictures = vec![
+        jpeg_picture(&mut rest[0], &targets[1]),
+        jpeg_picture(&mut first[0], &targets[0]),
+    ];
+    let err = mf.submit(pictures).unwrap_err();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_HW_BUSY));
+    let submissions = fake.submissions();
+    assert_eq!(submissions.len(), 3);
+    assert_eq!(submissions[2].context(), contexts[1].id);
+    assert_eq!(fake.abandoned_pictures(), 0);
+
+    // Added contexts are kept alive by the multi-frame context (and by buffers that are in
+    // flight, so finish those first).
+    for target in &targets {
+        target.status().unwrap();
+    }
+    drop(contexts.remove(0));
+    assert_eq!(fake.live_objects().contexts, 4);
+    drop(mf);
+    assert_eq!(fake.live_objects().contexts, 2);
+
+    // Configs that don't support `MultipleFrame` are rejected.
+    let vpp = Config::new(&display, Profile::None, Entrypoint::VideoProc).unwrap();
+    let vpp_context = Context::new(&vpp, 64, 64).unwrap();
+    let mut mf = MFContext::new(&display).unwrap();
+    let err = mf.add_context(&vpp_context).unwrap_err();
+    let unsupported = err.unsupported_attrib().unwrap();
+    assert!(unsupported.supported().is_none());
+
+    // Frame counts beyond what the attribute can hold are rejected as well.
+    let fake = FakeBackend::new();
+    fake.set_max_concurrent_frames(Profile::JPEGBaseline, Entrypoint::VLD, 255);
+    let display = Display::with_backend(fake).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut mf = MFContext::new(&display).unwrap();
+    for _ in 0..255 {
+        mf.add_context(&Context::new(&config, 64, 64).unwrap())
+            .unwrap();
+    }
+    let err = mf
+        .add_context(&Context::new(&config, 64, 64).unwrap())
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnsupportedConfig);
+
+    let fake = FakeBackend::new();
+    fake.set_features(LibvaFeatures::empty());
+    let display = Display::with_backend(fake).unwrap();
+    let err = MFContext::new(&display).err().unwrap();
+    assert_eq!(err.missing_function(), Some("vaCreateMFContext"));
+}
+
+#[test]
+fn picture_layout() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let buffer = |context: &Context, ty| Buffer::new_param(context, ty, 0u32).unwrap();
+
+    let err = context
+        .build_picture::<VideoProcPicture>(&surface)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let dht = buffer(&context, BufferType::HuffmanTable);
+    let pp = buffer(&context, BufferType::PictureParameter);
+    let iq = buffer(&context, BufferType::IQMatrix);
+    let pipeline = buffer(&context, BufferType::ProcPipelineParameter);
+    let mut picture = context
+        .build_picture::<JpegDecodePicture>(&surface)
+        .unwrap();
+    picture.add_buffer(dht).unwrap();
+    // Required buffers can't be skipped, and buffers can't be submitted twice or out of order.
+    let err = picture.add_buffer(pp).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("IQMatrix buffer has to be submitted before PictureParameter"));
+    picture.add_buffer(iq).unwrap();
+    assert_eq!(
+        picture.missing(),
+        [
+            BufferType::PictureParameter,
+            BufferType::SliceParameter,
+            BufferType::SliceData
+        ]
+    );
+    let err = picture.add_buffer(pipeline).unwrap_err();
+    assert!(err.to_string().contains("not part of the picture"));
+    let err = picture.finish().err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(fake.submissions().is_empty());
+
+    let buffers = JpegDecodePicture::BUFFERS
+        .iter()
+        .map(|slot| buffer(&context, slot.buffer_type()))
+        .collect::<Vec<_>>();
+    let mut picture = context
+        .build_picture::<JpegDecodePicture>(&surface)
+        .unwrap();
+    for buffer in buffers {
+        picture.add_buffer(buffer).unwrap();
+    }
+    picture.submit().unwrap();
+    assert_eq!(
+        fake.submissions()[0].buffer_types(),
+        [
+            BufferType::HuffmanTable,
+            BufferType::IQMatrix,
+            BufferType::PictureParameter,
+            BufferType::SliceParameter,
+            BufferType::SliceData,
+        ]
+    );
+
+    // Optional buffers can be skipped, repeated ones submitted several times.
+    struct Layout;
+    unsafe impl PictureLayout for Layout {
+        const BUFFERS: &'static [BufferSlot] = &[
+            BufferSlot::optional(BufferType::PictureParameter),
+            BufferSlot::required(BufferType::SliceData).repeated(),
+            BufferSlot::optional(BufferType::SliceParameter),
+        ];
+
+        fn supports(_profile: Profile, _entrypoint: Entrypoint) -> bool {
+            true
+        }
+    }
+    let buffers = [
+        BufferType::SliceData,
+        BufferType::SliceData,
+        BufferType::PictureParameter,
+    ]
+    .map(|ty| buffer(&context, ty));
+    let mut picture = context.build_picture::<Layout>(&surface).unwrap();
+    let [first, second, pp] = buffers;
+    picture.add_buffer(first).unwrap();
+    picture.add_buffer(second).unwrap();
+    let err = picture.add_buffer(pp).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("PictureParameter buffer has to be submitted before SliceData"));
+    assert!(picture.missing().is_empty());
+    picture.submit().unwrap();
+    assert_eq!(
+        fake.submissions()[1].buffer_types(),
+        [BufferType::SliceData, BufferType::SliceData]
+    );
+
+    // A picture whose buffers the driver rejects is not ended, but discarded by the next one.
+    // The buffers that were submitted, including the rejected one, are kept alive until then.
+    fake.fail_after("vaRenderPicture", 1, VAError::ERROR_INVALID_BUFFER);
+    let err = jpeg_picture(&mut context, &surface).submit().unwrap_err();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_INVALID_BUFFER));
+    assert_eq!(fake.submissions().len(), 2);
+    assert_eq!(fake.live_objects().buffers, 2);
+    jpeg_picture(&mut context, &surface).submit().unwrap();
+    assert_eq!(fake.submissions().len(), 3);
+    assert_eq!(fake.abandoned_pictures(), 1);
+}
@@ -1,24 +1,40 @@
 //! Display API access and attributes.
 
+#[cfg(test)]
+mod tests;
+
 use core::fmt;
 use std::{
-    ffi::{c_char, c_int, c_void, CStr},
+    env,
+    ffi::{c_char, c_int, c_void, CStr, CString},
     mem,
     panic::catch_unwind,
-    ptr,
-    sync::Arc,
+    path::Path,
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
     vec,
 };
 
 use raw_window_handle::{HasRawDisplayHandle, RawDisplayHandle};
 
 use crate::{
+    backend::Backend,
     check, check_log,
     dlopen::{libva, libva_drm, libva_wayland, libva_x11},
+    drm::{DrmDevice, RenderNode},
+    error::Repr,
     image::{ImageFormat, ImageFormats},
+    intercept::Interceptor,
+    native::{WaylandConnection, XlibConnection},
     raw::{VADisplay, VA_PADDING_LOW},
+    replay::Recording,
     subpicture::{SubpictureFlags, SubpictureFormats},
-    Entrypoint, Entrypoints, Error, Profile, Profiles, Result,
+    trace::Trace,
+    wait::WaitQueue,
+    Entrypoint, Entrypoints, Error, Profile, Profiles, Result, Rotation,
 };
 
 ffi_enum! {
@@ -112,6 +128,34 @@ impl DisplayAttributes {
     pub fn is_empty(&self) -> bool {
         self.vec.is_empty()
     }
+
+    /// Returns the attribute of type `ty`, if the driver supports it.
+    pub fn get(&self, ty: DisplayAttribType) -> Option<&DisplayAttribute> {
+        self.vec.iter().find(|attrib| attrib.type_ == ty)
+    }
+
+    /// Checks that `attrib` may be written, according to the range and flags reported by the
+    /// driver.
+    fn validate_write(&self, attrib: &DisplayAttribute) -> Result<()> {
+        let ty = attrib.type_;
+        let Some(supported) = self.get(ty) else {
+            return Err(Error::from(Repr::UnsupportedDisplayAttrib(ty)));
+        };
+        if !supported.flags.contains(DisplayAttribFlags::SETTABLE) {
+            return Err(Error::invalid_input(format!(
+                "display attribute {ty:?} is read-only"
+            )));
+        }
+        // Composite attributes pack several fields into the value, so a range doesn't apply.
+        let ranged = ty != DisplayAttribType::SubDevice && ty != DisplayAttribType::PCIID;
+        if ranged && !(supported.min_value..=supported.max_value).contains(&attrib.value) {
+            return Err(Error::invalid_input(format!(
+                "value {} for display attribute {ty:?} is outside of the supported range {}..={}",
+                attrib.value, supported.min_value, supported.max_value,
+            )));
+        }
+        Ok(())
+    }
 }
 
 impl IntoIterator for DisplayAttributes {
@@ -123,6 +167,129 @@ impl IntoIterator for DisplayAttributes {
     }
 }
 
+bitflags! {
+    /// Rendering modes, the value of [`DisplayAttribType::RenderMode`].
+    pub struct RenderMode: u32 {
+        const LOCAL_OVERLAY    = 1;
+        const LOCAL_GPU        = 2;
+        const EXTERNAL_OVERLAY = 4;
+        const EXTERNAL_GPU     = 8;
+    }
+}
+
+bitflags! {
+    /// Rendering devices, the value of [`DisplayAttribType::RenderDevice`].
+    pub struct RenderDevice: u32 {
+        const LOCAL    = 1;
+        const EXTERNAL = 2;
+    }
+}
+
+/// PCI vendor and device ID of the GPU, the value of [`DisplayAttribType::PCIID`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PciId {
+    vendor_id: u16,
+    device_id: u16,
+}
+
+impl PciId {
+    pub fn new(vendor_id: u16, device_id: u16) -> Self {
+        Self {
+            vendor_id,
+            device_id,
+        }
+    }
+
+    fn from_raw(value: i32) -> Self {
+        let value = value as u32;
+        Self::new((value >> 16) as u16, value as u16)
+    }
+
+    #[inline]
+    pub fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    #[inline]
+    pub fn device_id(&self) -> u16 {
+        self.device_id
+    }
+}
+
+impl fmt::Display for PciId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.device_id)
+    }
+}
+
+/// Sub-device selection of a multi-tile GPU, the value of [`DisplayAttribType::SubDevice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubDevice {
+    raw: u32,
+}
+
+impl SubDevice {
+    fn from_raw(value: i32) -> Self {
+        Self { raw: value as u32 }
+    }
+
+    /// Returns the index of the sub-device that is currently in use.
+    #[inline]
+    pub fn current(&self) -> u32 {
+        self.raw & 0xf
+    }
+
+    /// Returns the number of sub-devices.
+    #[inline]
+    pub fn count(&self) -> u32 {
+        (self.raw >> 4) & 0xf
+    }
+
+    /// Returns a bit mask of the available sub-devices.
+    #[inline]
+    pub fn available(&self) -> u16 {
+        (self.raw >> 16) as u16
+    }
+
+    /// Returns whether the sub-device with index `index` is available.
+    pub fn is_available(&self, index: u32) -> bool {
+        index < 16 && self.available() & (1 << index) != 0
+    }
+
+    fn with_current(self, index: u32) -> Self {
+        Self {
+            raw: (self.raw & !0xf) | index,
+        }
+    }
+}
+
+bitflags! {
+    /// Optional parts of the libva API.
+    ///
+    /// These correspond to functions that were added in later libva versions. If the libva
+    /// library (or [`Backend`]) in use doesn't provide them, the corresponding feature is absent
+    /// and methods that need it return an error for which [`Error::missing_function`] returns
+    /// the missing function's name.
+    #[derive(Default)]
+    pub struct LibvaFeatures: u32 {
+        /// `vaSyncSurface2`, which supports waiting for a surface with a timeout.
+        const SYNC_SURFACE2            = 1 << 0;
+        /// `vaSyncBuffer`, which waits for an operation on a buffer to finish.
+        const SYNC_BUFFER              = 1 << 1;
+        /// `vaCreateBuffer2`, which creates a buffer for 2D data.
+        const CREATE_BUFFER2           = 1 << 2;
+        /// `vaQueryVideoProcPipelineCaps`.
+        const VIDEO_PROC_PIPELINE_CAPS = 1 << 3;
+        /// `vaQueryProcessingRate`.
+        const PROCESSING_RATE          = 1 << 4;
+        /// `vaCreateMFContext`, `vaMFAddContext`, `vaMFReleaseContext` and `vaMFSubmit`, which
+        /// allow submitting the pictures of several contexts at once.
+        const MULTI_FRAME              = 1 << 5;
+        /// `vaExportSurfaceHandle`.
+        const EXPORT_SURFACE_HANDLE    = 1 << 6;
+    }
+}
+
 /// List of OS APIs that may be used to obtain a libva [`Display`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
@@ -130,12 +297,21 @@ pub enum DisplayApi {
     Xlib,
     Wayland,
     Drm,
+    /// A custom [`Backend`] that doesn't use any native display API.
+    Custom,
 }
 
 /// Owns a VADisplay and destroys it on drop.
 pub(crate) struct DisplayOwner {
     pub(crate) raw: VADisplay,
-    pub(crate) libva: &'static libva,
+    pub(crate) libva: Box<dyn Backend>,
+    pub(crate) features: LibvaFeatures,
+    /// Passed to libva as the `user_context` of the message callbacks, so it has to stay alive
+    /// until the display is terminated.
+    messages: Box<MessageContext>,
+    /// The pending operations of [`SurfaceSync`][crate::surface::SurfaceSync] and
+    /// [`BufferSync`][crate::buffer::BufferSync] futures.
+    pub(crate) waits: Arc<WaitQueue>,
     #[allow(dead_code)]
     display_handle_owner: Option<Box<dyn HasRawDisplayHandle>>,
 }
@@ -152,10 +328,23 @@ impl fmt::Debug for DisplayOwner {
     }
 }
 
+impl DisplayOwner {
+    /// Returns an error if `feature` isn't supported by the libva implementation.
+    ///
+    /// `function` is the name of the libva function that was going to be called.
+    pub(crate) fn require(&self, feature: LibvaFeatures, function: &'static str) -> Result<()> {
+        if self.features.contains(feature) {
+            Ok(())
+        } else {
+            Err(Error::from(Repr::MissingFunction(function)))
+        }
+    }
+}
+
 impl Drop for DisplayOwner {
     fn drop(&mut self) {
         unsafe {
-            check_log(self.libva.vaTerminate(self.raw), "vaTerminate call in drop");
+            check_log(self.libva.vaTerminate(self.raw), "vaTerminate", &[]);
         }
     }
 }
@@ -182,7 +371,7 @@ impl Display {
     /// This function takes ownership of `handle` to ensure that the native display handle isn't
     /// closed before the VA-API [`Display`] is dropped.
     pub fn new<H: HasRawDisplayHandle + 'static>(handle: H) -> Result<Self> {
-        Self::new_impl(handle.raw_display_handle(), Some(Box::new(handle)))
+        DisplayBuilder::new().open(handle)
     }
 
     /// Opens a VA-API display from a raw, native display handle with unmanaged lifetime.
@@ -193,12 +382,34 @@ impl Display {
     /// valid until the last VA-API object created from this [`Display`] (including the [`Display`]
     /// itself) has been destroyed.
     pub unsafe fn new_unmanaged<H: HasRawDisplayHandle>(handle: &H) -> Result<Self> {
-        Self::new_impl(handle.raw_display_handle(), None)
+        DisplayBuilder::new().open_unmanaged(handle)
+    }
+
+    /// Opens a headless VA-API display using the DRM device at `path` (typically a render node
+    /// like `/dev/dri/renderD128`).
+    ///
+    /// The device file is kept open for as long as the [`Display`] or any object created from it
+    /// is alive.
+    pub fn open_drm(path: impl AsRef<Path>) -> Result<Self> {
+        DisplayBuilder::new().open_drm(path)
+    }
+
+    /// Opens a VA-API display without requiring a display handle from the application.
+    ///
+    /// See [`DisplayBuilder::open_default`] for how the display is chosen.
+    pub fn open_default() -> Result<Self> {
+        DisplayBuilder::new().open_default()
+    }
+
+    /// Returns a [`DisplayBuilder`] for configuring how the [`Display`] is initialized.
+    pub fn builder() -> DisplayBuilder {
+        DisplayBuilder::new()
     }
 
     fn new_impl(
         handle: RawDisplayHandle,
         display_handle_owner: Option<Box<dyn HasRawDisplayHandle>>,
+        builder: DisplayBuilder,
     ) -> Result<Self> {
         unsafe {
             let raw: VADisplay;
@@ -236,29 +447,115 @@ impl Display {
                 )));
             }
 
-            libva.vaSetErrorCallback(raw, error_callback, ptr::null_mut());
-            libva.vaSetInfoCallback(raw, info_callback, ptr::null_mut());
+            Self::init(raw, api, Box::new(libva), display_handle_owner, builder)
+        }
+    }
+
+    /// Opens a [`Display`] that is driven by a custom [`Backend`] instead of the system's libva.
+    ///
+    /// This can be used to run the library against an in-process implementation such as
+    /// [`FakeBackend`][crate::backend::FakeBackend].
+    pub fn with_backend<B: Backend>(backend: B) -> Result<Self> {
+        DisplayBuilder::new().open_backend(backend)
+    }
+
+    fn with_backend_impl(backend: Box<dyn Backend>, builder: DisplayBuilder) -> Result<Self> {
+        // Custom backends aren't tied to a native display, but libva functions still take a handle,
+        // so give them a dummy one.
+        let raw = NonNull::<c_void>::dangling().as_ptr();
+        unsafe { Self::init(raw, DisplayApi::Custom, backend, None, builder) }
+    }
+
+    unsafe fn init(
+        raw: VADisplay,
+        api: DisplayApi,
+        libva: Box<dyn Backend>,
+        display_handle_owner: Option<Box<dyn HasRawDisplayHandle>>,
+        builder: DisplayBuilder,
+    ) -> Result<Self> {
+        let DisplayBuilder {
+            driver_name,
+            handlers,
+            min_version,
+            trace,
+            recording,
+        } = builder;
+        let libva = Interceptor::wrap(libva, trace, recording);
+
+        // The context is boxed so that its address doesn't change when moving it into the
+        // `DisplayOwner`.
+        let messages = Box::new(MessageContext {
+            display: DisplayId::next(),
+            handlers: RwLock::new(handlers),
+            errors: AtomicU64::new(0),
+            infos: AtomicU64::new(0),
+        });
+        let ctx = &*messages as *const MessageContext as *mut c_void;
+        libva.vaSetErrorCallback(raw, error_callback, ctx);
+        libva.vaSetInfoCallback(raw, info_callback, ctx);
+
+        let init = || {
+            if let Some(name) = driver_name {
+                check(
+                    libva.vaSetDriverName(raw, name.as_ptr() as *mut c_char),
+                    "vaSetDriverName",
+                    &[],
+                )?;
+            }
 
             let mut major = 0;
             let mut minor = 0;
-            check(libva.vaInitialize(raw, &mut major, &mut minor))?;
-
-            log::info!("initialized libva {major}.{minor}");
-
-            let this = Self {
-                d: Arc::new(DisplayOwner {
-                    raw,
-                    libva,
-                    display_handle_owner,
-                }),
-                api,
-                major: major as _,
-                minor: minor as _,
-            };
-            let vendor = this.query_vendor_string()?;
-            log::info!("VA-API vendor: {vendor}");
-            Ok(this)
+            check(
+                libva.vaInitialize(raw, &mut major, &mut minor),
+                "vaInitialize",
+                &[],
+            )?;
+            Ok((major, minor))
+        };
+        let (major, minor) = match init() {
+            Ok(version) => version,
+            Err(e) => {
+                // `messages` is about to be freed, so make sure libva doesn't keep a pointer to it.
+                libva.vaSetErrorCallback(raw, error_callback, ptr::null_mut());
+                libva.vaSetInfoCallback(raw, info_callback, ptr::null_mut());
+                return Err(e);
+            }
+        };
+
+        log::info!("initialized libva {major}.{minor}");
+
+        let features = libva.features();
+        if features != LibvaFeatures::all() {
+            log::info!(
+                "libva is missing optional features: {:?}",
+                LibvaFeatures::all() - features
+            );
         }
+
+        let this = Self {
+            d: Arc::new(DisplayOwner {
+                raw,
+                libva,
+                features,
+                messages,
+                waits: Arc::default(),
+                display_handle_owner,
+            }),
+            api,
+            major: major as _,
+            minor: minor as _,
+        };
+        if let Some((req_major, req_minor)) = min_version {
+            if (this.major, this.minor) < (req_major, req_minor) {
+                return Err(Error::from(format!(
+                    "VA-API version {major}.{minor} is older than the required version \
+                     {req_major}.{req_minor}"
+                )));
+            }
+        }
+        let vendor = this.query_vendor_string()?;
+        log::info!("VA-API vendor: {vendor}");
+        Ok(this)
     }
 
     /// Returns the major part of the libva version.
@@ -273,6 +570,45 @@ impl Display {
         self.minor
     }
 
+    /// Returns the optional parts of the libva API that are available.
+    #[inline]
+    pub fn features(&self) -> LibvaFeatures {
+        self.d.features
+    }
+
+    /// Returns the process-wide unique ID of this [`Display`].
+    ///
+    /// All [`Message`]s emitted by this display carry this ID.
+    #[inline]
+    pub fn id(&self) -> DisplayId {
+        self.d.messages.display
+    }
+
+    /// Installs a function that receives all messages libva emits for this [`Display`].
+    ///
+    /// The sink replaces the handlers set by [`DisplayBuilder::error_handler`] and
+    /// [`DisplayBuilder::info_handler`], as well as the default forwarding to the `log` crate. It
+    /// may be invoked from any thread that uses this [`Display`] or objects created from it.
+    pub fn set_message_sink(&self, sink: impl Fn(&Message<'_>) + Send + Sync + 'static) {
+        self.d.messages.handlers_mut().sink = Some(Arc::new(sink));
+    }
+
+    /// Removes the message sink installed by [`Display::set_message_sink`].
+    pub fn clear_message_sink(&self) {
+        self.d.messages.handlers_mut().sink = None;
+    }
+
+    /// Returns how many messages libva has emitted for this [`Display`] so far.
+    ///
+    /// Messages are counted regardless of where they're forwarded to. An increasing error count
+    /// can be used as an indicator of driver or hardware problems.
+    pub fn message_counts(&self) -> MessageCounts {
+        MessageCounts {
+            errors: self.d.messages.errors.load(Ordering::Relaxed),
+            infos: self.d.messages.infos.load(Ordering::Relaxed),
+        }
+    }
+
     /// Returns the [`DisplayApi`] that this [`Display`] is using.
     #[inline]
     pub fn display_api(&self) -> DisplayApi {
@@ -282,8 +618,11 @@ impl Display {
     /// Queries a string representing the vendor of the libva implementation.
     pub fn query_vendor_string(&self) -> Result<&str> {
         unsafe {
-            let cstr = CStr::from_ptr(self.d.libva.vaQueryVendorString(self.d.raw));
-            cstr.to_str().map_err(Error::from)
+            let ptr = self.d.libva.vaQueryVendorString(self.d.raw);
+            if ptr.is_null() {
+                return Err(Error::from("vaQueryVendorString returned NULL"));
+            }
+            CStr::from_ptr(ptr).to_str().map_err(Error::from)
         }
     }
 
@@ -297,6 +636,8 @@ impl Display {
                 self.d
                     .libva
                     .vaQueryConfigProfiles(self.d.raw, profiles.as_mut_ptr(), &mut num),
+                "vaQueryConfigProfiles",
+                &[],
             )?;
         }
         profiles.truncate(num as usize);
@@ -309,12 +650,16 @@ impl Display {
         let mut entrypoints = vec![Entrypoint(0); max];
         let mut num = 0;
         unsafe {
-            check(self.d.libva.vaQueryConfigEntrypoints(
-                self.d.raw,
-                profile,
-                entrypoints.as_mut_ptr(),
-                &mut num,
-            ))?;
+            check(
+                self.d.libva.vaQueryConfigEntrypoints(
+                    self.d.raw,
+                    profile,
+                    entrypoints.as_mut_ptr(),
+                    &mut num,
+                ),
+                "vaQueryConfigEntrypoints",
+                &[],
+            )?;
         }
         entrypoints.truncate(num as usize);
         Ok(Entrypoints { vec: entrypoints })
@@ -330,6 +675,8 @@ impl Display {
                 self.d
                     .libva
                     .vaQueryImageFormats(self.d.raw, formats.as_mut_ptr(), &mut num),
+                "vaQueryImageFormats",
+                &[],
             )?;
             formats.truncate(num as usize);
             Ok(ImageFormats { vec: formats })
@@ -342,12 +689,16 @@ impl Display {
             let mut formats = vec![ImageFormat::zeroed(); max];
             let mut flags: Vec<SubpictureFlags> = vec![SubpictureFlags::empty(); max];
             let mut num = 0;
-            check(self.d.libva.vaQuerySubpictureFormats(
-                self.d.raw,
-                formats.as_mut_ptr(),
-                flags.as_mut_ptr().cast(),
-                &mut num,
-            ))?;
+            check(
+                self.d.libva.vaQuerySubpictureFormats(
+                    self.d.raw,
+                    formats.as_mut_ptr(),
+                    flags.as_mut_ptr().cast(),
+                    &mut num,
+                ),
+                "vaQuerySubpictureFormats",
+                &[],
+            )?;
             formats.truncate(num as usize);
             flags.truncate(num as usize);
 
@@ -360,16 +711,23 @@ impl Display {
         let mut attribs = vec![DisplayAttribute::zeroed(); max];
         let mut num = 0;
         unsafe {
-            check(self.d.libva.vaQueryDisplayAttributes(
-                self.d.raw,
-                attribs.as_mut_ptr(),
-                &mut num,
-            ))?;
+            check(
+                self.d
+                    .libva
+                    .vaQueryDisplayAttributes(self.d.raw, attribs.as_mut_ptr(), &mut num),
+                "vaQueryDisplayAttributes",
+                &[],
+            )?;
         }
         attribs.truncate(num as usize);
         Ok(DisplayAttributes { vec: attribs })
     }
 
+    /// Sets the name of the driver libva should load.
+    ///
+    /// Note that libva only uses the driver name when the display is initialized, which already
+    /// happened when this is called. Use [`DisplayBuilder::driver_name`] to select the driver
+    /// instead.
     pub fn set_driver_name(&mut self, name: &str) -> Result<()> {
         let mut buf;
         let mut name = name.as_bytes();
@@ -386,48 +744,510 @@ impl Display {
                 self.d
                     .libva
                     .vaSetDriverName(self.d.raw, name.as_ptr() as *mut c_char),
+                "vaSetDriverName",
+                &[],
             )
         }
     }
 
+    /// Sets the values of several display attributes at once.
+    ///
+    /// Every attribute is checked against the flags and range reported by
+    /// [`Display::query_display_attributes`] first. Nothing is written if any of them is invalid.
     pub fn set_attributes(&mut self, attr_list: &mut [DisplayAttribute]) -> Result<()> {
+        let supported = self.query_display_attributes()?;
+        for attrib in &*attr_list {
+            supported.validate_write(attrib)?;
+        }
         unsafe {
-            check(self.d.libva.vaSetDisplayAttributes(
-                self.d.raw,
-                attr_list.as_mut_ptr(),
-                attr_list.len().try_into().unwrap(),
-            ))?;
+            check(
+                self.d.libva.vaSetDisplayAttributes(
+                    self.d.raw,
+                    attr_list.as_mut_ptr(),
+                    attr_list.len().try_into().unwrap(),
+                ),
+                "vaSetDisplayAttributes",
+                &[],
+            )?;
             Ok(())
         }
     }
+
+    /// Reads the current value of the display attribute `ty`.
+    ///
+    /// The returned attribute also contains the range and flags reported by the driver.
+    pub fn display_attribute(&self, ty: DisplayAttribType) -> Result<DisplayAttribute> {
+        let supported = self.query_display_attributes()?;
+        let Some(&attrib) = supported.get(ty) else {
+            return Err(Error::from(Repr::UnsupportedDisplayAttrib(ty)));
+        };
+        if !attrib.flags.contains(DisplayAttribFlags::GETTABLE) {
+            return Err(Error::invalid_input(format!(
+                "display attribute {ty:?} is write-only"
+            )));
+        }
+
+        let mut current = attrib;
+        unsafe {
+            check(
+                self.d
+                    .libva
+                    .vaGetDisplayAttributes(self.d.raw, &mut current, 1),
+                "vaGetDisplayAttributes",
+                &[],
+            )?;
+        }
+        Ok(DisplayAttribute {
+            value: current.value,
+            ..attrib
+        })
+    }
+
+    /// Sets the value of the display attribute `ty`, after checking it against the flags and
+    /// range reported by the driver.
+    pub fn set_display_attribute(&mut self, ty: DisplayAttribType, value: i32) -> Result<()> {
+        self.set_attributes(&mut [DisplayAttribute::new(ty, value)])
+    }
+
+    pub fn brightness(&self) -> Result<i32> {
+        Ok(self.display_attribute(DisplayAttribType::Brightness)?.value)
+    }
+
+    pub fn set_brightness(&mut self, value: i32) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::Brightness, value)
+    }
+
+    pub fn contrast(&self) -> Result<i32> {
+        Ok(self.display_attribute(DisplayAttribType::Contrast)?.value)
+    }
+
+    pub fn set_contrast(&mut self, value: i32) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::Contrast, value)
+    }
+
+    pub fn hue(&self) -> Result<i32> {
+        Ok(self.display_attribute(DisplayAttribType::Hue)?.value)
+    }
+
+    pub fn set_hue(&mut self, value: i32) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::Hue, value)
+    }
+
+    pub fn saturation(&self) -> Result<i32> {
+        Ok(self.display_attribute(DisplayAttribType::Saturation)?.value)
+    }
+
+    pub fn set_saturation(&mut self, value: i32) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::Saturation, value)
+    }
+
+    /// Returns the background color as `0xRRGGBB`.
+    pub fn background_color(&self) -> Result<u32> {
+        Ok(self
+            .display_attribute(DisplayAttribType::BackgroundColor)?
+            .value as u32)
+    }
+
+    /// Sets the background color, given as `0xRRGGBB`.
+    pub fn set_background_color(&mut self, rgb: u32) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::BackgroundColor, rgb as i32)
+    }
+
+    pub fn rotation(&self) -> Result<Rotation> {
+        Ok(Rotation(
+            self.display_attribute(DisplayAttribType::Rotation)?.value as u32,
+        ))
+    }
+
+    pub fn set_rotation(&mut self, rotation: Rotation) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::Rotation, rotation.0 as i32)
+    }
+
+    pub fn render_mode(&self) -> Result<RenderMode> {
+        let value = self.display_attribute(DisplayAttribType::RenderMode)?.value;
+        Ok(RenderMode::from_bits_truncate(value as u32))
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::RenderMode, mode.bits() as i32)
+    }
+
+    pub fn render_device(&self) -> Result<RenderDevice> {
+        let value = self
+            .display_attribute(DisplayAttribType::RenderDevice)?
+            .value;
+        Ok(RenderDevice::from_bits_truncate(value as u32))
+    }
+
+    pub fn set_render_device(&mut self, device: RenderDevice) -> Result<()> {
+        self.set_display_attribute(DisplayAttribType::RenderDevice, device.bits() as i32)
+    }
+
+    /// Returns the PCI vendor and device ID of the GPU backing this display.
+    pub fn pci_id(&self) -> Result<PciId> {
+        Ok(PciId::from_raw(
+            self.display_attribute(DisplayAttribType::PCIID)?.value,
+        ))
+    }
+
+    /// Returns the sub-device configuration of a multi-tile GPU.
+    pub fn sub_device(&self) -> Result<SubDevice> {
+        Ok(SubDevice::from_raw(
+            self.display_attribute(DisplayAttribType::SubDevice)?.value,
+        ))
+    }
+
+    /// Selects the sub-device with index `index` for subsequent operations.
+    ///
+    /// Returns an error if the sub-device isn't available.
+    pub fn set_sub_device(&mut self, index: u32) -> Result<()> {
+        let sub_device = self.sub_device()?;
+        if !sub_device.is_available(index) {
+            return Err(Error::invalid_input(format!(
+                "sub-device {index} is not available (available mask: {:#06x})",
+                sub_device.available()
+            )));
+        }
+        self.set_display_attribute(
+            DisplayAttribType::SubDevice,
+            sub_device.with_current(index).raw as i32,
+        )
+    }
 }
 
-extern "C" fn error_callback(_ctx: *mut c_void, message: *const c_char) {
-    catch_unwind(|| unsafe {
-        let cstr = CStr::from_ptr(message);
-        match cstr.to_str() {
-            Ok(s) => {
-                log::error!("libva: {}", s.trim());
+/// Configures and opens a [`Display`].
+///
+/// Some options have to be applied to libva after obtaining the native `VADisplay`, but before
+/// initializing it with `vaInitialize`. This builder takes care of doing that.
+///
+/// # Example
+///
+/// ```no_run
+/// use v_ayylmao::display::Display;
+/// use winit::event_loop::EventLoop;
+///
+/// let ev = EventLoop::new();
+/// let display = unsafe {
+///     Display::builder()
+///         .driver_name("iHD")
+///         .error_handler(|msg| eprintln!("libva error: {msg}"))
+///         .min_version(1, 15)
+///         .open_unmanaged(&*ev)?
+/// };
+/// # Ok::<_, v_ayylmao::error::Error>(())
+/// ```
+#[derive(Default, Clone)]
+pub struct DisplayBuilder {
+    driver_name: Option<CString>,
+    handlers: MessageHandlers,
+    min_version: Option<(u32, u32)>,
+    trace: Option<Trace>,
+    recording: Option<Recording>,
+}
+
+impl DisplayBuilder {
+    /// Creates a [`DisplayBuilder`] with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forces libva to load the driver with the given name (eg. `iHD`, `i965` or `radeonsi`)
+    /// instead of auto-detecting it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains a NUL byte.
+    pub fn driver_name(mut self, name: &str) -> Self {
+        self.driver_name = Some(CString::new(name).expect("driver name contains a NUL byte"));
+        self
+    }
+
+    /// Sets a function that is invoked with every error message emitted by libva or its driver.
+    ///
+    /// By default, error messages are logged via the `log` crate.
+    pub fn error_handler(mut self, handler: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.handlers.error = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets a function that is invoked with every informational message emitted by libva or its
+    /// driver.
+    ///
+    /// By default, info messages are logged via the `log` crate.
+    pub fn info_handler(mut self, handler: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.handlers.info = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets a function that receives all messages emitted by libva or its driver.
+    ///
+    /// This takes precedence over [`DisplayBuilder::error_handler`] and
+    /// [`DisplayBuilder::info_handler`]. See [`Display::set_message_sink`].
+    pub fn message_sink(mut self, sink: impl Fn(&Message<'_>) + Send + Sync + 'static) -> Self {
+        self.handlers.sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Makes opening the [`Display`] fail if the VA-API version reported by `vaInitialize` is lower
+    /// than `major.minor`.
+    pub fn min_version(mut self, major: u32, minor: u32) -> Self {
+        self.min_version = Some((major, minor));
+        self
+    }
+
+    /// Records all libva calls made through the [`Display`] in `trace`.
+    ///
+    /// See the [`trace`][crate::trace] module for details.
+    pub fn trace(mut self, trace: Trace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Records the objects and submissions made through the [`Display`] in `recording`, so that
+    /// they can be saved and replayed later.
+    ///
+    /// See the [`replay`][crate::replay] module for details.
+    pub fn record(mut self, recording: Recording) -> Self {
+        self.recording = Some(recording);
+        self
+    }
+
+    /// Opens a VA-API display from an owned display handle.
+    ///
+    /// See [`Display::new`].
+    pub fn open<H: HasRawDisplayHandle + 'static>(self, handle: H) -> Result<Display> {
+        Display::new_impl(handle.raw_display_handle(), Some(Box::new(handle)), self)
+    }
+
+    /// Opens a VA-API display from a raw, native display handle with unmanaged lifetime.
+    ///
+    /// See [`Display::new_unmanaged`].
+    ///
+    /// # Safety
+    ///
+    /// It is the user's responsibility to ensure that the native display handle `handle` remains
+    /// valid until the last VA-API object created from the [`Display`] (including the [`Display`]
+    /// itself) has been destroyed.
+    pub unsafe fn open_unmanaged<H: HasRawDisplayHandle>(self, handle: &H) -> Result<Display> {
+        Display::new_impl(handle.raw_display_handle(), None, self)
+    }
+
+    /// Opens a headless VA-API display using the DRM device at `path`.
+    ///
+    /// See [`Display::open_drm`].
+    pub fn open_drm(self, path: impl AsRef<Path>) -> Result<Display> {
+        self.open(DrmDevice::open(path.as_ref())?)
+    }
+
+    /// Opens a VA-API display without requiring a display handle from the application.
+    ///
+    /// This tries the following, in order, and returns the first [`Display`] that could be
+    /// initialized:
+    ///
+    /// - All DRM render nodes, as returned by [`RenderNode::enumerate`]. If they can't be listed,
+    ///   the display servers are tried anyway.
+    /// - The Wayland compositor, if `WAYLAND_DISPLAY` is set.
+    /// - The X server, if `DISPLAY` is set.
+    ///
+    /// Any connection to a display server is owned by the returned [`Display`].
+    pub fn open_default(self) -> Result<Display> {
+        let mut last_error = None;
+        let mut attempt = |what: &dyn fmt::Display, result: Result<Display>| match result {
+            Ok(display) => Some(display),
+            Err(e) => {
+                log::debug!("failed to open VA-API display using {what}: {e}");
+                last_error = Some(e);
+                None
             }
+        };
+
+        match RenderNode::enumerate() {
+            Ok(nodes) => {
+                for node in nodes {
+                    let result = node.open_with(self.clone());
+                    if let Some(display) = attempt(&node.path().display(), result) {
+                        return Ok(display);
+                    }
+                }
+            }
+            // The display servers may still be reachable if the render nodes can't be listed.
             Err(e) => {
-                log::error!("failed to decode libva error: {e}");
+                attempt(&"DRM render nodes", Err(e));
+            }
+        }
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            let result = WaylandConnection::connect().and_then(|conn| self.clone().open(conn));
+            if let Some(display) = attempt(&"Wayland", result) {
+                return Ok(display);
             }
         }
-    })
-    .ok();
+        if env::var_os("DISPLAY").is_some() {
+            let result = XlibConnection::connect().and_then(|conn| self.clone().open(conn));
+            if let Some(display) = attempt(&"X11", result) {
+                return Ok(display);
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            Error::from(
+                "no VA-API display available: there are no DRM render nodes, and neither \
+                 `WAYLAND_DISPLAY` nor `DISPLAY` is set",
+            )
+        }))
+    }
+
+    /// Opens a [`Display`] that is driven by a custom [`Backend`].
+    ///
+    /// See [`Display::with_backend`].
+    pub fn open_backend<B: Backend>(self, backend: B) -> Result<Display> {
+        Display::with_backend_impl(Box::new(backend), self)
+    }
 }
 
-extern "C" fn info_callback(_ctx: *mut c_void, message: *const c_char) {
-    catch_unwind(|| unsafe {
-        let cstr = CStr::from_ptr(message);
-        match cstr.to_str() {
-            Ok(s) => {
-                log::info!("libva: {}", s.trim());
-            }
-            Err(e) => {
-                log::error!("failed to decode libva info message: {e}");
+type MessageHandler = Arc<dyn Fn(&str) + Send + Sync>;
+type MessageSink = Arc<dyn Fn(&Message<'_>) + Send + Sync>;
+
+/// User-provided handlers for libva's log messages.
+#[derive(Default, Clone)]
+struct MessageHandlers {
+    error: Option<MessageHandler>,
+    info: Option<MessageHandler>,
+    sink: Option<MessageSink>,
+}
+
+/// Uniquely identifies a [`Display`] within the process.
+///
+/// IDs are assigned in the order the [`Display`]s are created, and are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DisplayId(u64);
+
+impl DisplayId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the numeric value of this ID.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for DisplayId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Severity of a [`Message`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageLevel {
+    Error,
+    Info,
+}
+
+/// A log message emitted by libva or its driver.
+#[derive(Debug, Clone, Copy)]
+pub struct Message<'a> {
+    display: DisplayId,
+    level: MessageLevel,
+    text: &'a str,
+}
+
+impl<'a> Message<'a> {
+    /// Returns the ID of the [`Display`] the message belongs to.
+    pub fn display(&self) -> DisplayId {
+        self.display
+    }
+
+    pub fn level(&self) -> MessageLevel {
+        self.level
+    }
+
+    /// Returns the message text, without trailing whitespace.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+}
+
+/// Number of messages a [`Display`] has received from libva, by [`MessageLevel`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub errors: u64,
+    pub infos: u64,
+}
+
+/// Per-display state of the message callbacks, passed to libva as their `user_context`.
+struct MessageContext {
+    display: DisplayId,
+    handlers: RwLock<MessageHandlers>,
+    errors: AtomicU64,
+    infos: AtomicU64,
+}
+
+impl MessageContext {
+    fn handlers(&self) -> RwLockReadGuard<'_, MessageHandlers> {
+        self.handlers.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn handlers_mut(&self) -> RwLockWriteGuard<'_, MessageHandlers> {
+        self.handlers.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn dispatch(&self, level: MessageLevel, text: &str) {
+        let counter = match level {
+            MessageLevel::Error => &self.errors,
+            MessageLevel::Info => &self.infos,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        // Clone the handlers out of the lock, so that they can replace themselves.
+        let handlers = self.handlers().clone();
+        let handler = match level {
+            MessageLevel::Error => handlers.error,
+            MessageLevel::Info => handlers.info,
+        };
+        if let Some(sink) = handlers.sink {
+            sink(&Message {
+                display: self.display,
+                level,
+                text,
+            });
+        } else if let Some(handler) = handler {
+            handler(text);
+        } else {
+            match level {
+                MessageLevel::Error => log::error!("libva {}: {}", self.display, text),
+                MessageLevel::Info => log::info!("libva {}: {}", self.display, text),
             }
         }
-    })
-    .ok();
+    }
+}
+
+/// Decodes a message and forwards it to the [`MessageContext`] at `ctx`, or to the `log` crate if
+/// `ctx` is NULL.
+unsafe fn dispatch(ctx: *mut c_void, level: MessageLevel, message: *const c_char) {
+    let cstr = CStr::from_ptr(message);
+    let text = match cstr.to_str() {
+        Ok(s) => s.trim(),
+        Err(e) => {
+            log::error!("failed to decode libva {level:?} message: {e}");
+            return;
+        }
+    };
+    match ctx.cast::<MessageContext>().as_ref() {
+        Some(ctx) => ctx.dispatch(level, text),
+        None => match level {
+            MessageLevel::Error => log::error!("libva: {text}"),
+            MessageLevel::Info => log::info!("libva: {text}"),
+        },
+    }
+}
+
+extern "C" fn error_callback(ctx: *mut c_void, message: *const c_char) {
+    catch_unwind(|| unsafe { dispatch(ctx, MessageLevel::Error, message) }).ok();
+}
+
+extern "C" fn info_callback(ctx: *mut c_void, message: *const c_char) {
+    catch_unwind(|| unsafe { dispatch(ctx, MessageLevel::Info, message) }).ok();
 }
@@ -0,0 +1,130 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    backend::FakeBackend,
+    config::Config,
+    error::{ErrorKind, VAError},
+    Entrypoint, Profile, Rotation,
+};
+
+use super::{Display, DisplayAttribType, Message, MessageCounts, MessageLevel, PciId};
+
+#[test]
+fn message_sink() {
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let sink = {
+        let messages = messages.clone();
+        move |msg: &Message<'_>| {
+            messages
+                .lock()
+                .unwrap()
+                .push((msg.display(), msg.level(), msg.text().to_string()))
+        }
+    };
+
+    let fake_a = FakeBackend::new();
+    let fake_b = FakeBackend::new();
+    let a = Display::builder()
+        .message_sink(sink.clone())
+        .open_backend(fake_a.clone())
+        .unwrap();
+    let b = Display::with_backend(fake_b.clone()).unwrap();
+    b.set_message_sink(sink);
+    assert_ne!(a.id(), b.id());
+
+    fake_a.fail_next("vaCreateConfig", VAError::ERROR_ALLOCATION_FAILED);
+    fake_b.fail_next("vaCreateConfig", VAError::ERROR_HW_BUSY);
+    assert!(Config::new(&a, Profile::JPEGBaseline, Entrypoint::VLD).is_err());
+    assert!(Config::new(&b, Profile::JPEGBaseline, Entrypoint::VLD).is_err());
+
+    assert_eq!(
+        *messages.lock().unwrap(),
+        [
+            (
+                a.id(),
+                MessageLevel::Info,
+                "VA-API version 1.20.0".to_string()
+            ),
+            (
+                a.id(),
+                MessageLevel::Error,
+                "vaCreateConfig failed: ERROR_ALLOCATION_FAILED".to_string()
+            ),
+            (
+                b.id(),
+                MessageLevel::Error,
+                "vaCreateConfig failed: ERROR_HW_BUSY".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        a.message_counts(),
+        MessageCounts {
+            errors: 1,
+            infos: 1
+        }
+    );
+    // `b`'s init message was counted, but logged because the sink wasn't installed yet.
+    assert_eq!(
+        b.message_counts(),
+        MessageCounts {
+            errors: 1,
+            infos: 1
+        }
+    );
+
+    b.clear_message_sink();
+    fake_b.fail_next("vaCreateConfig", VAError::ERROR_HW_BUSY);
+    assert!(Config::new(&b, Profile::JPEGBaseline, Entrypoint::VLD).is_err());
+    assert_eq!(messages.lock().unwrap().len(), 3);
+    assert_eq!(b.message_counts().errors, 2);
+}
+
+#[test]
+fn display_attributes() {
+    let fake = FakeBackend::new();
+    fake.set_pci_id(PciId::new(0x8086, 0x46a6));
+    let mut display = Display::with_backend(fake.clone()).unwrap();
+
+    assert_eq!(display.brightness().unwrap(), 0);
+    display.set_brightness(-50).unwrap();
+    assert_eq!(display.brightness().unwrap(), -50);
+    let attrib = display
+        .display_attribute(DisplayAttribType::Brightness)
+        .unwrap();
+    assert_eq!((attrib.min_value(), attrib.max_value()), (-100, 100));
+
+    let err = display.set_brightness(101).unwrap_err();
+    assert!(err.is_invalid_input());
+    assert_eq!(
+        err.to_string(),
+        "value 101 for display attribute Brightness is outside of the supported range -100..=100"
+    );
+    assert_eq!(display.brightness().unwrap(), -50);
+
+    display.set_rotation(Rotation::R180).unwrap();
+    assert_eq!(display.rotation().unwrap(), Rotation::R180);
+
+    let pci_id = display.pci_id().unwrap();
+    assert_eq!((pci_id.vendor_id(), pci_id.device_id()), (0x8086, 0x46a6));
+    assert_eq!(pci_id.to_string(), "8086:46a6");
+    let err = display
+        .set_display_attribute(DisplayAttribType::PCIID, 0)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "display attribute PCIID is read-only");
+
+    let sub_device = display.sub_device().unwrap();
+    assert_eq!((sub_device.current(), sub_device.count()), (0, 2));
+    display.set_sub_device(1).unwrap();
+    let sub_device = display.sub_device().unwrap();
+    assert_eq!((sub_device.current(), sub_device.count()), (1, 2));
+    assert!(display.set_sub_device(2).unwrap_err().is_invalid_input());
+
+    let err = display.render_mode().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "display attribute RenderMode is not supported by the driver"
+    );
+    assert!(err.is_unsupported());
+    assert_eq!(err.kind(), ErrorKind::UnsupportedConfig);
+}
@@ -7,66 +7,162 @@
 use std::{
     ffi::c_void,
     os::raw::{c_char, c_float, c_int, c_uchar, c_uint},
+    path::{Path, PathBuf},
+    ptr,
+    time::Instant,
 };
 
 use crate::{
     buffer::BufferType,
     config::ConfigAttrib,
-    display::DisplayAttribute,
-    error::VAStatus,
+    display::{DisplayAttribute, LibvaFeatures},
+    error::{VAError, VAStatus},
     image::{ImageFormat, VAImage},
+    intercept::Interceptor,
+    loader::{self, Library},
     raw::*,
     subpicture::SubpictureFlags,
     surface::SurfaceStatus,
     surface::{ExportSurfaceFlags, RTFormat, SurfaceAttrib, SurfaceAttribMemoryType},
+    trace::{TraceArg, TraceRet},
     vpp::{FilterType, RawProcPipelineCaps},
     Entrypoint, Profile,
 };
 
 use once_cell::sync::OnceCell;
 
+/// Provides the value a [`Backend`] method returns when the backend does not implement it.
+trait Unsupported {
+    fn unsupported() -> Self;
+}
+
+impl Unsupported for () {
+    fn unsupported() -> Self {}
+}
+
+impl Unsupported for VAStatus {
+    fn unsupported() -> Self {
+        VAError::ERROR_UNIMPLEMENTED.into()
+    }
+}
+
+impl Unsupported for c_int {
+    fn unsupported() -> Self {
+        0
+    }
+}
+
+impl<T> Unsupported for *const T {
+    fn unsupported() -> Self {
+        ptr::null()
+    }
+}
+
+impl<T> Unsupported for *mut T {
+    fn unsupported() -> Self {
+        ptr::null_mut()
+    }
+}
+
+impl<T> Unsupported for Option<T> {
+    fn unsupported() -> Self {
+        None
+    }
+}
+
 /// `dylib! {}`
+///
+/// Functions marked `optional(FEATURE)` are allowed to be missing from the library. Calling them
+/// will then return [`VAError::ERROR_UNIMPLEMENTED`], and `FEATURE` will be absent from the
+/// [`LibvaFeatures`] returned by `features()`.
 macro_rules! dylib {
     (
-        pub struct $strukt:ident;
+        pub struct $strukt:ident: $library:path;
 
         $(
             fn $func:ident( $( $name:ident : $t:ty ),* $(,)? ) $( -> $ret:ty )?;
         )+
+
+        $(
+            optional($feature:ident) fn $ofunc:ident( $( $oname:ident : $ot:ty ),* $(,)? ) -> VAStatus;
+        )*
     ) => {
         $(
             pub(crate) type $func = unsafe extern "C" fn( $( $name : $t ),* ) $( -> $ret )?;
         )+
+        $(
+            pub(crate) type $ofunc = unsafe extern "C" fn( $( $oname : $ot ),* ) -> VAStatus;
+        )*
 
         pub struct $strukt {
+            path: PathBuf,
             $(
                 $func: $func,
             )+
+            $(
+                $ofunc: Option<$ofunc>,
+            )*
         }
 
         #[allow(unused)]
         impl $strukt {
             fn load() -> Result<Self, libloading::Error> {
                 unsafe {
-                    let libname = concat!(stringify!($strukt), ".so").replace('_', "-");
-                    let lib = libloading::Library::new(&libname)?;
+                    let mut this = loader::open($library, |lib| {
+                        Ok(Self {
+                            path: PathBuf::new(),
+                            $(
+                                $func: *lib.get(concat!(stringify!($func), "\0").as_bytes())?,
+                            )+
+                            $(
+                                $ofunc: lib.get(concat!(stringify!($ofunc), "\0").as_bytes()).ok().map(|sym| *sym),
+                            )*
+                        })
+                    })?;
 
-                    let this = Self {
-                        $(
-                            $func: *lib.get(concat!(stringify!($func), "\0").as_bytes())?,
-                        )+
-                    };
+                    let addrs = [$( this.$func as *const c_void ),+];
+                    this.path = loader::object_path(addrs[0])
+                        .unwrap_or_else(|| PathBuf::from($library.file_name()));
+                    log::debug!("loaded {} from {}", $library.file_name(), this.path.display());
 
-                    // Ensure the library is never unloaded.
-                    std::mem::forget(lib);
+                    $(
+                        if this.$ofunc.is_none() {
+                            log::debug!("optional function {} is not available", stringify!($ofunc));
+                        }
+                    )*
 
                     Ok(this)
                 }
             }
 
-            pub fn get() -> Result<&'static Self, libloading::Error> {
+            fn cell() -> &'static OnceCell<Self> {
                 static CELL: OnceCell<$strukt> = OnceCell::new();
-                CELL.get_or_try_init(Self::load)
+                &CELL
+            }
+
+            pub fn get() -> Result<&'static Self, libloading::Error> {
+                Self::cell().get_or_try_init(Self::load)
+            }
+
+            /// Returns the library if it has already been loaded.
+            pub fn loaded() -> Option<&'static Self> {
+                Self::cell().get()
+            }
+
+            /// Returns the path of the file the library was loaded from.
+            pub fn path(&self) -> &Path {
+                &self.path
+            }
+
+            /// Returns the set of optional features whose functions are all available.
+            pub fn features(&self) -> LibvaFeatures {
+                let mut features = LibvaFeatures::empty() $( | LibvaFeatures::$feature )*;
+                $(
+                    if self.$ofunc.is_none() {
+                        features.remove(LibvaFeatures::$feature);
+                    }
+                )*
+                features
             }
 
             $(
@@ -74,12 +170,136 @@ macro_rules! dylib {
                     (self.$func)($($name),*)
                 }
             )+
+            $(
+                #[allow(clippy::too_many_arguments)]
+                pub(crate) unsafe fn $ofunc( &self, $( $oname : $ot ),* ) -> VAStatus {
+                    match self.$ofunc {
+                        Some(f) => f($($oname),*),
+                        None => VAError::ERROR_UNIMPLEMENTED.into(),
+                    }
+                }
+            )*
         }
     };
 }
 
-dylib! {
-    pub struct libva;
+/// `backend! {}`
+///
+/// Defines the `libva` function table via [`dylib!`], as well as a trait with one method per
+/// function, which is implemented by the dynamically loaded library and by the [`Interceptor`].
+macro_rules! backend {
+    (
+        $( #[$attrs:meta] )*
+        pub unsafe trait $trait:ident for $strukt:ident: $library:path;
+
+        $(
+            fn $func:ident( $( $name:ident : $t:ty ),* $(,)? ) $( -> $ret:ty )?;
+        )+
+
+        $(
+            optional($feature:ident) fn $ofunc:ident( $( $oname:ident : $ot:ty ),* $(,)? ) -> VAStatus;
+        )*
+    ) => {
+        dylib! {
+            pub struct $strukt: $library;
+
+            $(
+                fn $func( $( $name : $t ),* ) $( -> $ret )?;
+            )+
+
+            $(
+                optional($feature) fn $ofunc( $( $oname : $ot ),* ) -> VAStatus;
+            )*
+        }
+
+        $( #[$attrs] )*
+        #[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+        pub unsafe trait $trait: Send + Sync + 'static {
+            /// Returns the optional parts of the libva API this backend implements.
+            ///
+            /// The default implementation claims to support everything.
+            fn features(&self) -> LibvaFeatures {
+                LibvaFeatures::all()
+            }
+
+            $(
+                #[allow(unused_variables)]
+                unsafe fn $func( &self, $( $name : $t ),* ) $( -> $ret )? {
+                    Unsupported::unsupported()
+                }
+            )+
+            $(
+                #[allow(unused_variables)]
+                unsafe fn $ofunc( &self, $( $oname : $ot ),* ) -> VAStatus {
+                    Unsupported::unsupported()
+                }
+            )*
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        unsafe impl $trait for &'static $strukt {
+            fn features(&self) -> LibvaFeatures {
+                $strukt::features(self)
+            }
+
+            $(
+                unsafe fn $func( &self, $( $name : $t ),* ) $( -> $ret )? {
+                    (self.$func)($($name),*)
+                }
+            )+
+            $(
+                unsafe fn $ofunc( &self, $( $oname : $ot ),* ) -> VAStatus {
+                    $strukt::$ofunc(self, $($oname),*)
+                }
+            )*
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        unsafe impl $trait for Interceptor {
+            fn features(&self) -> LibvaFeatures {
+                self.inner.features()
+            }
+
+            $(
+                unsafe fn $func( &self, $( $name : $t ),* ) $( -> $ret )? {
+                    let args = vec![$( (stringify!($name), TraceArg::trace(&$name)) ),*];
+                    let seq = self.before(stringify!($func), &args);
+                    let start = Instant::now();
+                    let ret = self.inner.$func($($name),*);
+                    self.after(seq, start, stringify!($func), args, TraceRet::trace_ret(&ret));
+                    ret
+                }
+            )+
+            $(
+                unsafe fn $ofunc( &self, $( $oname : $ot ),* ) -> VAStatus {
+                    let args = vec![$( (stringify!($oname), TraceArg::trace(&$oname)) ),*];
+                    let seq = self.before(stringify!($ofunc), &args);
+                    let start = Instant::now();
+                    let ret = self.inner.$ofunc($($oname),*);
+                    self.after(seq, start, stringify!($ofunc), args, TraceRet::trace_ret(&ret));
+                    ret
+                }
+            )*
+        }
+    };
+}
+
+backend! {
+    /// A libva implementation.
+    ///
+    /// Every method corresponds to the libva function of the same name, and has to behave as
+    /// documented in `va.h`. Methods that aren't overridden return
+    /// [`VAError::ERROR_UNIMPLEMENTED`] (or `0`/null, for functions that don't return a status
+    /// code).
+    ///
+    /// The `dpy` parameter of every method is the handle passed to (and returned from)
+    /// `vaInitialize`. Backends that aren't tied to a native display are free to ignore it.
+    ///
+    /// # Safety
+    ///
+    /// All pointer parameters and results must follow the contract of the corresponding libva
+    /// function, since the rest of the library relies on that to uphold memory safety.
+    pub unsafe trait Backend for libva: Library::Libva;
 
     fn vaErrorStr(error_status: VAStatus) -> *const c_char;
     fn vaSetErrorCallback(dpy: VADisplay, callback: VAMessageCallback, user_context: *mut c_void);
@@ -89,7 +309,7 @@ dylib! {
     fn vaInitialize(dpy: VADisplay, major_version: *mut c_int, minor_version: *mut c_int) -> VAStatus;
     fn vaTerminate(dpy: VADisplay) -> VAStatus;
     fn vaQueryVendorString(dpy: VADisplay) -> *const c_char;
-    fn vaGetLibFunc(dpy: VADisplay, func: *const c_char) -> VAPrivFunc;
+    fn vaGetLibFunc(dpy: VADisplay, func: *const c_char) -> Option<VAPrivFunc>;
     fn vaMaxNumProfiles(dpy: VADisplay) -> c_int;
     fn vaMaxNumEntrypoints(dpy: VADisplay) -> c_int;
     fn vaMaxNumConfigAttributes(dpy: VADisplay) -> c_int;
@@ -104,27 +324,20 @@ dylib! {
     fn vaDestroySurfaces(dpy: VADisplay, surfaces: *mut VASurfaceID, num_surfaces: c_int) -> VAStatus;
     fn vaCreateContext(dpy: VADisplay, config_id: VAConfigID, picture_width: c_int, picture_height: c_int, flag: c_int, render_targets: *mut VASurfaceID, num_render_targets: c_int, context: *mut VAContextID) -> VAStatus;
     fn vaDestroyContext(dpy: VADisplay, context: VAContextID) -> VAStatus;
-    fn vaCreateMFContext(dpy: VADisplay, mf_context: *mut VAMFContextID) -> VAStatus;
     // (some missing)
-    fn vaQueryProcessingRate(dpy: VADisplay, config: VAConfigID, proc_buf: *mut VAProcessingRateParameter, processing_rate: *mut c_uint) -> VAStatus;
     fn vaCreateBuffer(dpy: VADisplay, context: VAContextID, type_: BufferType, size: c_uint, num_elements: c_uint, data: *mut c_void, buf_id: *mut VABufferID) -> VAStatus;
-    fn vaCreateBuffer2(dpy: VADisplay, context: VAContextID, type_: BufferType, width: c_uint, height: c_uint, unit_size: *mut c_uint, pitch: *mut c_uint, buf_id: *mut VABufferID) -> VAStatus;
     fn vaBufferSetNumElements(dpy: VADisplay, buf_id: VABufferID, num_elements: c_uint) -> VAStatus;
     fn vaMapBuffer(dpy: VADisplay, buf_id: VABufferID, pbuf: *mut *mut c_void) -> VAStatus;
     fn vaUnmapBuffer(dpy: VADisplay, buf_id: VABufferID) -> VAStatus;
     fn vaDestroyBuffer(dpy: VADisplay, buffer_id: VABufferID) -> VAStatus;
     fn vaAcquireBufferHandle(dpy: VADisplay, buf_id: VABufferID, buf_info: *mut VABufferInfo) -> VAStatus;
     fn vaReleaseBufferHandle(dpy: VADisplay, buf_id: VABufferID) -> VAStatus;
-    fn vaExportSurfaceHandle(dpy: VADisplay, surface_id: VASurfaceID, mem_type: SurfaceAttribMemoryType, flags: ExportSurfaceFlags, descriptor: *mut c_void) -> VAStatus;
     fn vaBeginPicture(dpy: VADisplay, context: VAContextID, render_target: VASurfaceID) -> VAStatus;
     fn vaRenderPicture(dpy: VADisplay, context: VAContextID, buffers: *mut VABufferID, num_buffers: c_int) -> VAStatus;
     fn vaEndPicture(dpy: VADisplay, context: VAContextID) -> VAStatus;
-    fn vaMFSubmit(dpy: VADisplay, mf_context: VAMFContextID, contexts: *mut VAContextID, num_contexts: c_int) -> VAStatus;
     fn vaSyncSurface(dpy: VADisplay, render_target: VASurfaceID) -> VAStatus;
-    fn vaSyncSurface2(dpy: VADisplay, surface: VASurfaceID, timeout_ns: u64) -> VAStatus;
     fn vaQuerySurfaceStatus(dpy: VADisplay, render_target: VASurfaceID, status: *mut SurfaceStatus) -> VAStatus;
     fn vaQuerySurfaceError(dpy: VADisplay, surface: VASurfaceID, error_status: VAStatus, error_info: *mut *mut c_void) -> VAStatus;
-    fn vaSyncBuffer(dpy: VADisplay, buf_id: VABufferID, timeout_ns: u64) -> VAStatus;
     fn vaMaxNumImageFormats(dpy: VADisplay) -> c_int;
     fn vaQueryImageFormats(dpy: VADisplay, format_list: *mut ImageFormat, num_formats: *mut c_int) -> VAStatus;
     fn vaCreateImage(dpy: VADisplay, format: *mut ImageFormat, width: c_int, height: c_int, image: *mut VAImage) -> VAStatus;
@@ -163,23 +376,34 @@ dylib! {
 
     fn vaQueryVideoProcFilters(dpy: VADisplay, context: VAContextID, filters: *mut FilterType, num_filters: *mut c_uint) -> VAStatus;
     fn vaQueryVideoProcFilterCaps(dpy: VADisplay, context: VAContextID, type_: FilterType, filter_caps: *mut c_void, num_filter_caps: *mut c_uint) -> VAStatus;
-    fn vaQueryVideoProcPipelineCaps(dpy: VADisplay, context: VAContextID, filters: *mut VABufferID, num_filters: c_uint, pipeline_caps: *mut RawProcPipelineCaps) -> VAStatus;
+
+    // Functions that are missing from older libva versions.
+    optional(MULTI_FRAME) fn vaCreateMFContext(dpy: VADisplay, mf_context: *mut VAMFContextID) -> VAStatus;
+    optional(PROCESSING_RATE) fn vaQueryProcessingRate(dpy: VADisplay, config: VAConfigID, proc_buf: *mut VAProcessingRateParameter, processing_rate: *mut c_uint) -> VAStatus;
+    optional(CREATE_BUFFER2) fn vaCreateBuffer2(dpy: VADisplay, context: VAContextID, type_: BufferType, width: c_uint, height: c_uint, unit_size: *mut c_uint, pitch: *mut c_uint, buf_id: *mut VABufferID) -> VAStatus;
+    optional(EXPORT_SURFACE_HANDLE) fn vaExportSurfaceHandle(dpy: VADisplay, surface_id: VASurfaceID, mem_type: SurfaceAttribMemoryType, flags: ExportSurfaceFlags, descriptor: *mut c_void) -> VAStatus;
+    optional(MULTI_FRAME) fn vaMFAddContext(dpy: VADisplay, mf_context: VAMFContextID, context: VAContextID) -> VAStatus;
+    optional(MULTI_FRAME) fn vaMFReleaseContext(dpy: VADisplay, mf_context: VAMFContextID, context: VAContextID) -> VAStatus;
+    optional(MULTI_FRAME) fn vaMFSubmit(dpy: VADisplay, mf_context: VAMFContextID, contexts: *mut VAContextID, num_contexts: c_int) -> VAStatus;
+    optional(SYNC_SURFACE2) fn vaSyncSurface2(dpy: VADisplay, surface: VASurfaceID, timeout_ns: u64) -> VAStatus;
+    optional(SYNC_BUFFER) fn vaSyncBuffer(dpy: VADisplay, buf_id: VABufferID, timeout_ns: u64) -> VAStatus;
+    optional(VIDEO_PROC_PIPELINE_CAPS) fn vaQueryVideoProcPipelineCaps(dpy: VADisplay, context: VAContextID, filters: *mut VABufferID, num_filters: c_uint, pipeline_caps: *mut RawProcPipelineCaps) -> VAStatus;
 }
 
 dylib! {
-    pub struct libva_x11;
+    pub struct libva_x11: Library::X11;
 
     fn vaGetDisplay(dpy: *mut Display) -> VADisplay;
 }
 
 dylib! {
-    pub struct libva_wayland;
+    pub struct libva_wayland: Library::Wayland;
 
     fn vaGetDisplayWl(display: *mut wl_display) -> VADisplay;
 }
 
 dylib! {
-    pub struct libva_drm;
+    pub struct libva_drm: Library::Drm;
 
     fn vaGetDisplayDRM(fd: c_int) -> VADisplay;
 }
@@ -0,0 +1,283 @@
+//! Headless access to GPUs via DRM render nodes.
+//!
+//! Render nodes (`/dev/dri/renderD*`) allow using a GPU for rendering and video acceleration
+//! without a display server, which makes them the preferred way to use VA-API on servers.
+//!
+//! On machines with more than one GPU (eg. an integrated and a discrete one), [`Device::enumerate`]
+//! opens all of them, and [`Device::select`] picks one that supports a required [`ConfigRequest`].
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use raw_window_handle::{DrmDisplayHandle, HasRawDisplayHandle, RawDisplayHandle};
+
+use crate::{
+    capabilities::{ConfigMatch, ConfigRequest},
+    display::{Display, DisplayAttribFlags, DisplayAttribType, DisplayBuilder, PciId},
+    Error, Profile, Result,
+};
+
+const DRI_DIR: &str = "/dev/dri";
+const RENDER_NODE_PREFIX: &str = "renderD";
+
+/// A DRM render node of a GPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderNode {
+    path: PathBuf,
+    minor: u32,
+    driver: Option<String>,
+}
+
+impl RenderNode {
+    /// Returns a list of all render nodes in `/dev/dri`, ordered by their minor number.
+    ///
+    /// If the system has no DRM devices, an empty list is returned.
+    pub fn enumerate() -> Result<Vec<RenderNode>> {
+        let entries = match fs::read_dir(DRI_DIR) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::from(e)),
+        };
+
+        let mut nodes = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(Error::from)?;
+            let name = entry.file_name();
+            let Some(minor) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(RENDER_NODE_PREFIX))
+                .and_then(|minor| minor.parse().ok())
+            else {
+                continue;
+            };
+            nodes.push(Self::new(entry.path(), minor));
+        }
+        nodes.sort_by_key(|node| node.minor);
+        Ok(nodes)
+    }
+
+    fn new(path: PathBuf, minor: u32) -> Self {
+        // The kernel driver bound to the device is exposed as a symlink in sysfs.
+        let driver = fs::read_link(format!(
+            "/sys/class/drm/{RENDER_NODE_PREFIX}{minor}/device/driver"
+        ))
+        .ok()
+        .and_then(|link| Some(link.file_name()?.to_str()?.to_string()));
+        Self {
+            path,
+            minor,
+            driver,
+        }
+    }
+
+    /// Returns the path of the device file, eg. `/dev/dri/renderD128`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the minor device number of the render node (eg. 128).
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    /// Returns the name of the kernel driver of the GPU (eg. `i915`, `amdgpu` or `nouveau`), if it
+    /// could be determined.
+    ///
+    /// Note that this is not the name of the VA-API driver. To find out which VA-API
+    /// implementation is used, open the node and use [`Display::query_vendor_string`].
+    pub fn driver(&self) -> Option<&str> {
+        self.driver.as_deref()
+    }
+
+    /// Opens a [`Display`] using this render node.
+    pub fn open(&self) -> Result<Display> {
+        self.open_with(DisplayBuilder::new())
+    }
+
+    /// Opens a [`Display`] using this render node, configured by `builder`.
+    pub fn open_with(&self, builder: DisplayBuilder) -> Result<Display> {
+        builder.open_drm(&self.path)
+    }
+}
+
+/// A GPU that was opened via its [`RenderNode`], along with a summary of its VA-API support.
+pub struct Device {
+    node: RenderNode,
+    display: Display,
+    vendor: String,
+    pci_id: Option<PciId>,
+    profiles: Vec<Profile>,
+}
+
+impl Device {
+    /// Opens every render node in `/dev/dri`, ordered by their minor number.
+    ///
+    /// Render nodes that fail to open (eg. because no VA-API driver supports the GPU) are skipped.
+    pub fn enumerate() -> Result<Vec<Device>> {
+        Self::enumerate_with(DisplayBuilder::new())
+    }
+
+    /// Opens every render node in `/dev/dri`, configuring each [`Display`] with `builder`.
+    ///
+    /// Render nodes that fail to open are skipped.
+    pub fn enumerate_with(builder: DisplayBuilder) -> Result<Vec<Device>> {
+        let mut devices = Vec::new();
+        for node in RenderNode::enumerate()? {
+            let result = node
+                .open_with(builder.clone())
+                .and_then(|display| Self::new(node.clone(), display));
+            match result {
+                Ok(device) => devices.push(device),
+                Err(e) => log::debug!("skipping render node {}: {e}", node.path().display()),
+            }
+        }
+        Ok(devices)
+    }
+
+    fn new(node: RenderNode, display: Display) -> Result<Self> {
+        let vendor = display.query_vendor_string()?.to_string();
+        // The PCI ID is optional, so failing to query it doesn't make the device unusable.
+        let pci_id = Self::query_pci_id(&display).unwrap_or_else(|e| {
+            log::debug!("{}: failed to query PCI ID: {e}", node.path().display());
+            None
+        });
+        let profiles = display.query_profiles()?.into_iter().collect();
+        Ok(Self {
+            node,
+            display,
+            vendor,
+            pci_id,
+            profiles,
+        })
+    }
+
+    fn query_pci_id(display: &Display) -> Result<Option<PciId>> {
+        match display
+            .query_display_attributes()?
+            .get(DisplayAttribType::PCIID)
+        {
+            Some(attrib) if attrib.flags().contains(DisplayAttribFlags::GETTABLE) => {
+                Ok(Some(display.pci_id()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the first device in `devices` that supports `request`, along with the matching
+    /// configuration.
+    ///
+    /// Returns [`None`] if no device supports `request`.
+    pub fn select<'a>(
+        devices: &'a [Device],
+        request: &ConfigRequest,
+    ) -> Result<Option<(&'a Device, ConfigMatch)>> {
+        for device in devices {
+            match device.find_config(request) {
+                Ok(found) => return Ok(Some((device, found))),
+                Err(e) if e.is_unsupported() => {
+                    log::debug!("{}: {e}", device.node.path().display());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the render node this device was opened from.
+    pub fn node(&self) -> &RenderNode {
+        &self.node
+    }
+
+    /// Returns the [`Display`] of this device.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Consumes the [`Device`] and returns its [`Display`].
+    pub fn into_display(self) -> Display {
+        self.display
+    }
+
+    /// Returns the vendor string of the VA-API driver.
+    ///
+    /// See [`Display::query_vendor_string`].
+    pub fn vendor(&self) -> &str {
+        &self.vendor
+    }
+
+    /// Returns the name of the kernel driver of the GPU, if it could be determined.
+    ///
+    /// See [`RenderNode::driver`].
+    pub fn driver(&self) -> Option<&str> {
+        self.node.driver()
+    }
+
+    /// Returns the PCI vendor and device ID of the GPU, if the VA-API driver reports them via
+    /// [`DisplayAttribType::PCIID`].
+    pub fn pci_id(&self) -> Option<PciId> {
+        self.pci_id
+    }
+
+    /// Returns the [`Profile`]s supported by the VA-API driver.
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// Returns whether the VA-API driver supports `profile` with any entrypoint.
+    pub fn supports_profile(&self, profile: Profile) -> bool {
+        self.profiles.contains(&profile)
+    }
+
+    /// Finds a configuration satisfying `request` on this device.
+    ///
+    /// See [`Display::find_config`].
+    pub fn find_config(&self, request: &ConfigRequest) -> Result<ConfigMatch> {
+        self.display.find_config(request)
+    }
+}
+
+impl fmt::Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Device")
+            .field("node", &self.node)
+            .field("vendor", &self.vendor)
+            .field("pci_id", &self.pci_id)
+            .field("profiles", &self.profiles)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An open DRM device file.
+///
+/// Used as the display handle of DRM [`Display`]s, so that the file descriptor stays open for as
+/// long as the display is in use.
+pub(crate) struct DrmDevice {
+    file: File,
+}
+
+impl DrmDevice {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| Error::from(format!("failed to open {}: {e}", path.display())))?;
+        Ok(Self { file })
+    }
+}
+
+unsafe impl HasRawDisplayHandle for DrmDevice {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        let mut handle = DrmDisplayHandle::empty();
+        handle.fd = self.file.as_raw_fd();
+        RawDisplayHandle::Drm(handle)
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use crate::{
+    backend::FakeBackend,
+    capabilities::ConfigRequest,
+    display::{Display, PciId},
+    error::VAError,
+    surface::RTFormat,
+    Entrypoint, Profile,
+};
+
+use super::{Device, RenderNode};
+
+fn fake_device(minor: u32, backend: FakeBackend) -> Device {
+    let node = RenderNode {
+        path: PathBuf::from(format!("/dev/dri/renderD{minor}")),
+        minor,
+        driver: Some("fake".into()),
+    };
+    Device::new(node, Display::with_backend(backend).unwrap()).unwrap()
+}
+
+#[test]
+fn select_device() {
+    // An "integrated" GPU that only does video processing, and a "discrete" one that can also
+    // decode JPEGs.
+    let igpu = FakeBackend::without_profiles();
+    igpu.support(Profile::None, Entrypoint::VideoProc, RTFormat::YUV420);
+    igpu.set_pci_id(PciId::new(0x8086, 0x46a6));
+    let dgpu = FakeBackend::new();
+    dgpu.set_max_picture_size(Profile::JPEGBaseline, Entrypoint::VLD, 4096, 4096);
+    let devices = [fake_device(128, igpu), fake_device(129, dgpu)];
+
+    let igpu = &devices[0];
+    assert_eq!(igpu.vendor(), "v-ayylmao fake driver");
+    assert_eq!(igpu.driver(), Some("fake"));
+    assert_eq!(igpu.pci_id(), Some(PciId::new(0x8086, 0x46a6)));
+    assert_eq!(igpu.profiles(), [Profile::None]);
+    assert!(!igpu.supports_profile(Profile::JPEGBaseline));
+    assert!(devices[1].supports_profile(Profile::JPEGBaseline));
+
+    let jpeg = ConfigRequest::new(Profile::JPEGBaseline, RTFormat::YUV420, 1920, 1080);
+    let (device, found) = Device::select(&devices, &jpeg).unwrap().unwrap();
+    assert_eq!(device.node().minor(), 129);
+    assert_eq!(found.entrypoint(), Entrypoint::VLD);
+    found.create(device.display()).unwrap();
+
+    let vpp = ConfigRequest::new(Profile::None, RTFormat::YUV420, 1920, 1080);
+    let (device, _) = Device::select(&devices, &vpp).unwrap().unwrap();
+    assert_eq!(device.node().minor(), 128);
+
+    let too_large = ConfigRequest::new(Profile::JPEGBaseline, RTFormat::YUV420, 8192, 8192);
+    assert!(Device::select(&devices, &too_large).unwrap().is_none());
+}
+
+#[test]
+fn pci_id_is_optional() {
+    let fake = FakeBackend::new();
+    fake.set_pci_id(PciId::new(0x1002, 0x73bf));
+    fake.fail_next("vaQueryDisplayAttributes", VAError::ERROR_OPERATION_FAILED);
+    let device = fake_device(128, fake.clone());
+    assert_eq!(device.pci_id(), None);
+    assert!(device.supports_profile(Profile::JPEGBaseline));
+
+    fake.fail_next("vaGetDisplayAttributes", VAError::ERROR_OPERATION_FAILED);
+    let device = fake_device(129, fake);
+    assert_eq!(device.pci_id(), None);
+}
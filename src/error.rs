@@ -2,15 +2,24 @@
 
 use core::fmt;
 use std::{
+    cmp,
     ffi::{c_int, CStr},
+    io,
     num::TryFromIntError,
     str::Utf8Error,
+    thread,
+    time::Duration,
 };
 
-use crate::dlopen::libva;
+use crate::{
+    capabilities::Unsupported, config::UnsupportedAttrib, display::DisplayAttribType, dlopen::libva,
+};
 
 ffi_enum! {
-    pub(crate) enum VAStatus: c_int {
+    /// A status code returned by *libva* functions.
+    ///
+    /// Any value other than [`VAStatus::SUCCESS`] is a [`VAError`].
+    pub enum VAStatus: c_int {
         SUCCESS                        = 0x00000000,
         // Other allowed values are in `VAError`.
     }
@@ -77,19 +86,172 @@ impl PartialEq<VAStatus> for VAError {
 }
 
 impl VAError {
+    /// Returns whether this error is likely to go away when retrying the operation later.
+    ///
+    /// This is the case for [`VAError::ERROR_HW_BUSY`], [`VAError::ERROR_SURFACE_BUSY`] and
+    /// [`VAError::ERROR_TIMEDOUT`].
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            Self::ERROR_HW_BUSY | Self::ERROR_SURFACE_BUSY | Self::ERROR_TIMEDOUT
+        )
+    }
+
+    /// Returns whether this error indicates that the driver does not support the requested
+    /// operation or configuration at all.
+    pub fn is_unsupported(self) -> bool {
+        matches!(
+            self,
+            Self::ERROR_ATTR_NOT_SUPPORTED
+                | Self::ERROR_UNSUPPORTED_PROFILE
+                | Self::ERROR_UNSUPPORTED_ENTRYPOINT
+                | Self::ERROR_UNSUPPORTED_RT_FORMAT
+                | Self::ERROR_UNSUPPORTED_BUFFERTYPE
+                | Self::ERROR_FLAG_NOT_SUPPORTED
+                | Self::ERROR_RESOLUTION_NOT_SUPPORTED
+                | Self::ERROR_UNIMPLEMENTED
+                | Self::ERROR_INVALID_IMAGE_FORMAT
+                | Self::ERROR_UNSUPPORTED_FILTER
+                | Self::ERROR_UNSUPPORTED_MEMORY_TYPE
+        )
+    }
+
+    /// Returns whether this error indicates that the data or parameters passed to the driver were
+    /// invalid (for example, because the bitstream to decode is corrupt).
+    pub fn is_invalid_input(self) -> bool {
+        matches!(
+            self,
+            Self::ERROR_INVALID_PARAMETER
+                | Self::ERROR_INVALID_VALUE
+                | Self::ERROR_DECODING_ERROR
+                | Self::ERROR_INVALID_FILTER_CHAIN
+        )
+    }
+
+    /// Returns the description of this error, as reported by libva's `vaErrorStr`.
+    ///
+    /// This loads the system libva. Use [`VAError::description`] to avoid that.
     pub fn to_str(self) -> Result<&'static str, Error> {
         unsafe {
             let cstr = &CStr::from_ptr(libva::get().map_err(Error::from)?.vaErrorStr(self.into()));
             Ok(cstr.to_str().map_err(Error::from)?)
         }
     }
+
+    /// Returns a description of this error.
+    ///
+    /// Unlike [`VAError::to_str`], this doesn't need libva, so it also works with a custom
+    /// [`Backend`][crate::backend::Backend]. The descriptions match the ones of libva 2.x.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::ERROR_OPERATION_FAILED => "operation failed",
+            Self::ERROR_ALLOCATION_FAILED => "resource allocation failed",
+            Self::ERROR_INVALID_DISPLAY => "invalid VADisplay",
+            Self::ERROR_INVALID_CONFIG => "invalid VAConfigID",
+            Self::ERROR_INVALID_CONTEXT => "invalid VAContextID",
+            Self::ERROR_INVALID_SURFACE => "invalid VASurfaceID",
+            Self::ERROR_INVALID_BUFFER => "invalid VABufferID",
+            Self::ERROR_INVALID_IMAGE => "invalid VAImageID",
+            Self::ERROR_INVALID_SUBPICTURE => "invalid VASubpictureID",
+            Self::ERROR_ATTR_NOT_SUPPORTED => "attribute not supported",
+            Self::ERROR_MAX_NUM_EXCEEDED => "list argument exceeds maximum number",
+            Self::ERROR_UNSUPPORTED_PROFILE => "the requested VAProfile is not supported",
+            Self::ERROR_UNSUPPORTED_ENTRYPOINT => "the requested VAEntryPoint is not supported",
+            Self::ERROR_UNSUPPORTED_RT_FORMAT => "the requested RT Format is not supported",
+            Self::ERROR_UNSUPPORTED_BUFFERTYPE => "the requested VABufferType is not supported",
+            Self::ERROR_SURFACE_BUSY => "surface is in use",
+            Self::ERROR_FLAG_NOT_SUPPORTED => "flag not supported",
+            Self::ERROR_INVALID_PARAMETER => "invalid parameter",
+            Self::ERROR_RESOLUTION_NOT_SUPPORTED => "resolution not supported",
+            Self::ERROR_UNIMPLEMENTED => "the requested function is not implemented",
+            Self::ERROR_SURFACE_IN_DISPLAYING => "surface is in displaying (may by overlay)",
+            Self::ERROR_INVALID_IMAGE_FORMAT => "invalid VAImageFormat",
+            Self::ERROR_DECODING_ERROR => "internal decoding error",
+            Self::ERROR_ENCODING_ERROR => "internal encoding error",
+            Self::ERROR_INVALID_VALUE => "an invalid/unsupported value was supplied",
+            Self::ERROR_UNSUPPORTED_FILTER => "the requested filter is not supported",
+            Self::ERROR_INVALID_FILTER_CHAIN => "an invalid filter chain was supplied",
+            Self::ERROR_HW_BUSY => "HW busy now",
+            Self::ERROR_UNSUPPORTED_MEMORY_TYPE => "an unsupported memory type was supplied",
+            Self::ERROR_NOT_ENOUGH_BUFFER => {
+                "allocated memory size is not enough for input or output"
+            }
+            Self::ERROR_TIMEDOUT => "operation timed out",
+            _ => "unknown libva error",
+        }
+    }
+}
+
+/// The category of an [`Error`], as returned by [`Error::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A libva function returned an error code (see [`Error::as_libva`]).
+    Libva,
+    /// The libva implementation does not provide an optional function (see
+    /// [`Error::missing_function`]).
+    MissingFunction,
+    /// A shared library or one of its symbols could not be loaded.
+    Loading,
+    /// An I/O operation failed.
+    Io,
+    /// A string returned by libva or its driver was not valid UTF-8.
+    InvalidUtf8,
+    /// An integer was out of range for the type libva expects.
+    IntConversion,
+    /// The input data (like a JPEG image) is malformed or uses features that aren't supported.
+    InvalidInput,
+    /// The display doesn't support a requested configuration (see [`Error::unsupported_config`]
+    /// and [`Error::unsupported_attrib`]), or a requested display attribute.
+    UnsupportedConfig,
+    /// A [`SurfacePool`][crate::surface::SurfacePool] has reached its maximum number of surfaces,
+    /// and none of them are free.
+    PoolExhausted,
+    /// Any other error, like an unexpected value returned by the driver.
+    Other,
+}
+
+/// A libva object that was passed to a function that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Object {
+    Config(u32),
+    Context(u32),
+    Surface(u32),
+    Buffer(u32),
+    Image(u32),
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Config(id) => write!(f, "config {id}"),
+            Object::Context(id) => write!(f, "context {id}"),
+            Object::Surface(id) => write!(f, "surface {id}"),
+            Object::Buffer(id) => write!(f, "buffer {id}"),
+            Object::Image(id) => write!(f, "image {id}"),
+        }
+    }
 }
 
 pub(crate) enum Repr {
-    Libva(VAError),
+    Libva {
+        error: VAError,
+        function: &'static str,
+        objects: Vec<Object>,
+    },
     Libloading(libloading::Error),
+    Io(io::Error),
     Utf8Error(Utf8Error),
     TryFromIntError(TryFromIntError),
+    MissingFunction(&'static str),
+    InvalidInput(String),
+    Unsupported(Box<Unsupported>),
+    UnsupportedAttrib(Box<UnsupportedAttrib>),
+    UnsupportedDisplayAttrib(DisplayAttribType),
+    PoolExhausted {
+        max: usize,
+    },
     Other(String),
 }
 
@@ -123,9 +285,9 @@ impl From<libloading::Error> for Repr {
     }
 }
 
-impl From<VAError> for Repr {
-    fn from(v: VAError) -> Self {
-        Self::Libva(v)
+impl From<io::Error> for Repr {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
     }
 }
 
@@ -135,11 +297,147 @@ pub struct Error {
 }
 
 impl Error {
+    /// Returns the category of this [`Error`].
+    pub fn kind(&self) -> ErrorKind {
+        match &self.repr {
+            Repr::Libva { .. } => ErrorKind::Libva,
+            Repr::Libloading(_) => ErrorKind::Loading,
+            Repr::Io(_) => ErrorKind::Io,
+            Repr::Utf8Error(_) => ErrorKind::InvalidUtf8,
+            Repr::TryFromIntError(_) => ErrorKind::IntConversion,
+            Repr::MissingFunction(_) => ErrorKind::MissingFunction,
+            Repr::InvalidInput(_) => ErrorKind::InvalidInput,
+            Repr::Unsupported(_)
+            | Repr::UnsupportedAttrib(_)
+            | Repr::UnsupportedDisplayAttrib(_) => ErrorKind::UnsupportedConfig,
+            Repr::PoolExhausted { .. } => ErrorKind::PoolExhausted,
+            Repr::Other(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Returns whether retrying the failed operation later is likely to succeed.
+    ///
+    /// This is the case for the libva errors listed in [`VAError::is_transient`], and when a
+    /// [`SurfacePool`][crate::surface::SurfacePool] is exhausted. See also [`Retry`].
+    pub fn is_transient(&self) -> bool {
+        match &self.repr {
+            Repr::Libva { error, .. } => error.is_transient(),
+            Repr::PoolExhausted { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the error indicates that VA-API can't be used for the operation at all, so
+    /// that the application should permanently fall back to a software implementation.
+    ///
+    /// This includes errors for unsupported profiles, entrypoints and formats, optional libva
+    /// functions that are missing, and failure to load libva itself.
+    pub fn is_unsupported(&self) -> bool {
+        match &self.repr {
+            Repr::Libva { error, .. } => error.is_unsupported(),
+            Repr::MissingFunction(_)
+            | Repr::Libloading(_)
+            | Repr::Unsupported(_)
+            | Repr::UnsupportedAttrib(_)
+            | Repr::UnsupportedDisplayAttrib(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the error was caused by the specific input that was passed in (like a
+    /// malformed or unsupported JPEG image).
+    ///
+    /// Other inputs may still be processed successfully.
+    pub fn is_invalid_input(&self) -> bool {
+        match &self.repr {
+            Repr::Libva { error, .. } => error.is_invalid_input(),
+            Repr::InvalidInput(_) => true,
+            _ => false,
+        }
+    }
+
     /// If this [`Error`] was returned by a *libva* function, returns the corresponding [`VAError`]
     /// code.
     pub fn as_libva(&self) -> Option<VAError> {
         match &self.repr {
-            Repr::Libva(e) => Some(*e),
+            Repr::Libva { error, .. } => Some(*error),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the *libva* function that failed (or that is missing), if this error
+    /// was caused by one.
+    pub fn function(&self) -> Option<&'static str> {
+        match &self.repr {
+            Repr::Libva { function, .. } | Repr::MissingFunction(function) => Some(function),
+            _ => None,
+        }
+    }
+
+    /// Returns the libva objects that were passed to the failing function.
+    ///
+    /// Not every function reports the objects involved, so this may be empty even for
+    /// [`ErrorKind::Libva`] errors.
+    pub fn objects(&self) -> &[Object] {
+        match &self.repr {
+            Repr::Libva { objects, .. } => objects,
+            _ => &[],
+        }
+    }
+
+    /// Returns the ID of the surface involved in the failed call, if any.
+    pub fn surface(&self) -> Option<u32> {
+        self.objects().iter().find_map(|obj| match obj {
+            Object::Surface(id) => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// Returns the ID of the context involved in the failed call, if any.
+    pub fn context(&self) -> Option<u32> {
+        self.objects().iter().find_map(|obj| match obj {
+            Object::Context(id) => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// Returns the ID of the buffer involved in the failed call, if any.
+    pub fn buffer(&self) -> Option<u32> {
+        self.objects().iter().find_map(|obj| match obj {
+            Object::Buffer(id) => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// If this [`Error`] was caused by the libva implementation not providing an optional function,
+    /// returns the name of that function.
+    ///
+    /// See [`LibvaFeatures`][crate::display::LibvaFeatures] for the list of optional functions.
+    pub fn missing_function(&self) -> Option<&'static str> {
+        match &self.repr {
+            Repr::MissingFunction(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// If this [`Error`] was returned because the display doesn't support a requested
+    /// configuration, returns the reasons.
+    ///
+    /// See [`Display::find_config`][crate::display::Display::find_config].
+    pub fn unsupported_config(&self) -> Option<&Unsupported> {
+        match &self.repr {
+            Repr::Unsupported(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// If this [`Error`] was returned because the driver doesn't support a requested config
+    /// attribute value, returns which one.
+    ///
+    /// See [`Config::with_attribs`][crate::config::Config::with_attribs].
+    pub fn unsupported_attrib(&self) -> Option<&UnsupportedAttrib> {
+        match &self.repr {
+            Repr::UnsupportedAttrib(e) => Some(e),
             _ => None,
         }
     }
@@ -147,15 +445,43 @@ impl Error {
     pub(crate) fn from(e: impl Into<Repr>) -> Self {
         Self { repr: e.into() }
     }
+
+    pub(crate) fn invalid_input(msg: impl Into<String>) -> Self {
+        Self::from(Repr::InvalidInput(msg.into()))
+    }
+
+    pub(crate) fn libva(error: VAError, function: &'static str, objects: &[Object]) -> Self {
+        Self::from(Repr::Libva {
+            error,
+            function,
+            objects: objects.to_vec(),
+        })
+    }
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.repr {
-            Repr::Libva(e) => e.fmt(f),
+            Repr::Libva {
+                error,
+                function,
+                objects,
+            } => f
+                .debug_struct("Libva")
+                .field("error", error)
+                .field("function", function)
+                .field("objects", objects)
+                .finish(),
             Repr::Libloading(e) => e.fmt(f),
+            Repr::Io(e) => e.fmt(f),
             Repr::Utf8Error(e) => e.fmt(f),
             Repr::TryFromIntError(e) => e.fmt(f),
+            Repr::MissingFunction(name) => write!(f, "MissingFunction({name})"),
+            Repr::InvalidInput(s) => write!(f, "InvalidInput({s:?})"),
+            Repr::Unsupported(e) => e.fmt(f),
+            Repr::UnsupportedAttrib(e) => e.fmt(f),
+            Repr::UnsupportedDisplayAttrib(ty) => write!(f, "UnsupportedDisplayAttrib({ty:?})"),
+            Repr::PoolExhausted { max } => write!(f, "PoolExhausted({max})"),
             Repr::Other(s) => s.fmt(f),
         }
     }
@@ -164,16 +490,143 @@ impl fmt::Debug for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.repr {
-            Repr::Libva(e) => match e.to_str() {
-                Ok(s) => write!(f, "{self:?}: {s}"),
-                Err(_) => fmt::Debug::fmt(e, f),
-            },
-            Repr::Libloading(e) => e.fmt(f),
-            Repr::Utf8Error(e) => e.fmt(f),
-            Repr::TryFromIntError(e) => e.fmt(f),
-            Repr::Other(e) => e.fmt(f),
+            Repr::Libva {
+                error,
+                function,
+                objects,
+            } => {
+                write!(f, "{function} failed")?;
+                for (i, obj) in objects.iter().enumerate() {
+                    f.write_str(if i == 0 { " (" } else { ", " })?;
+                    obj.fmt(f)?;
+                }
+                if !objects.is_empty() {
+                    f.write_str(")")?;
+                }
+                write!(f, ": {error:?} ({})", error.description())
+            }
+            Repr::Libloading(e) => write!(f, "failed to load a shared library: {e}"),
+            Repr::Io(e) => write!(f, "I/O error: {e}"),
+            Repr::Utf8Error(_) => f.write_str("libva returned a string that is not valid UTF-8"),
+            Repr::TryFromIntError(_) => f.write_str("integer out of range"),
+            Repr::MissingFunction(name) => {
+                write!(f, "`{name}` is not supported by this libva version")
+            }
+            Repr::InvalidInput(e) | Repr::Other(e) => e.fmt(f),
+            Repr::Unsupported(e) => e.fmt(f),
+            Repr::UnsupportedAttrib(e) => e.fmt(f),
+            Repr::UnsupportedDisplayAttrib(ty) => {
+                write!(f, "display attribute {ty:?} is not supported by the driver")
+            }
+            Repr::PoolExhausted { max } => {
+                write!(f, "all {max} surfaces of the surface pool are in use")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.repr {
+            Repr::Libloading(e) => Some(e),
+            Repr::Io(e) => Some(e),
+            Repr::Utf8Error(e) => Some(e),
+            Repr::TryFromIntError(e) => Some(e),
+            Repr::Libva { .. }
+            | Repr::MissingFunction(_)
+            | Repr::InvalidInput(_)
+            | Repr::Unsupported(_)
+            | Repr::UnsupportedAttrib(_)
+            | Repr::UnsupportedDisplayAttrib(_)
+            | Repr::PoolExhausted { .. }
+            | Repr::Other(_) => None,
+        }
+    }
+}
+
+/// Retries operations that fail with a [transient][Error::is_transient] error, waiting
+/// exponentially longer between attempts.
+///
+/// # Example
+///
+/// ```no_run
+/// # use v_ayylmao::{config::Config, context::Context, error::Retry};
+/// # fn f(config: &Config) -> Result<(), v_ayylmao::error::Error> {
+/// let retry = Retry::new()
+///     .with_max_attempts(10)
+///     .with_initial_delay(std::time::Duration::from_millis(5));
+/// let context = retry.run(|| Context::new(config, 1920, 1080))?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Retry {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(50),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl Retry {
+    /// Creates a [`Retry`] policy that makes up to 5 attempts, waiting 1ms after the first one and
+    /// doubling the delay after every attempt, up to 50ms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the total number of attempts, including the first one.
+    ///
+    /// A value of 1 disables retrying.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `attempts` is 0.
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        assert!(attempts > 0, "at least one attempt must be made");
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Sets the delay before the second attempt.
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the maximum delay between two attempts.
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Invokes `op` until it succeeds, fails with an error that isn't transient, or the maximum
+    /// number of attempts is reached.
+    ///
+    /// The error of the last attempt is returned.
+    pub fn run<T>(&self, mut op: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(e) if e.is_transient() && attempt < self.max_attempts => {
+                    log::debug!(
+                        "{e}; retrying in {delay:?} (attempt {attempt}/{})",
+                        self.max_attempts
+                    );
+                    thread::sleep(delay);
+                    delay = cmp::min(delay.saturating_mul(2), self.max_delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -13,6 +13,7 @@ use crate::{
     buffer::Mapping,
     check, check_log,
     display::{Display, DisplayOwner},
+    error::Object,
     pixelformat::PixelFormat,
     raw::{VABufferID, VAImageID, VA_PADDING_LOW},
     Error, Result,
@@ -162,9 +163,10 @@ impl IntoIterator for ImageFormats {
     }
 }
 
-#[derive(Debug)]
+/// Raw description of an image, as exchanged with a [`Backend`][crate::backend::Backend].
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
-pub(crate) struct VAImage {
+pub struct VAImage {
     pub image_id: VAImageID,
     pub format: ImageFormat,
     pub buf: VABufferID,
@@ -180,6 +182,12 @@ pub(crate) struct VAImage {
     va_reserved: [u32; VA_PADDING_LOW],
 }
 
+impl VAImage {
+    pub(crate) fn zeroed() -> Self {
+        unsafe { mem::zeroed() }
+    }
+}
+
 /// An [`Image`] is a mappable [`Buffer`][crate::buffer::Buffer] storing image data.
 #[derive(Debug)]
 pub struct Image {
@@ -198,13 +206,17 @@ impl Image {
         let height: c_int = height.try_into().map_err(Error::from)?;
         let mut image = MaybeUninit::uninit();
         unsafe {
-            check(display.d.libva.vaCreateImage(
-                display.d.raw,
-                &mut format,
-                width,
-                height,
-                image.as_mut_ptr(),
-            ))?;
+            check(
+                display.d.libva.vaCreateImage(
+                    display.d.raw,
+                    &mut format,
+                    width,
+                    height,
+                    image.as_mut_ptr(),
+                ),
+                "vaCreateImage",
+                &[],
+            )?;
             Ok(Image {
                 d: display.d.clone(),
                 raw: image.assume_init(),
@@ -243,7 +255,14 @@ impl Image {
 
         let mut ptr = ptr::null_mut();
         unsafe {
-            check(self.d.libva.vaMapBuffer(self.d.raw, self.raw.buf, &mut ptr))?;
+            check(
+                self.d.libva.vaMapBuffer(self.d.raw, self.raw.buf, &mut ptr),
+                "vaMapBuffer",
+                &[
+                    Object::Image(self.raw.image_id),
+                    Object::Buffer(self.raw.buf),
+                ],
+            )?;
         }
 
         log::trace!("vaMapBuffer for VAImage took {:?}", start.elapsed());
@@ -262,7 +281,8 @@ impl Drop for Image {
         unsafe {
             check_log(
                 self.d.libva.vaDestroyImage(self.d.raw, self.raw.image_id),
-                "vaDestroyImage call in drop",
+                "vaDestroyImage",
+                &[Object::Image(self.raw.image_id)],
             );
         }
     }
@@ -0,0 +1,214 @@
+//! A [`Backend`] layer that observes all calls made through it.
+//!
+//! This powers [`Trace`] and [`Recording`]. The [`Backend`] implementation for [`Interceptor`] is
+//! generated by the `backend!` macro in `dlopen.rs`.
+
+use std::{slice, time::Instant};
+
+use crate::{
+    backend::Backend,
+    image::VAImage,
+    raw::VAGenericID,
+    replay::Recording,
+    trace::{Trace, TraceArg, TraceValue},
+};
+
+/// A call made through an [`Interceptor`].
+pub(crate) struct Call {
+    pub(crate) function: &'static str,
+    pub(crate) args: Vec<(&'static str, TraceValue)>,
+    /// The returned value. [`None`] before the call has been made, and for functions that don't
+    /// return anything.
+    pub(crate) result: Option<TraceValue>,
+}
+
+impl Call {
+    pub(crate) fn arg(&self, name: &str) -> Option<&TraceValue> {
+        self.args.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    pub(crate) fn int(&self, name: &str) -> Option<i64> {
+        self.arg(name).and_then(TraceValue::as_int)
+    }
+
+    pub(crate) fn pointer(&self, name: &str) -> usize {
+        self.arg(name).and_then(TraceValue::as_pointer).unwrap_or(0)
+    }
+
+    /// Returns the object IDs in the argument `name`, which must have been resolved by
+    /// [`resolve_ids`].
+    pub(crate) fn ids(&self, name: &str) -> Vec<VAGenericID> {
+        match self.arg(name) {
+            Some(TraceValue::List(ids)) => ids
+                .iter()
+                .filter_map(|id| id.as_int().and_then(|id| id.try_into().ok()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns whether the function returned `VA_STATUS_SUCCESS`.
+    pub(crate) fn succeeded(&self) -> bool {
+        self.result.as_ref().and_then(TraceValue::as_int) == Some(0)
+    }
+}
+
+/// Forwards all calls to another [`Backend`], while recording them in a [`Trace`] and/or
+/// [`Recording`].
+pub(crate) struct Interceptor {
+    pub(crate) inner: Box<dyn Backend>,
+    trace: Option<Trace>,
+    recording: Option<Recording>,
+}
+
+impl Interceptor {
+    /// Wraps `inner` if any observers are given, otherwise returns it as-is.
+    pub(crate) fn wrap(
+        inner: Box<dyn Backend>,
+        trace: Option<Trace>,
+        recording: Option<Recording>,
+    ) -> Box<dyn Backend> {
+        if trace.is_none() && recording.is_none() {
+            return inner;
+        }
+        Box::new(Self {
+            inner,
+            trace,
+            recording,
+        })
+    }
+
+    /// Invoked before forwarding a call to the inner [`Backend`]. Returns the sequence number
+    /// assigned to the call by the [`Trace`].
+    ///
+    /// # Safety
+    ///
+    /// The pointer arguments of the call must be valid.
+    pub(crate) unsafe fn before(
+        &self,
+        function: &'static str,
+        args: &[(&'static str, TraceValue)],
+    ) -> Option<u64> {
+        let seq = self.trace.as_ref().map(|trace| trace.begin(function, args));
+        if let Some(recording) = &self.recording {
+            let call = Call {
+                function,
+                args: args.to_vec(),
+                result: None,
+            };
+            recording.before(&call);
+        }
+        seq
+    }
+
+    /// Invoked after the inner [`Backend`] has returned.
+    ///
+    /// # Safety
+    ///
+    /// The pointer arguments of the call must be valid.
+    pub(crate) unsafe fn after(
+        &self,
+        seq: Option<u64>,
+        start: Instant,
+        function: &'static str,
+        args: Vec<(&'static str, TraceValue)>,
+        result: Option<TraceValue>,
+    ) {
+        let duration = start.elapsed();
+        let mut call = Call {
+            function,
+            args,
+            result,
+        };
+        resolve_ids(&mut call);
+        if let Some(recording) = &self.recording {
+            recording.after(&call);
+        }
+        if let (Some(trace), Some(seq)) = (&self.trace, seq) {
+            trace.record(seq, start, duration, call);
+        }
+    }
+}
+
+/// Replaces pointers to object IDs in the arguments of `call` with the IDs they point to.
+///
+/// Output parameters are only read if the call succeeded.
+unsafe fn resolve_ids(call: &mut Call) {
+    // Pairs of pointer argument and length argument, or `None` if the pointer points to a single
+    // ID.
+    type IdArgs = &'static [(&'static str, Option<&'static str>)];
+
+    let success = call.succeeded();
+    let (input, output): (IdArgs, IdArgs) = match call.function {
+        "vaCreateConfig" => (&[], &[("config_id", None)]),
+        "vaCreateSurfaces" => (&[], &[("surfaces", Some("num_surfaces"))]),
+        "vaDestroySurfaces" => (&[("surfaces", Some("num_surfaces"))], &[]),
+        "vaCreateContext" => (
+            &[("render_targets", Some("num_render_targets"))],
+            &[("context", None)],
+        ),
+        "vaCreateBuffer" => (&[], &[("buf_id", None)]),
+        // The unit size and pitch are returned as 32-bit integers too.
+        "vaCreateBuffer2" => (
+            &[],
+            &[("unit_size", None), ("pitch", None), ("buf_id", None)],
+        ),
+        "vaRenderPicture" => (&[("buffers", Some("num_buffers"))], &[]),
+        "vaCreateSubpicture" => (&[], &[("subpicture", None)]),
+        "vaAssociateSubpicture" | "vaDeassociateSubpicture" => {
+            (&[("target_surfaces", Some("num_surfaces"))], &[])
+        }
+        "vaCreateMFContext" => (&[], &[("mf_context", None)]),
+        "vaMFSubmit" => (&[("contexts", Some("num_contexts"))], &[]),
+        "vaQueryVideoProcPipelineCaps" => (&[("filters", Some("num_filters"))], &[]),
+        "vaCreateImage" | "vaDeriveImage" => {
+            if success {
+                resolve_image(call);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    let resolve = |call: &mut Call, (name, len): (&str, Option<&str>)| {
+        let len = match len {
+            Some(len) => match call.int(len) {
+                Some(len) => usize::try_from(len).unwrap_or(0),
+                None => return,
+            },
+            None => 1,
+        };
+        let Some((_, value)) = call.args.iter_mut().find(|(n, _)| *n == name) else {
+            return;
+        };
+        let TraceValue::Pointer(ptr) = *value else {
+            return;
+        };
+        if ptr == 0 {
+            return;
+        }
+        let ids = slice::from_raw_parts(ptr as *const VAGenericID, len);
+        *value = TraceValue::List(ids.iter().map(|id| id.trace()).collect());
+    };
+
+    for &arg in input {
+        resolve(call, arg);
+    }
+    if success {
+        for &arg in output {
+            resolve(call, arg);
+        }
+    }
+}
+
+unsafe fn resolve_image(call: &mut Call) {
+    let ptr = call.pointer("image");
+    if ptr == 0 {
+        return;
+    }
+    let image = &*(ptr as *const VAImage);
+    call.args
+        .push(("image_id", TraceValue::Int(image.image_id.into())));
+    call.args
+        .push(("image_buf", TraceValue::Int(image.buf.into())));
+}
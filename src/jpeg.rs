@@ -12,12 +12,15 @@ use bytemuck::{AnyBitPattern, Pod, Zeroable};
 use crate::{
     buffer::{Buffer, BufferType, Mapping},
     config::Config,
-    context::Context,
+    context::{BufferSlot, Context, PictureLayout},
     display::Display,
-    error::Error,
+    error::{Error, Retry},
     raw::{VA_PADDING_LOW, VA_PADDING_MEDIUM},
-    surface::{Surface, SurfaceWithImage},
-    vpp::{ColorProperties, ColorStandardType, ProcPipelineParameterBuffer, SourceRange},
+    surface::{Surface, SurfacePool, SurfaceWithImage},
+    vpp::{
+        ColorProperties, ColorStandardType, ProcPipelineParameterBuffer, SourceRange,
+        VideoProcPicture,
+    },
     Entrypoint, PixelFormat, Profile, Result, Rotation, SliceParameterBufferBase,
 };
 
@@ -346,6 +349,23 @@ impl HuffmanTable {
     }
 }
 
+/// [`PictureLayout`] of a baseline JPEG picture, decoded with [`Entrypoint::VLD`].
+pub struct JpegDecodePicture;
+
+unsafe impl PictureLayout for JpegDecodePicture {
+    const BUFFERS: &'static [BufferSlot] = &[
+        BufferSlot::required(BufferType::HuffmanTable),
+        BufferSlot::required(BufferType::IQMatrix),
+        BufferSlot::required(BufferType::PictureParameter),
+        BufferSlot::required(BufferType::SliceParameter),
+        BufferSlot::required(BufferType::SliceData),
+    ];
+
+    fn supports(profile: Profile, entrypoint: Entrypoint) -> bool {
+        profile == Profile::JPEGBaseline && entrypoint == Entrypoint::VLD
+    }
+}
+
 /// JPEG metadata required to create a VA-API JPEG decoding session.
 #[derive(Debug, Clone, Copy)]
 pub struct JpegInfo {
@@ -365,15 +385,15 @@ impl JpegInfo {
         let mut parser = JpegParser::new(&jpeg);
         let segment = parser
             .next_segment()?
-            .ok_or_else(|| Error::from("missing SOI segment"))?;
+            .ok_or_else(|| Error::invalid_input("missing SOI segment"))?;
         if !matches!(segment.kind, parser::SegmentKind::Soi) {
-            return Err(Error::from("missing SOI segment"));
+            return Err(Error::invalid_input("missing SOI segment"));
         }
 
         let sof = loop {
             let segment = parser
                 .next_segment()?
-                .ok_or_else(|| Error::from("missing SOF segment"))?;
+                .ok_or_else(|| Error::invalid_input("missing SOF segment"))?;
             match segment.kind {
                 SegmentKind::Sof(sof) => break sof,
                 _ => {}
@@ -381,13 +401,13 @@ impl JpegInfo {
         };
 
         if sof.sof() != SofMarker::SOF0 {
-            return Err(Error::from(format!(
+            return Err(Error::invalid_input(format!(
                 "not a baseline JPEG ({:?})",
                 sof.sof()
             )));
         }
         if sof.P() != 8 {
-            return Err(Error::from(format!(
+            return Err(Error::invalid_input(format!(
                 "unsupported sample precision of {} bits (only 8-bit samples are supported)",
                 sof.P()
             )));
@@ -410,6 +430,9 @@ impl JpegInfo {
     }
 }
 
+/// Format of the surface that JPEGs are decoded into, before they are converted to RGBA.
+const JPEG_SURFACE_FORMAT: PixelFormat = PixelFormat::NV12;
+
 /// A VA-API JPEG decoding session.
 ///
 /// This type encapsulates [`Surface`]s and [`Context`]s for decoding baseline JPEG files of a
@@ -418,13 +441,20 @@ pub struct JpegDecodeSession {
     width: u32,
     height: u32,
 
-    jpeg_surface: Surface,
+    jpeg_surface: JpegSurface,
     vpp_surface: SurfaceWithImage,
 
     jpeg_context: Context,
     vpp_context: Context,
 }
 
+/// The surface a [`JpegDecodeSession`] decodes into.
+enum JpegSurface {
+    Owned(Surface),
+    /// A surface is leased from the pool for every [`JpegDecodeSession::decode`] call.
+    Pooled(SurfacePool),
+}
+
 impl JpegDecodeSession {
     /// Creates [`Surface`]s and [`Context`]s to decode JPEG images of the given size.
     ///
@@ -434,6 +464,33 @@ impl JpegDecodeSession {
     /// that the implementation does not support JPEG decoding, but it can also indicate that the
     /// JPEG is simply too large and smaller ones would work.
     pub fn new(display: &Display, width: u16, height: u16) -> Result<Self> {
+        Self::create(display, width, height, None)
+    }
+
+    /// Creates a session that decodes into surfaces leased from `pool`.
+    ///
+    /// The decoding surface is only leased for the duration of each [`JpegDecodeSession::decode`]
+    /// call, so sessions of different sizes can share the surfaces of a single pool. Only the
+    /// surface holding the converted RGBA image is owned by the session.
+    ///
+    /// # Errors
+    ///
+    /// See [`JpegDecodeSession::new`].
+    pub fn with_pool(
+        display: &Display,
+        pool: &SurfacePool,
+        width: u16,
+        height: u16,
+    ) -> Result<Self> {
+        Self::create(display, width, height, Some(pool))
+    }
+
+    fn create(
+        display: &Display,
+        width: u16,
+        height: u16,
+        pool: Option<&SurfacePool>,
+    ) -> Result<Self> {
         let width = u32::from(width);
         let height = u32::from(height);
 
@@ -442,12 +499,15 @@ impl JpegDecodeSession {
         let config = Config::new(&display, Profile::None, Entrypoint::VideoProc)?;
         let vpp_context = Context::new(&config, width, height)?;
 
-        let jpeg_surface = Surface::new(
-            &display,
-            width,
-            height,
-            PixelFormat::NV12.to_rtformat().unwrap(),
-        )?;
+        let jpeg_surface = match pool {
+            Some(pool) => JpegSurface::Pooled(pool.clone()),
+            None => JpegSurface::Owned(Surface::new(
+                &display,
+                width,
+                height,
+                JPEG_SURFACE_FORMAT.to_rtformat().unwrap(),
+            )?),
+        };
         let vpp_surface = SurfaceWithImage::new(&display, width, height, PixelFormat::RGBA)?;
 
         log::debug!("image format = {:?}", vpp_surface.image());
@@ -469,12 +529,16 @@ impl JpegDecodeSession {
     /// # Errors
     ///
     /// This method returns an error when the JPEG is malformed or VA-API returns an error during
-    /// decoding.
+    /// decoding. Malformed and unsupported JPEGs result in errors for which
+    /// [`Error::is_invalid_input`] returns `true`, so that the caller can fall back to a software
+    /// decoder for that image. [Transient][Error::is_transient] errors are retried internally,
+    /// except when submitting a picture to the driver fails. Those are returned, and the decode
+    /// can be retried by calling this method again.
     pub fn decode(&mut self, jpeg: &[u8]) -> Result<Mapping<'_, u8>> {
         // TODO make this more flexible and move to `error` module
         macro_rules! bail {
             ($($args:tt)*) => {
-                return Err(Error::from(format!(
+                return Err(Error::invalid_input(format!(
                     $($args)*
                 )))
             };
@@ -588,24 +652,47 @@ impl JpegDecodeSession {
         let Some(ppbuf) = ppbuf else { bail!("file is missing SOI segment") };
         let Some((slice_params, slice_data)) = slice else { bail!("file is missing SOS header") };
 
-        let mut buf_dht = Buffer::new_param(&self.jpeg_context, BufferType::HuffmanTable, dhtbuf)?;
-        let mut buf_iq = Buffer::new_param(&self.jpeg_context, BufferType::IQMatrix, iqbuf)?;
-        let mut buf_pp =
-            Buffer::new_param(&self.jpeg_context, BufferType::PictureParameter, ppbuf)?;
-        let mut buf_slice_param =
-            Buffer::new_param(&self.jpeg_context, BufferType::SliceParameter, slice_params)?;
-        let mut buf_slice_data =
-            Buffer::new_data(&self.jpeg_context, BufferType::SliceData, &slice_data)?;
-
-        let mut picture = self.jpeg_context.begin_picture(&mut self.jpeg_surface)?;
-        picture.render_picture(&mut buf_dht)?;
-        picture.render_picture(&mut buf_iq)?;
-        picture.render_picture(&mut buf_pp)?;
-        picture.render_picture(&mut buf_slice_param)?;
-        picture.render_picture(&mut buf_slice_data)?;
-        unsafe { picture.end_picture()? }
-
-        let mut pppbuf = ProcPipelineParameterBuffer::new(&self.jpeg_surface);
+        // A pool may be exhausted, or the hardware or a surface may be busy, so retry the steps
+        // that can be repeated. Submitting a picture can't be retried, since the driver may have
+        // already started processing it.
+        let retry = Retry::default();
+        let lease;
+        let jpeg_surface: &Surface = match &self.jpeg_surface {
+            JpegSurface::Owned(surface) => surface,
+            JpegSurface::Pooled(pool) => {
+                lease = retry.run(|| {
+                    pool.get(
+                        self.width,
+                        self.height,
+                        JPEG_SURFACE_FORMAT.to_rtformat().unwrap(),
+                        &[],
+                    )
+                })?;
+                &lease
+            }
+        };
+        let jpeg_context = &self.jpeg_context;
+        let buf_dht =
+            retry.run(|| Buffer::new_param(jpeg_context, BufferType::HuffmanTable, dhtbuf))?;
+        let buf_iq = retry.run(|| Buffer::new_param(jpeg_context, BufferType::IQMatrix, iqbuf))?;
+        let buf_pp =
+            retry.run(|| Buffer::new_param(jpeg_context, BufferType::PictureParameter, ppbuf))?;
+        let buf_slice_param = retry
+            .run(|| Buffer::new_param(jpeg_context, BufferType::SliceParameter, slice_params))?;
+        let buf_slice_data =
+            retry.run(|| Buffer::new_data(jpeg_context, BufferType::SliceData, &slice_data))?;
+
+        let mut picture = self
+            .jpeg_context
+            .build_picture::<JpegDecodePicture>(jpeg_surface)?;
+        picture.add_buffer(buf_dht)?;
+        picture.add_buffer(buf_iq)?;
+        picture.add_buffer(buf_pp)?;
+        picture.add_buffer(buf_slice_param)?;
+        picture.add_buffer(buf_slice_data)?;
+        picture.submit()?;
+
+        let mut pppbuf = ProcPipelineParameterBuffer::new(jpeg_surface);
         // The input color space is the JPEG color space
         let input_props = ColorProperties::new().with_color_range(SourceRange::FULL);
         pppbuf.set_input_color_properties(input_props);
@@ -615,15 +702,20 @@ impl JpegDecodeSession {
         pppbuf.set_output_color_properties(output_props);
         pppbuf.set_output_color_standard(ColorStandardType::SRGB);
 
-        let mut pppbuf =
-            Buffer::new_param(&self.vpp_context, BufferType::ProcPipelineParameter, pppbuf)?;
-
-        let mut picture = self.vpp_context.begin_picture(&mut self.vpp_surface)?;
-        picture.render_picture(&mut pppbuf)?;
-        unsafe { picture.end_picture()? }
-
-        drop(pppbuf);
-
+        let pppbuf = retry.run(|| {
+            Buffer::new_param(&self.vpp_context, BufferType::ProcPipelineParameter, pppbuf)
+        })?;
+        let mut picture = self
+            .vpp_context
+            .build_picture::<VideoProcPicture>(&self.vpp_surface)?;
+        picture.add_buffer(pppbuf)?;
+        picture.submit()?;
+
+        // `sync` retries on its own. The decode finishes before the conversion does, so querying
+        // the status afterwards releases the decoder's buffers. If that fails, they are released
+        // the next time the surface is used instead.
+        self.vpp_surface.sync()?;
+        jpeg_surface.status().ok();
         self.vpp_surface.map_sync()
     }
 }
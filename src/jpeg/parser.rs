@@ -28,7 +28,7 @@ impl<'a> JpegParser<'a> {
         let marker = self.reader.read_u8()?;
 
         let kind = match marker {
-            0x00 => return Err(Error::from("invalid ff 00 marker".to_string())),
+            0x00 => return Err(Error::invalid_input("invalid ff 00 marker")),
             0xD8 => SegmentKind::Soi,
             0xD9 => SegmentKind::Eoi,
             0xDB => SegmentKind::Dqt(self.read_dqt()?),
@@ -155,8 +155,8 @@ impl<'a> Reader<'a> {
 
     fn peek_u8(&self, offset: usize) -> Result<u8> {
         if self.position + offset >= self.buf.len() {
-            Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
+            Err(Error::invalid_input(
+                "reached end of data while decoding JPEG stream",
             ))
         } else {
             let byte = self.buf[self.position + offset];
@@ -179,8 +179,8 @@ impl<'a> Reader<'a> {
 
     fn read_slice(&mut self, count: usize) -> Result<&'a [u8]> {
         if self.remaining().len() < count {
-            Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
+            Err(Error::invalid_input(
+                "reached end of data while decoding JPEG stream",
             ))
         } else {
             let slice = &self.remaining()[..count];
@@ -193,8 +193,8 @@ impl<'a> Reader<'a> {
         assert_eq!(mem::align_of::<T>(), 1);
 
         if self.remaining().len() < mem::size_of::<T>() {
-            return Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
+            return Err(Error::invalid_input(
+                "reached end of data while decoding JPEG stream",
             ));
         }
 
@@ -221,7 +221,9 @@ impl<'a> Reader<'a> {
     fn read_length(&mut self) -> Result<u16> {
         let len = self.read_u16()?;
         if len < 2 {
-            return Err(Error::from(format!("invalid segment length {len}")));
+            return Err(Error::invalid_input(format!(
+                "invalid segment length {len}"
+            )));
         }
         Ok(len)
     }
@@ -229,8 +231,8 @@ impl<'a> Reader<'a> {
     fn read_segment(&mut self) -> Result<Reader<'a>> {
         let len = usize::from(self.read_length()?) - 2;
         if self.remaining().len() < len {
-            return Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
+            return Err(Error::invalid_input(
+                "reached end of data while decoding JPEG stream",
             ));
         }
 
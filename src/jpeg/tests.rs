@@ -2,7 +2,15 @@ use std::{error::Error, fmt::Write, fs};
 
 use expect_test::{expect_file, ExpectFile};
 
-use super::parser::JpegParser;
+use crate::{
+    backend::{FakeBackend, LiveObjects},
+    buffer::BufferType,
+    display::Display,
+    error::{ErrorKind, VAError},
+    surface::SurfacePool,
+};
+
+use super::{parser::JpegParser, JpegDecodeSession, JpegInfo};
 
 fn check(filename: &str, expect: ExpectFile) {
     let bytes = std::fs::read(format!("src/jpeg/test-images/{filename}")).unwrap();
@@ -41,3 +49,93 @@ fn do_reftests() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn decode_session() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+
+    let jpeg = fs::read("src/jpeg/test-images/grayscale_square.jpg").unwrap();
+    let info = JpegInfo::new(&jpeg).unwrap();
+    let mut session = JpegDecodeSession::new(&display, info.width(), info.height()).unwrap();
+    let mapping = session.decode(&jpeg).unwrap();
+    assert!(mapping.len() >= usize::from(info.width()) * usize::from(info.height()) * 4);
+    drop(mapping);
+
+    let submissions = fake.submissions();
+    assert_eq!(submissions.len(), 2);
+    assert_eq!(
+        submissions[0].buffer_types(),
+        [
+            BufferType::HuffmanTable,
+            BufferType::IQMatrix,
+            BufferType::PictureParameter,
+            BufferType::SliceParameter,
+            BufferType::SliceData,
+        ]
+    );
+    assert_eq!(
+        submissions[1].buffer_types(),
+        [BufferType::ProcPipelineParameter]
+    );
+    // The video processing step reads from the JPEG decode target.
+    assert_eq!(
+        submissions[1].buffers()[0].data()[..4],
+        submissions[0].target().to_ne_bytes()
+    );
+
+    // Transient failures are retried, unless the picture was already passed to the driver.
+    fake.fail_next("vaCreateBuffer", VAError::ERROR_HW_BUSY);
+    fake.fail_next("vaSyncSurface", VAError::ERROR_SURFACE_BUSY);
+    session.decode(&jpeg).unwrap();
+    let submissions = fake.submissions();
+    assert_eq!(submissions.len(), 4);
+    assert_eq!(submissions[2].buffers().len(), 5);
+
+    fake.fail_next("vaEndPicture", VAError::ERROR_HW_BUSY);
+    let err = session.decode(&jpeg).err().unwrap();
+    assert!(err.is_transient());
+    assert_eq!(fake.submissions().len(), 4);
+    // The picture that failed to end is discarded when the next one is begun.
+    session.decode(&jpeg).unwrap();
+    assert_eq!(fake.submissions().len(), 6);
+    assert_eq!(fake.abandoned_pictures(), 1);
+
+    // Malformed input is reported as such.
+
+    let err = session.decode(&jpeg[..jpeg.len() / 2]).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(err.is_invalid_input());
+    assert!(!err.is_transient());
+
+    drop(session);
+    assert_eq!(fake.live_objects(), LiveObjects::default());
+}
+
+#[test]
+fn decode_session_pool() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let pool = SurfacePool::new(&display, 1).with_batch_size(1);
+
+    // Sessions of different sizes take turns using the pool's only surface.
+    let mut sessions = ["grayscale_square.jpg", "grayscale_16x24_sampling2x2.jpg"].map(|name| {
+        let jpeg = fs::read(format!("src/jpeg/test-images/{name}")).unwrap();
+        let info = JpegInfo::new(&jpeg).unwrap();
+        let session =
+            JpegDecodeSession::with_pool(&display, &pool, info.width(), info.height()).unwrap();
+        (session, jpeg)
+    });
+    for (session, jpeg) in &mut sessions {
+        session.decode(jpeg).unwrap();
+        assert_eq!(pool.allocated(), 1);
+        assert_eq!(pool.free(), 1);
+    }
+    // Every session owns its RGBA surface.
+    assert_eq!(fake.live_objects().surfaces, 3);
+    assert_eq!(fake.submissions().len(), 4);
+
+    drop(sessions);
+    drop(pool);
+    assert_eq!(fake.live_objects(), LiveObjects::default());
+}
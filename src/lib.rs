@@ -5,25 +5,36 @@
 #[macro_use]
 mod macros;
 mod dlopen;
+mod intercept;
+mod native;
 mod pixelformat;
+mod profile;
 mod raw;
+mod wait;
 
+pub mod backend;
 pub mod buffer;
+pub mod capabilities;
 pub mod config;
 pub mod context;
 pub mod display;
+pub mod drm;
 pub mod error;
 pub mod image;
 pub mod jpeg;
+pub mod loader;
+pub mod replay;
 pub mod subpicture;
 pub mod surface;
+pub mod trace;
 pub mod vpp;
 
 pub use pixelformat::PixelFormat;
+pub use profile::{ChromaFormat, Codec, EntrypointKind};
 
 use std::{ffi::c_int, vec};
 
-use error::{Error, VAError, VAStatus};
+use error::{Error, Object, VAError, VAStatus};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -222,17 +233,21 @@ impl IntoIterator for Entrypoints {
     }
 }
 
-fn check(status: VAStatus) -> Result<()> {
+/// Turns the `status` returned by the libva function `function` into a [`Result`].
+///
+/// `objects` are the libva objects passed to the function, and are included in the error.
+fn check(status: VAStatus, function: &'static str, objects: &[Object]) -> Result<()> {
     if status == VAStatus::SUCCESS {
         Ok(())
     } else {
-        Err(Error::from(VAError(status.0)))
+        Err(Error::libva(VAError(status.0), function, objects))
     }
 }
 
-fn check_log(status: VAStatus, location: &'static str) {
-    match check(status) {
+/// Logs the error returned by `function`, if any. Used in [`Drop`] impls.
+fn check_log(status: VAStatus, function: &'static str, objects: &[Object]) {
+    match check(status, function, objects) {
         Ok(()) => {}
-        Err(e) => log::error!("ignoring error in {location}: {e}"),
+        Err(e) => log::error!("ignoring error in drop: {e}"),
     }
 }
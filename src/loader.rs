@@ -0,0 +1,238 @@
+//! Configuration of how the *libva* shared libraries are located.
+//!
+//! By default, the libraries are loaded by their unversioned file name (eg. `libva.so`), falling
+//! back to the versioned soname (eg. `libva.so.2`) if that fails, using the system's library
+//! search path.
+//!
+//! Applications that bundle their own libva build, or want to pin a specific version, can
+//! configure this with a [`LoaderConfig`], which has to be applied before the first
+//! [`Display`][crate::display::Display] is created.
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, c_void, CStr, OsStr},
+    mem,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    dlopen::{libva, libva_drm, libva_wayland, libva_x11},
+    Error, Result,
+};
+
+/// The soname major version of libva 2.x.
+const DEFAULT_SONAME_VERSION: u32 = 2;
+
+/// Shared by [`LoaderConfig::apply`] and [`open`], so that a configuration can't be applied while a
+/// library is being loaded.
+static STATE: Mutex<LoaderState> = Mutex::new(LoaderState {
+    config: None,
+    opened: Vec::new(),
+});
+
+struct LoaderState {
+    config: Option<LoaderConfig>,
+    /// Libraries that were successfully opened with the active configuration.
+    opened: Vec<Library>,
+}
+
+/// The shared libraries that make up *libva*.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Library {
+    /// The core library, `libva.so`.
+    Libva,
+    /// The X11 integration, `libva-x11.so`.
+    X11,
+    /// The Wayland integration, `libva-wayland.so`.
+    Wayland,
+    /// The DRM integration, `libva-drm.so`.
+    Drm,
+}
+
+impl Library {
+    /// Returns the unversioned file name of the library.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Library::Libva => "libva.so",
+            Library::X11 => "libva-x11.so",
+            Library::Wayland => "libva-wayland.so",
+            Library::Drm => "libva-drm.so",
+        }
+    }
+
+    /// Returns the path of the file this library was loaded from, or [`None`] if it hasn't been
+    /// loaded (yet).
+    pub fn loaded_path(self) -> Option<&'static Path> {
+        match self {
+            Library::Libva => libva::loaded().map(|lib| lib.path()),
+            Library::X11 => libva_x11::loaded().map(|lib| lib.path()),
+            Library::Wayland => libva_wayland::loaded().map(|lib| lib.path()),
+            Library::Drm => libva_drm::loaded().map(|lib| lib.path()),
+        }
+    }
+}
+
+/// Configures where the *libva* libraries are loaded from.
+///
+/// # Example
+///
+/// ```no_run
+/// use v_ayylmao::loader::LoaderConfig;
+///
+/// LoaderConfig::new()
+///     .search_path("/opt/myapp/lib")
+///     .soname_version(2)
+///     .apply()?;
+/// # Ok::<_, v_ayylmao::error::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LoaderConfig {
+    search_paths: Vec<PathBuf>,
+    soname_version: Option<u32>,
+    paths: HashMap<Library, PathBuf>,
+}
+
+impl LoaderConfig {
+    /// Creates a [`LoaderConfig`] that uses the default behavior.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a directory to search for the libraries.
+    ///
+    /// Search paths are tried in the order they were added, before falling back to the system's
+    /// library search path.
+    pub fn search_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(dir.into());
+        self
+    }
+
+    /// Only loads the libraries by their versioned soname with the given major version (eg.
+    /// `libva.so.2`), instead of trying the unversioned name first.
+    pub fn soname_version(mut self, major: u32) -> Self {
+        self.soname_version = Some(major);
+        self
+    }
+
+    /// Loads `library` from an explicit `path`.
+    ///
+    /// This overrides the search paths and soname version for that library.
+    pub fn library_path(mut self, library: Library, path: impl Into<PathBuf>) -> Self {
+        self.paths.insert(library, path.into());
+        self
+    }
+
+    /// Makes this configuration the one used for loading the libraries.
+    ///
+    /// # Errors
+    ///
+    /// Libraries are only loaded once, so this returns an error if any of them has already been
+    /// loaded (typically because a [`Display`][crate::display::Display] was already created).
+    /// If another thread is loading a library concurrently, this waits until it is done.
+    pub fn apply(self) -> Result<()> {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.opened.is_empty() {
+            return Err(Error::from(format!(
+                "cannot change loader configuration, libraries have already been loaded: {:?}",
+                state.opened
+            )));
+        }
+        state.config = Some(self);
+        Ok(())
+    }
+
+    /// Returns the list of paths to try, in order, when loading `library`.
+    fn candidates(&self, library: Library) -> Vec<PathBuf> {
+        if let Some(path) = self.paths.get(&library) {
+            return vec![path.clone()];
+        }
+
+        let file_names = match self.soname_version {
+            Some(major) => vec![format!("{}.{major}", library.file_name())],
+            None => vec![
+                library.file_name().to_string(),
+                format!("{}.{DEFAULT_SONAME_VERSION}", library.file_name()),
+            ],
+        };
+
+        let mut candidates = Vec::new();
+        for file_name in &file_names {
+            for dir in &self.search_paths {
+                candidates.push(dir.join(file_name));
+            }
+        }
+        candidates.extend(file_names.into_iter().map(PathBuf::from));
+        candidates
+    }
+}
+
+/// Opens `library` and resolves its symbols with `load`, trying all paths configured by the active
+/// [`LoaderConfig`] in order.
+///
+/// Paths that can't be opened, or that `load` fails on, are skipped. The library that `load`
+/// succeeds on is never unloaded, since the returned value refers to its symbols.
+///
+/// The configuration stays locked while loading, so that it can't be changed halfway.
+pub(crate) fn open<T>(
+    library: Library,
+    mut load: impl FnMut(&libloading::Library) -> Result<T, libloading::Error>,
+) -> Result<T, libloading::Error> {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let candidates = state
+        .config
+        .get_or_insert_with(LoaderConfig::default)
+        .candidates(library);
+
+    let mut last_error = None;
+    for path in candidates {
+        let lib = match unsafe { libloading::Library::new(&path) } {
+            Ok(lib) => lib,
+            Err(e) => {
+                log::debug!("failed to load {}: {e}", path.display());
+                last_error = Some(e);
+                continue;
+            }
+        };
+        match load(&lib) {
+            Ok(value) => {
+                mem::forget(lib);
+                if !state.opened.contains(&library) {
+                    state.opened.push(library);
+                }
+                return Ok(value);
+            }
+            Err(e) => {
+                log::debug!("failed to resolve symbols of {}: {e}", path.display());
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.expect("no loader candidates"))
+}
+
+/// Returns the path of the shared object containing `addr`.
+pub(crate) fn object_path(addr: *const c_void) -> Option<PathBuf> {
+    #[repr(C)]
+    struct DlInfo {
+        dli_fname: *const c_char,
+        dli_fbase: *mut c_void,
+        dli_sname: *const c_char,
+        dli_saddr: *mut c_void,
+    }
+
+    extern "C" {
+        fn dladdr(addr: *const c_void, info: *mut DlInfo) -> c_int;
+    }
+
+    unsafe {
+        let mut info: DlInfo = std::mem::zeroed();
+        if dladdr(addr, &mut info) == 0 || info.dli_fname.is_null() {
+            return None;
+        }
+        let bytes = CStr::from_ptr(info.dli_fname).to_bytes();
+        Some(PathBuf::from(OsStr::from_bytes(bytes)))
+    }
+}
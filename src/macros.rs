@@ -21,20 +21,72 @@ macro_rules! ffi_enum {
                 $( #[$variant_attrs] )*
                 $v const $variant: Self = Self($value);
             )+
+
+            /// All known values, in declaration order.
+            #[allow(dead_code)]
+            pub(crate) const ALL: &'static [Self] = &[$(Self::$variant),+];
         }
 
-        #[allow(unreachable_patterns)]
-        impl std::fmt::Debug for $name {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        #[allow(dead_code)]
+        impl $name {
+            /// Returns an iterator over all values known to this library, in declaration order.
+            $v fn iter() -> impl Iterator<Item = Self> {
+                Self::ALL.iter().copied()
+            }
+
+            /// Returns the name of this value, or [`None`] if it is not known to this library.
+            #[allow(unreachable_patterns)]
+            $v fn name(&self) -> Option<&'static str> {
                 match *self {
                     $(
-                        Self::$variant => f.pad(stringify!($variant)),
+                        Self::$variant => Some(stringify!($variant)),
                     )+
 
-                    _ => write!(f, "(unknown: {:#x})", self.0),
+                    _ => None,
+                }
+            }
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self.name() {
+                    Some(name) => f.pad(name),
+                    None => write!(f, "(unknown: {:#x})", self.0),
+                }
+            }
+        }
+
+        /// Formats the name of the value, or the raw value if it is unknown.
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self.name() {
+                    Some(name) => f.pad(name),
+                    None => self.0.fmt(f),
+                }
+            }
+        }
+
+        /// Parses the name of a value (ignoring ASCII case), or a raw value.
+        impl std::str::FromStr for $name {
+            type Err = crate::Error;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                if let Some(&value) = Self::ALL
+                    .iter()
+                    .find(|value| value.name().is_some_and(|name| name.eq_ignore_ascii_case(s)))
+                {
+                    return Ok(value);
                 }
+                s.parse::<$native>().map(Self).map_err(|_| {
+                    crate::Error::invalid_input(format!(
+                        "unknown {} `{s}`",
+                        stringify!($name)
+                    ))
+                })
             }
         }
+
+        impl_serde!($name: $native, |this| this.0, |raw| Self(raw));
     };
 }
 
@@ -43,10 +95,48 @@ macro_rules! ffi_enum {
 ///
 /// bitflags! {}
 macro_rules! bitflags {
-    ($($t:tt)*) => {
+    (
+        $( #[$attrs:meta] )*
+        $v:vis struct $name:ident: $native:ty {
+            $($body:tt)*
+        }
+    ) => {
         bitflags::bitflags! {
             #[repr(transparent)]
-            $($t)*
+            $( #[$attrs] )*
+            $v struct $name: $native {
+                $($body)*
+            }
+        }
+
+        impl_serde!($name: $native, |this| this.bits(), |raw| Self::from_bits_truncate(raw));
+    };
+}
+
+/// Implements `Serialize` and `Deserialize` for a type that is represented by a single integer, if
+/// the `serde` feature is enabled.
+///
+/// The value is (de)serialized as the raw integer, like libva represents it.
+macro_rules! impl_serde {
+    ($name:ident: $native:ty, |$this:ident| $to_raw:expr, |$raw:ident| $from_raw:expr) => {
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                let $this = self;
+                serde::Serialize::serialize(&$to_raw, serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                <$native as serde::Deserialize>::deserialize(deserializer).map(|$raw| $from_raw)
+            }
         }
     };
 }
@@ -0,0 +1,109 @@
+//! Connections to display servers, used by [`Display::open_default`][crate::display::Display::open_default].
+//!
+//! The client libraries are loaded at runtime, so that they're only required when actually used.
+
+use std::{
+    ffi::{c_char, c_int, c_void},
+    ptr::{self, NonNull},
+};
+
+use libloading::Library;
+use raw_window_handle::{
+    HasRawDisplayHandle, RawDisplayHandle, WaylandDisplayHandle, XlibDisplayHandle,
+};
+
+use crate::{Error, Result};
+
+/// A connection to the Wayland compositor specified by `WAYLAND_DISPLAY`.
+pub(crate) struct WaylandConnection {
+    display: NonNull<c_void>,
+    disconnect: unsafe extern "C" fn(*mut c_void),
+    _lib: Library,
+}
+
+impl WaylandConnection {
+    pub(crate) fn connect() -> Result<Self> {
+        unsafe {
+            let lib = Library::new("libwayland-client.so.0").map_err(Error::from)?;
+            let connect = *lib
+                .get::<unsafe extern "C" fn(*const c_char) -> *mut c_void>(b"wl_display_connect\0")
+                .map_err(Error::from)?;
+            let disconnect = *lib
+                .get::<unsafe extern "C" fn(*mut c_void)>(b"wl_display_disconnect\0")
+                .map_err(Error::from)?;
+
+            let display = NonNull::new(connect(ptr::null()))
+                .ok_or_else(|| Error::from("failed to connect to the Wayland compositor"))?;
+            Ok(Self {
+                display,
+                disconnect,
+                _lib: lib,
+            })
+        }
+    }
+}
+
+impl Drop for WaylandConnection {
+    fn drop(&mut self) {
+        unsafe { (self.disconnect)(self.display.as_ptr()) }
+    }
+}
+
+unsafe impl HasRawDisplayHandle for WaylandConnection {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        let mut handle = WaylandDisplayHandle::empty();
+        handle.display = self.display.as_ptr();
+        RawDisplayHandle::Wayland(handle)
+    }
+}
+
+/// A connection to the X server specified by `DISPLAY`.
+pub(crate) struct XlibConnection {
+    display: NonNull<c_void>,
+    screen: c_int,
+    close: unsafe extern "C" fn(*mut c_void) -> c_int,
+    _lib: Library,
+}
+
+impl XlibConnection {
+    pub(crate) fn connect() -> Result<Self> {
+        unsafe {
+            let lib = Library::new("libX11.so.6").map_err(Error::from)?;
+            let open = *lib
+                .get::<unsafe extern "C" fn(*const c_char) -> *mut c_void>(b"XOpenDisplay\0")
+                .map_err(Error::from)?;
+            let default_screen = *lib
+                .get::<unsafe extern "C" fn(*mut c_void) -> c_int>(b"XDefaultScreen\0")
+                .map_err(Error::from)?;
+            let close = *lib
+                .get::<unsafe extern "C" fn(*mut c_void) -> c_int>(b"XCloseDisplay\0")
+                .map_err(Error::from)?;
+
+            let display = NonNull::new(open(ptr::null()))
+                .ok_or_else(|| Error::from("failed to connect to the X server"))?;
+            Ok(Self {
+                display,
+                screen: default_screen(display.as_ptr()),
+                close,
+                _lib: lib,
+            })
+        }
+    }
+}
+
+impl Drop for XlibConnection {
+    fn drop(&mut self) {
+        unsafe {
+            (self.close)(self.display.as_ptr());
+        }
+    }
+}
+
+unsafe impl HasRawDisplayHandle for XlibConnection {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        let mut handle = XlibDisplayHandle::empty();
+        handle.display = self.display.as_ptr();
+        handle.screen = self.screen;
+        RawDisplayHandle::Xlib(handle)
+    }
+}
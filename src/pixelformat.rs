@@ -7,6 +7,8 @@ use crate::surface::RTFormat;
 #[repr(transparent)]
 pub struct PixelFormat(u32);
 
+impl_serde!(PixelFormat: u32, |this| this.0, |raw| Self(raw));
+
 impl PixelFormat {
     /// Planar YUV 4:2:0 standard pixel format.
     ///
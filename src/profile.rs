@@ -0,0 +1,212 @@
+//! Metadata about [`Profile`]s and [`Entrypoint`]s.
+
+use std::{fmt, str::FromStr};
+
+use crate::{Entrypoint, Error, Profile};
+
+#[cfg(test)]
+mod tests;
+
+/// A codec family, grouping related [`Profile`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Codec {
+    MPEG2,
+    MPEG4,
+    H263,
+    H264,
+    VC1,
+    JPEG,
+    VP8,
+    VP9,
+    HEVC,
+    AV1,
+}
+
+impl Codec {
+    const ALL: &'static [Self] = &[
+        Self::MPEG2,
+        Self::MPEG4,
+        Self::H263,
+        Self::H264,
+        Self::VC1,
+        Self::JPEG,
+        Self::VP8,
+        Self::VP9,
+        Self::HEVC,
+        Self::AV1,
+    ];
+
+    /// Returns an iterator over all codecs.
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Returns an iterator over all [`Profile`]s of this codec.
+    pub fn profiles(self) -> impl Iterator<Item = Profile> {
+        Profile::iter().filter(move |profile| profile.codec() == Some(self))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::MPEG2 => "MPEG2",
+            Self::MPEG4 => "MPEG4",
+            Self::H263 => "H263",
+            Self::H264 => "H264",
+            Self::VC1 => "VC1",
+            Self::JPEG => "JPEG",
+            Self::VP8 => "VP8",
+            Self::VP9 => "VP9",
+            Self::HEVC => "HEVC",
+            Self::AV1 => "AV1",
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Parses the name of a codec, ignoring ASCII case.
+impl FromStr for Codec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::iter()
+            .find(|codec| codec.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::invalid_input(format!("unknown Codec `{s}`")))
+    }
+}
+
+/// A chroma subsampling scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChromaFormat {
+    /// Monochrome (luma only).
+    Yuv400,
+    Yuv420,
+    Yuv422,
+    Yuv440,
+    Yuv444,
+}
+
+/// What kind of operation an [`Entrypoint`] performs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EntrypointKind {
+    Decode,
+    Encode,
+    /// Video processing (see [`crate::vpp`]).
+    Processing,
+    /// Content protection, and unknown entrypoints.
+    Other,
+}
+
+impl Profile {
+    /// Returns the codec this profile belongs to.
+    ///
+    /// Returns [`None`] for [`Profile::None`], [`Profile::Protected`] and unknown profiles.
+    pub fn codec(self) -> Option<Codec> {
+        use Profile as P;
+        Some(match self {
+            P::MPEG2Simple | P::MPEG2Main => Codec::MPEG2,
+            P::MPEG4Simple | P::MPEG4AdvancedSimple | P::MPEG4Main => Codec::MPEG4,
+            P::H263Baseline => Codec::H263,
+            P::H264Baseline
+            | P::H264Main
+            | P::H264High
+            | P::H264ConstrainedBaseline
+            | P::H264MultiviewHigh
+            | P::H264StereoHigh => Codec::H264,
+            P::VC1Simple | P::VC1Main | P::VC1Advanced => Codec::VC1,
+            P::JPEGBaseline => Codec::JPEG,
+            P::VP8Version0_3 => Codec::VP8,
+            P::VP9Profile0 | P::VP9Profile1 | P::VP9Profile2 | P::VP9Profile3 => Codec::VP9,
+            P::HEVCMain
+            | P::HEVCMain10
+            | P::HEVCMain12
+            | P::HEVCMain422_10
+            | P::HEVCMain422_12
+            | P::HEVCMain444
+            | P::HEVCMain444_10
+            | P::HEVCMain444_12
+            | P::HEVCSccMain
+            | P::HEVCSccMain10
+            | P::HEVCSccMain444
+            | P::HEVCSccMain444_10 => Codec::HEVC,
+            P::AV1Profile0 | P::AV1Profile1 => Codec::AV1,
+            _ => return None,
+        })
+    }
+
+    /// Returns the highest bit depth per sample this profile allows.
+    ///
+    /// Returns [`None`] if [`Profile::codec`] does.
+    pub fn max_bit_depth(self) -> Option<u32> {
+        use Profile as P;
+        self.codec()?;
+        Some(match self {
+            P::HEVCMain10
+            | P::HEVCMain422_10
+            | P::HEVCMain444_10
+            | P::HEVCSccMain10
+            | P::HEVCSccMain444_10
+            | P::AV1Profile0
+            | P::AV1Profile1 => 10,
+            P::HEVCMain12
+            | P::HEVCMain422_12
+            | P::HEVCMain444_12
+            | P::VP9Profile2
+            | P::VP9Profile3 => 12,
+            _ => 8,
+        })
+    }
+
+    /// Returns the chroma formats this profile allows.
+    ///
+    /// Returns an empty slice if [`Profile::codec`] returns [`None`].
+    pub fn chroma_formats(self) -> &'static [ChromaFormat] {
+        use ChromaFormat::*;
+        use Profile as P;
+        match self {
+            _ if self.codec().is_none() => &[],
+            P::H264High | P::HEVCMain12 | P::AV1Profile0 => &[Yuv400, Yuv420],
+            P::JPEGBaseline => &[Yuv400, Yuv420, Yuv422, Yuv444],
+            P::VP9Profile1 | P::VP9Profile3 => &[Yuv422, Yuv440, Yuv444],
+            P::HEVCMain422_10 | P::HEVCMain422_12 => &[Yuv400, Yuv420, Yuv422],
+            P::HEVCMain444
+            | P::HEVCMain444_10
+            | P::HEVCMain444_12
+            | P::HEVCSccMain444
+            | P::HEVCSccMain444_10 => &[Yuv400, Yuv420, Yuv422, Yuv444],
+            P::AV1Profile1 => &[Yuv400, Yuv420, Yuv444],
+            _ => &[Yuv420],
+        }
+    }
+}
+
+impl Entrypoint {
+    /// Returns what kind of operation this entrypoint performs.
+    pub fn kind(self) -> EntrypointKind {
+        use Entrypoint as E;
+        match self {
+            E::VLD | E::IZZ | E::IDCT | E::MoComp | E::Deblocking => EntrypointKind::Decode,
+            E::EncSlice | E::EncPicture | E::EncSliceLP | E::FEI | E::Stats => {
+                EntrypointKind::Encode
+            }
+            E::VideoProc => EntrypointKind::Processing,
+            _ => EntrypointKind::Other,
+        }
+    }
+
+    #[inline]
+    pub fn is_decode(self) -> bool {
+        self.kind() == EntrypointKind::Decode
+    }
+
+    #[inline]
+    pub fn is_encode(self) -> bool {
+        self.kind() == EntrypointKind::Encode
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{error::ErrorKind, Entrypoint, Profile};
+
+use super::{ChromaFormat, Codec, EntrypointKind};
+
+#[test]
+fn metadata() {
+    assert_eq!(Profile::HEVCMain10.codec(), Some(Codec::HEVC));
+    assert_eq!(Profile::HEVCMain10.max_bit_depth(), Some(10));
+    assert_eq!(Profile::HEVCMain10.chroma_formats(), [ChromaFormat::Yuv420]);
+    assert_eq!(Profile::None.codec(), None);
+    assert_eq!(Profile::None.max_bit_depth(), None);
+    assert_eq!(Profile::None.chroma_formats(), []);
+    assert!(Profile::JPEGBaseline
+        .chroma_formats()
+        .contains(&ChromaFormat::Yuv444));
+
+    // Every profile of a codec is listed once.
+    let av1 = Codec::AV1.profiles().collect::<Vec<_>>();
+    assert_eq!(av1, [Profile::AV1Profile0, Profile::AV1Profile1]);
+    let total: usize = Codec::iter().map(|codec| codec.profiles().count()).sum();
+    assert_eq!(
+        total,
+        Profile::iter().filter(|p| p.codec().is_some()).count()
+    );
+
+    assert_eq!(Entrypoint::VLD.kind(), EntrypointKind::Decode);
+    assert!(Entrypoint::EncSliceLP.is_encode());
+    assert_eq!(Entrypoint::VideoProc.kind(), EntrypointKind::Processing);
+    assert_eq!(Entrypoint(99).kind(), EntrypointKind::Other);
+}
+
+#[test]
+fn parse_and_display() {
+    for profile in Profile::iter() {
+        assert_eq!(profile.to_string().parse::<Profile>().unwrap(), profile);
+    }
+    for entrypoint in Entrypoint::iter() {
+        assert_eq!(
+            entrypoint.to_string().parse::<Entrypoint>().unwrap(),
+            entrypoint
+        );
+    }
+
+    assert_eq!(
+        "hevcmain10".parse::<Profile>().unwrap(),
+        Profile::HEVCMain10
+    );
+    assert_eq!(Profile::HEVCMain10.to_string(), "HEVCMain10");
+    assert_eq!(Profile(99).to_string(), "99");
+    assert_eq!("99".parse::<Profile>().unwrap(), Profile(99));
+    let err = "HEVCMain11".parse::<Profile>().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "unknown Profile `HEVCMain11`");
+
+    assert_eq!("av1".parse::<Codec>().unwrap(), Codec::AV1);
+    assert_eq!(Codec::AV1.to_string(), "AV1");
+    assert!("Theora".parse::<Codec>().is_err());
+}
@@ -41,8 +41,8 @@ pub struct VAProcessingRateParameterDec {
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub union VAProcessingRateParameter {
-    proc_buf_enc: VAProcessingRateParameterEnc,
-    proc_buf_dec: VAProcessingRateParameterDec,
+    pub proc_buf_enc: VAProcessingRateParameterEnc,
+    pub proc_buf_dec: VAProcessingRateParameterDec,
 }
 
 #[repr(C)]
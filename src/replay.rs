@@ -0,0 +1,918 @@
+//! Recording and replaying of submission sessions.
+//!
+//! A [`Recording`] captures the objects created through a [`Display`] and the work submitted to
+//! them: configs, surfaces and contexts along with their parameters, and the type and contents of
+//! every buffer passed to `vaRenderPicture`. The result is a [`Session`], which can be saved to a
+//! portable text file and replayed against any other [`Display`] (for example, to reproduce a
+//! driver bug on another machine, or to compare the output of two drivers).
+//!
+//! Buffer contents are copied when a buffer is created with `vaCreateBuffer`, and again whenever a
+//! mapping of it is released. Buffers whose contents are pointers cannot be replayed, with the
+//! exception of the input surface of [`ProcPipelineParameterBuffer`]s. Buffers whose contents
+//! weren't captured (for example, because they were created before the recording started) are
+//! recorded as missing, and a session containing them fails to replay.
+//!
+//! # Example
+//!
+//! ```
+//! use v_ayylmao::{
+//!     backend::FakeBackend,
+//!     buffer::{Buffer, BufferType},
+//!     config::Config,
+//!     context::Context,
+//!     display::Display,
+//!     replay::{Recording, Session},
+//!     surface::{RTFormat, Surface},
+//!     Entrypoint, Profile,
+//! };
+//!
+//! let recording = Recording::new();
+//! let display = Display::builder()
+//!     .record(recording.clone())
+//!     .open_backend(FakeBackend::new())?;
+//!
+//! let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD)?;
+//! let mut context = Context::new(&config, 64, 64)?;
+//! let surface = Surface::new(&display, 64, 64, RTFormat::YUV420)?;
+//! let buf = Buffer::new_data(&context, BufferType::SliceData, &[0xff, 0xd8])?;
+//! let mut picture = context.begin_picture(&surface)?;
+//! picture.render_picture(buf)?;
+//! unsafe { picture.end_picture()? }
+//!
+//! let text = recording.session().to_string();
+//!
+//! // Later, possibly on another machine:
+//! let session: Session = text.parse()?;
+//! let replay = session.replay(&Display::with_backend(FakeBackend::new())?)?;
+//! # Ok::<_, v_ayylmao::error::Error>(())
+//! ```
+//!
+//! [`ProcPipelineParameterBuffer`]: crate::vpp::ProcPipelineParameterBuffer
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashMap,
+    ffi::{c_int, c_void},
+    fmt::{self, Write as _},
+    fs, io,
+    path::Path,
+    slice,
+    str::{FromStr, SplitWhitespace},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    buffer::{BufferType, RawBuffer},
+    check,
+    config::{Config, ConfigAttrib, ConfigAttribType},
+    context::Context,
+    display::Display,
+    error::Object,
+    intercept::Call,
+    raw::VABufferID,
+    surface::{
+        GenericValue, RTFormat, Surface, SurfaceAttrib, SurfaceAttribFlags, SurfaceAttribType,
+        SurfaceOwner,
+    },
+    vpp::ProcPipelineParameterBuffer,
+    Entrypoint, Error, Profile, Result,
+};
+
+const HEADER: &str = "v-ayylmao session 1";
+
+/// An operation recorded in a [`Session`].
+///
+/// Object IDs are the ones assigned by the driver the session was recorded with.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A [`Config`] was created.
+    CreateConfig {
+        id: u32,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        attribs: Vec<(ConfigAttribType, u32)>,
+    },
+    /// One or more [`Surface`]s were created.
+    ///
+    /// Only attributes with integer values are recorded.
+    CreateSurfaces {
+        format: RTFormat,
+        width: u32,
+        height: u32,
+        ids: Vec<u32>,
+        attribs: Vec<(SurfaceAttribType, i32)>,
+    },
+    /// A [`Context`] was created.
+    CreateContext {
+        id: u32,
+        config: u32,
+        width: u32,
+        height: u32,
+        flag: i32,
+        render_targets: Vec<u32>,
+    },
+    /// `vaBeginPicture` was called.
+    BeginPicture { context: u32, target: u32 },
+    /// Buffers were submitted via `vaRenderPicture`.
+    ///
+    /// Buffers whose contents weren't captured are [`None`].
+    RenderPicture {
+        context: u32,
+        buffers: Vec<Option<RecordedBuffer>>,
+    },
+    /// `vaEndPicture` was called.
+    EndPicture { context: u32 },
+    /// A surface was synchronized.
+    SyncSurface { surface: u32 },
+    /// Surfaces were destroyed.
+    DestroySurfaces { ids: Vec<u32> },
+    /// A context was destroyed.
+    DestroyContext { id: u32 },
+    /// A config was destroyed.
+    DestroyConfig { id: u32 },
+}
+
+/// The contents of a buffer passed to `vaRenderPicture`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedBuffer {
+    ty: BufferType,
+    size: u32,
+    num_elements: u32,
+    data: Vec<u8>,
+}
+
+impl RecordedBuffer {
+    /// Returns the type of the buffer.
+    pub fn ty(&self) -> BufferType {
+        self.ty
+    }
+
+    /// Returns the size of each buffer element, in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the number of elements in the buffer.
+    pub fn num_elements(&self) -> u32 {
+        self.num_elements
+    }
+
+    /// Returns the raw contents of the buffer at the time it was submitted.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[derive(Default)]
+struct RecordingState {
+    events: Vec<Event>,
+    /// All live buffers, with their most recently captured contents.
+    buffers: HashMap<VABufferID, RecordedBuffer>,
+    /// Addresses of currently mapped buffers.
+    mapped: HashMap<VABufferID, usize>,
+}
+
+/// Records the operations performed on a [`Display`].
+///
+/// [`Recording`] is a cheaply clonable handle, so a clone can be passed to
+/// [`DisplayBuilder::record`][crate::display::DisplayBuilder::record] while the original is used
+/// to retrieve the [`Session`].
+#[derive(Clone, Default)]
+pub struct Recording {
+    state: Arc<Mutex<RecordingState>>,
+}
+
+impl Recording {
+    /// Creates an empty [`Recording`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a [`Session`] containing all operations recorded so far.
+    pub fn session(&self) -> Session {
+        Session {
+            events: self.lock().events.clone(),
+        }
+    }
+
+    /// Removes all recorded operations.
+    ///
+    /// Objects that are still alive remain known to the [`Recording`], but a [`Session`]
+    /// recorded after this call can only be replayed if it doesn't reference them.
+    pub fn clear(&self) {
+        self.lock().events.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RecordingState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Invoked before `call` is forwarded to the driver.
+    pub(crate) unsafe fn before(&self, call: &Call) {
+        if call.function != "vaUnmapBuffer" {
+            return;
+        }
+        // Capture the contents before the mapping goes away.
+        let mut state = self.lock();
+        let Some(id) = call
+            .int("buf_id")
+            .and_then(|id| VABufferID::try_from(id).ok())
+        else {
+            return;
+        };
+        let Some(&ptr) = state.mapped.get(&id) else {
+            return;
+        };
+        if let Some(buffer) = state.buffers.get_mut(&id) {
+            if ptr != 0 && !buffer.data.is_empty() {
+                let len = buffer.data.len();
+                buffer
+                    .data
+                    .copy_from_slice(slice::from_raw_parts(ptr as *const u8, len));
+            }
+        }
+    }
+
+    /// Invoked after `call` has returned.
+    pub(crate) unsafe fn after(&self, call: &Call) {
+        if call.succeeded() {
+            self.lock().observe(call);
+        }
+    }
+}
+
+impl RecordingState {
+    unsafe fn observe(&mut self, call: &Call) -> Option<()> {
+        let int = |name| call.int(name);
+        let id = |name| call.ids(name).first().copied();
+        let event = match call.function {
+            "vaCreateConfig" => {
+                let ptr = call.pointer("attrib_list");
+                let num = usize::try_from(int("num_attribs")?).ok()?;
+                let attribs = if ptr == 0 || num == 0 {
+                    &[]
+                } else {
+                    slice::from_raw_parts(ptr as *const ConfigAttrib, num)
+                };
+                Event::CreateConfig {
+                    id: id("config_id")?,
+                    profile: Profile(int("profile")? as c_int),
+                    entrypoint: Entrypoint(int("entrypoint")? as c_int),
+                    attribs: attribs
+                        .iter()
+                        .map(|attrib| (attrib.attrib_type(), attrib.raw_value()))
+                        .collect(),
+                }
+            }
+            "vaCreateSurfaces" => {
+                let ptr = call.pointer("attrib_list");
+                let num = usize::try_from(int("num_attribs")?).ok()?;
+                let attribs = if ptr == 0 || num == 0 {
+                    &[]
+                } else {
+                    slice::from_raw_parts(ptr as *const SurfaceAttrib, num)
+                };
+                Event::CreateSurfaces {
+                    format: RTFormat::from_bits_truncate(int("format")? as u32),
+                    width: int("width")? as u32,
+                    height: int("height")? as u32,
+                    ids: call.ids("surfaces"),
+                    attribs: attribs
+                        .iter()
+                        .filter_map(|attrib| Some((attrib.ty(), attrib.raw_value().as_int()?)))
+                        .collect(),
+                }
+            }
+            "vaCreateContext" => Event::CreateContext {
+                id: id("context")?,
+                config: int("config_id")? as u32,
+                width: int("picture_width")? as u32,
+                height: int("picture_height")? as u32,
+                flag: int("flag")? as i32,
+                render_targets: call.ids("render_targets"),
+            },
+            "vaCreateBuffer" => {
+                let size = int("size")? as u32;
+                let num_elements = int("num_elements")? as u32;
+                let len = size as usize * num_elements as usize;
+                let ptr = call.pointer("data");
+                let data = if ptr == 0 || len == 0 {
+                    vec![0; len]
+                } else {
+                    slice::from_raw_parts(ptr as *const u8, len).to_vec()
+                };
+                let buffer = RecordedBuffer {
+                    ty: BufferType(int("type_")? as c_int),
+                    size,
+                    num_elements,
+                    data,
+                };
+                self.buffers.insert(id("buf_id")?, buffer);
+                return Some(());
+            }
+            "vaMapBuffer" => {
+                let pbuf = call.pointer("pbuf");
+                if pbuf == 0 {
+                    return None;
+                }
+                let ptr = *(pbuf as *const *mut c_void);
+                self.mapped
+                    .insert(int("buf_id")? as VABufferID, ptr as usize);
+                return Some(());
+            }
+            "vaUnmapBuffer" => {
+                self.mapped.remove(&(int("buf_id")? as VABufferID));
+                return Some(());
+            }
+            "vaDestroyBuffer" => {
+                let id = int("buffer_id")? as VABufferID;
+                self.buffers.remove(&id);
+                self.mapped.remove(&id);
+                return Some(());
+            }
+            "vaBeginPicture" => Event::BeginPicture {
+                context: int("context")? as u32,
+                target: int("render_target")? as u32,
+            },
+            "vaRenderPicture" => Event::RenderPicture {
+                context: int("context")? as u32,
+                buffers: call
+                    .ids("buffers")
+                    .iter()
+                    .map(|id| self.buffers.get(id).cloned())
+                    .collect(),
+            },
+            "vaEndPicture" => Event::EndPicture {
+                context: int("context")? as u32,
+            },
+            "vaSyncSurface" => Event::SyncSurface {
+                surface: int("render_target")? as u32,
+            },
+            "vaSyncSurface2" => Event::SyncSurface {
+                surface: int("surface")? as u32,
+            },
+            "vaDestroySurfaces" => Event::DestroySurfaces {
+                ids: call.ids("surfaces"),
+            },
+            "vaDestroyContext" => Event::DestroyContext {
+                id: int("context")? as u32,
+            },
+            "vaDestroyConfig" => Event::DestroyConfig {
+                id: int("config_id")? as u32,
+            },
+            _ => return None,
+        };
+        self.events.push(event);
+        Some(())
+    }
+}
+
+/// A recorded sequence of operations that can be saved, loaded and replayed.
+///
+/// Sessions are stored as line-based text, starting with a `v-ayylmao session 1` header. Every
+/// following line holds one operation as a keyword followed by integer fields, so the files can be
+/// inspected and edited by hand. Everything after a `#` is a comment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    events: Vec<Event>,
+}
+
+impl Session {
+    /// Returns the recorded operations, in order.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Loads a [`Session`] from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            Error::from(io::Error::new(
+                e.kind(),
+                format!("failed to read {}: {e}", path.display()),
+            ))
+        })?;
+        text.parse()
+    }
+
+    /// Saves the [`Session`] to the file at `path`.
+    ///
+    /// The file is created if it doesn't exist, and truncated if it does.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).map_err(|e| {
+            Error::from(io::Error::new(
+                e.kind(),
+                format!("failed to write {}: {e}", path.display()),
+            ))
+        })
+    }
+
+    /// Performs all operations of the [`Session`] on `display`.
+    ///
+    /// Object IDs are mapped to the objects created on `display`. Surfaces that are still alive at
+    /// the end of the session are returned in the [`Replay`], while all other objects are
+    /// destroyed.
+    pub fn replay(&self, display: &Display) -> Result<Replay> {
+        let mut configs = HashMap::new();
+        let mut surfaces: HashMap<u32, Surface> = HashMap::new();
+        let mut contexts = HashMap::new();
+        // Buffers submitted to each context. Once the picture is ended, they are kept alive until
+        // its target surface is synchronized.
+        let mut pending: HashMap<u32, Vec<RawBuffer>> = HashMap::new();
+        // The target surface of the picture each context is working on.
+        let mut targets: HashMap<u32, Arc<SurfaceOwner>> = HashMap::new();
+
+        for event in &self.events {
+            match event {
+                Event::CreateConfig {
+                    id,
+                    profile,
+                    entrypoint,
+                    attribs,
+                } => {
+                    let mut attribs = attribs
+                        .iter()
+                        .map(|&(type_, value)| ConfigAttrib { type_, value })
+                        .collect::<Vec<_>>();
+                    let config =
+                        Config::with_attribs(display, *profile, *entrypoint, &mut attribs)?;
+                    configs.insert(*id, config);
+                }
+                Event::CreateSurfaces {
+                    format,
+                    width,
+                    height,
+                    ids,
+                    attribs,
+                } => {
+                    let mut attribs = attribs
+                        .iter()
+                        .map(|&(type_, value)| SurfaceAttrib {
+                            type_,
+                            flags: SurfaceAttribFlags::SETTABLE,
+                            value: GenericValue::int(value),
+                        })
+                        .collect::<Vec<_>>();
+                    let created = Surface::create_batch(
+                        &display.d,
+                        *width,
+                        *height,
+                        *format,
+                        &mut attribs,
+                        ids.len(),
+                    )?;
+                    surfaces.extend(ids.iter().copied().zip(created));
+                }
+                Event::CreateContext {
+                    id,
+                    config,
+                    width,
+                    height,
+                    flag,
+                    render_targets,
+                } => {
+                    let config = lookup(&configs, *config, "config")?;
+                    let render_targets = render_targets
+                        .iter()
+                        .map(|id| lookup(&surfaces, *id, "surface").map(|s| s.owner().clone()))
+                        .collect::<Result<Vec<_>>>()?;
+                    let mut ids = render_targets.iter().map(|s| s.id()).collect::<Vec<_>>();
+                    let mut context_id = 0;
+                    unsafe {
+                        check(
+                            config.d.libva.vaCreateContext(
+                                config.d.raw,
+                                config.id,
+                                *width as c_int,
+                                *height as c_int,
+                                *flag,
+                                ids.as_mut_ptr(),
+                                ids.len() as c_int,
+                                &mut context_id,
+                            ),
+                            "vaCreateContext",
+                            &[Object::Config(config.id)],
+                        )?;
+                    }
+                    let context = Context::from_raw(config, context_id, render_targets);
+                    contexts.insert(*id, context);
+                }
+                Event::BeginPicture { context, target } => {
+                    let cx = lookup(&contexts, *context, "context")?;
+                    let target = lookup(&surfaces, *target, "surface")?.owner();
+                    unsafe {
+                        check(
+                            cx.d.libva.vaBeginPicture(cx.d.raw, cx.id, target.id()),
+                            "vaBeginPicture",
+                            &[Object::Context(cx.id), Object::Surface(target.id())],
+                        )?
+                    }
+                    targets.insert(*context, target.clone());
+                }
+                Event::RenderPicture { context, buffers } => {
+                    let cx = lookup(&contexts, *context, "context")?;
+                    let buffers = buffers
+                        .iter()
+                        .map(|buf| {
+                            buf.as_ref().ok_or_else(|| {
+                                Error::from(format!(
+                                    "session is missing the contents of a buffer submitted to \
+                                     context {context}"
+                                ))
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    let mut ids = Vec::with_capacity(buffers.len());
+                    for buf in buffers {
+                        let mut data = buf.data.clone();
+                        if buf.ty == BufferType::ProcPipelineParameter {
+                            ProcPipelineParameterBuffer::remap_surface(&mut data, |id| {
+                                surfaces.get(&id).map(Surface::id)
+                            })?;
+                        }
+                        let raw = RawBuffer::with_data(
+                            cx,
+                            buf.ty,
+                            buf.size,
+                            buf.num_elements,
+                            &mut data,
+                        )?;
+                        ids.push(raw.id());
+                        pending.entry(*context).or_default().push(raw);
+                    }
+                    unsafe {
+                        check(
+                            cx.d.libva.vaRenderPicture(
+                                cx.d.raw,
+                                cx.id,
+                                ids.as_mut_ptr(),
+                                ids.len() as c_int,
+                            ),
+                            "vaRenderPicture",
+                            &[Object::Context(cx.id)],
+                        )?;
+                    }
+                }
+                Event::EndPicture { context } => {
+                    let cx = lookup(&contexts, *context, "context")?;
+                    unsafe {
+                        check(
+                            cx.d.libva.vaEndPicture(cx.d.raw, cx.id),
+                            "vaEndPicture",
+                            &[Object::Context(cx.id)],
+                        )?
+                    }
+                    let buffers = pending.remove(context).unwrap_or_default();
+                    if let Some(target) = targets.remove(context) {
+                        target.retain_until_synced(buffers);
+                    }
+                }
+                Event::SyncSurface { surface } => {
+                    surfaces
+                        .get_mut(surface)
+                        .ok_or_else(|| unknown("surface", *surface))?
+                        .sync()?;
+                }
+                Event::DestroySurfaces { ids } => {
+                    for id in ids {
+                        surfaces.remove(id);
+                    }
+                }
+                Event::DestroyContext { id } => {
+                    pending.remove(id);
+                    targets.remove(id);
+                    contexts.remove(id);
+                }
+                Event::DestroyConfig { id } => {
+                    configs.remove(id);
+                }
+            }
+        }
+
+        Ok(Replay { surfaces })
+    }
+}
+
+fn lookup<'a, T>(map: &'a HashMap<u32, T>, id: u32, what: &str) -> Result<&'a T> {
+    map.get(&id).ok_or_else(|| unknown(what, id))
+}
+
+fn unknown(what: &str, id: u32) -> Error {
+    Error::from(format!("session references unknown {what} {id}"))
+}
+
+/// The result of replaying a [`Session`].
+pub struct Replay {
+    surfaces: HashMap<u32, Surface>,
+}
+
+impl Replay {
+    /// Returns the [`Surface`] that was recorded with the ID `id`.
+    ///
+    /// Returns [`None`] if the session didn't create the surface, or destroyed it again.
+    pub fn surface(&self, id: u32) -> Option<&Surface> {
+        self.surfaces.get(&id)
+    }
+
+    /// Returns the [`Surface`] that was recorded with the ID `id`, mutably.
+    pub fn surface_mut(&mut self, id: u32) -> Option<&mut Surface> {
+        self.surfaces.get_mut(&id)
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for event in &self.events {
+            match event {
+                Event::CreateConfig {
+                    id,
+                    profile,
+                    entrypoint,
+                    attribs,
+                } => {
+                    write!(f, "config {id} {} {}", profile.0, entrypoint.0)?;
+                    for (ty, value) in attribs {
+                        write!(f, " {}:{value}", ty.0)?;
+                    }
+                    writeln!(f, "  # {profile:?} {entrypoint:?}")?;
+                }
+                Event::CreateSurfaces {
+                    format,
+                    width,
+                    height,
+                    ids,
+                    attribs,
+                } => {
+                    write!(f, "surfaces {} {width} {height}", format.bits())?;
+                    for id in ids {
+                        write!(f, " {id}")?;
+                    }
+                    for (ty, value) in attribs {
+                        write!(f, " {}:{value}", ty.0)?;
+                    }
+                    writeln!(f, "  # {format:?}")?;
+                }
+                Event::CreateContext {
+                    id,
+                    config,
+                    width,
+                    height,
+                    flag,
+                    render_targets,
+                } => {
+                    write!(f, "context {id} {config} {width} {height} {flag}")?;
+                    for id in render_targets {
+                        write!(f, " {id}")?;
+                    }
+                    writeln!(f)?;
+                }
+                Event::BeginPicture { context, target } => writeln!(f, "begin {context} {target}")?,
+                Event::RenderPicture { context, buffers } => {
+                    writeln!(f, "render {context} {}", buffers.len())?;
+                    for buf in buffers {
+                        let Some(buf) = buf else {
+                            writeln!(f, "missing")?;
+                            continue;
+                        };
+                        let mut hex = String::with_capacity(buf.data.len() * 2);
+                        for byte in &buf.data {
+                            write!(hex, "{byte:02x}")?;
+                        }
+                        if hex.is_empty() {
+                            hex.push('-');
+                        }
+                        writeln!(
+                            f,
+                            "buffer {} {} {} {hex}  # {:?}",
+                            buf.ty.0, buf.size, buf.num_elements, buf.ty
+                        )?;
+                    }
+                }
+                Event::EndPicture { context } => writeln!(f, "end {context}")?,
+                Event::SyncSurface { surface } => writeln!(f, "sync {surface}")?,
+                Event::DestroySurfaces { ids } => {
+                    f.write_str("destroy-surfaces")?;
+                    for id in ids {
+                        write!(f, " {id}")?;
+                    }
+                    writeln!(f)?;
+                }
+                Event::DestroyContext { id } => writeln!(f, "destroy-context {id}")?,
+                Event::DestroyConfig { id } => writeln!(f, "destroy-config {id}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| Line {
+                number: i + 1,
+                tokens: line.split('#').next().unwrap().split_whitespace(),
+            })
+            .filter(|line| line.tokens.clone().next().is_some());
+
+        match lines.next() {
+            Some(line) if line.tokens.clone().eq(HEADER.split_whitespace()) => {}
+            _ => return Err(Error::from("missing session header")),
+        }
+
+        let mut events = Vec::new();
+        while let Some(mut line) = lines.next() {
+            let event = match line.next::<String>("keyword")?.as_str() {
+                "config" => Event::CreateConfig {
+                    id: line.next("config ID")?,
+                    profile: Profile(line.next("profile")?),
+                    entrypoint: Entrypoint(line.next("entrypoint")?),
+                    attribs: line
+                        .rest::<String>()?
+                        .iter()
+                        .map(|attrib| {
+                            let (ty, value) = attrib
+                                .split_once(':')
+                                .and_then(|(ty, value)| {
+                                    Some((ty.parse().ok()?, value.parse().ok()?))
+                                })
+                                .ok_or_else(|| {
+                                    line.error(format!("invalid attribute `{attrib}`"))
+                                })?;
+                            Ok((ConfigAttribType(ty), value))
+                        })
+                        .collect::<Result<_>>()?,
+                },
+                "surfaces" => {
+                    let format = RTFormat::from_bits_truncate(line.next("format")?);
+                    let width = line.next("width")?;
+                    let height = line.next("height")?;
+                    let (attribs, ids): (Vec<String>, Vec<String>) = line
+                        .rest::<String>()?
+                        .into_iter()
+                        .partition(|s| s.contains(':'));
+                    Event::CreateSurfaces {
+                        format,
+                        width,
+                        height,
+                        ids: ids
+                            .iter()
+                            .map(|id| {
+                                id.parse()
+                                    .map_err(|_| line.error(format!("invalid surface ID `{id}`")))
+                            })
+                            .collect::<Result<_>>()?,
+                        attribs: attribs
+                            .iter()
+                            .map(|attrib| {
+                                attrib
+                                    .split_once(':')
+                                    .and_then(|(ty, value)| {
+                                        Some((
+                                            SurfaceAttribType(ty.parse().ok()?),
+                                            value.parse().ok()?,
+                                        ))
+                                    })
+                                    .ok_or_else(|| {
+                                        line.error(format!("invalid attribute `{attrib}`"))
+                                    })
+                            })
+                            .collect::<Result<_>>()?,
+                    }
+                }
+                "context" => Event::CreateContext {
+                    id: line.next("context ID")?,
+                    config: line.next("config ID")?,
+                    width: line.next("width")?,
+                    height: line.next("height")?,
+                    flag: line.next("flag")?,
+                    render_targets: line.rest()?,
+                },
+                "begin" => Event::BeginPicture {
+                    context: line.next("context ID")?,
+                    target: line.next("surface ID")?,
+                },
+                "render" => {
+                    let context = line.next("context ID")?;
+                    let count: usize = line.next("buffer count")?;
+                    let mut buffers = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let mut buf_line = lines
+                            .next()
+                            .ok_or_else(|| line.error("unexpected end of session"))?;
+                        buffers.push(buf_line.buffer()?);
+                    }
+                    Event::RenderPicture { context, buffers }
+                }
+                "end" => Event::EndPicture {
+                    context: line.next("context ID")?,
+                },
+                "sync" => Event::SyncSurface {
+                    surface: line.next("surface ID")?,
+                },
+                "destroy-surfaces" => Event::DestroySurfaces { ids: line.rest()? },
+                "destroy-context" => Event::DestroyContext {
+                    id: line.next("context ID")?,
+                },
+                "destroy-config" => Event::DestroyConfig {
+                    id: line.next("config ID")?,
+                },
+                keyword => return Err(line.error(format!("unknown operation `{keyword}`"))),
+            };
+            line.finish()?;
+            events.push(event);
+        }
+
+        Ok(Session { events })
+    }
+}
+
+/// A non-empty line of a session file, with comments removed.
+struct Line<'a> {
+    number: usize,
+    tokens: SplitWhitespace<'a>,
+}
+
+impl Line<'_> {
+    fn error(&self, msg: impl fmt::Display) -> Error {
+        Error::from(format!("invalid session (line {}): {msg}", self.number))
+    }
+
+    fn next<T: FromStr>(&mut self, what: &str) -> Result<T> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or_else(|| self.error(format!("missing {what}")))?;
+        token
+            .parse()
+            .map_err(|_| self.error(format!("invalid {what} `{token}`")))
+    }
+
+    fn rest<T: FromStr>(&mut self) -> Result<Vec<T>> {
+        let mut values = Vec::new();
+        while self.tokens.clone().next().is_some() {
+            values.push(self.next("value")?);
+        }
+        Ok(values)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.tokens.next() {
+            Some(token) => Err(self.error(format!("unexpected `{token}`"))),
+            None => Ok(()),
+        }
+    }
+
+    fn buffer(&mut self) -> Result<Option<RecordedBuffer>> {
+        match self.next::<String>("keyword")?.as_str() {
+            "buffer" => {}
+            "missing" => {
+                self.finish()?;
+                return Ok(None);
+            }
+            keyword => {
+                return Err(self.error(format!("expected `buffer` or `missing`, found `{keyword}`")))
+            }
+        }
+        let ty = BufferType(self.next("buffer type")?);
+        let size: u32 = self.next("buffer size")?;
+        let num_elements: u32 = self.next("element count")?;
+        let hex: String = self.next("buffer data")?;
+        let data = if hex == "-" {
+            Vec::new()
+        } else {
+            decode_hex(&hex).ok_or_else(|| self.error("invalid buffer data"))?
+        };
+        if data.len() != size as usize * num_elements as usize {
+            return Err(self.error(format!(
+                "buffer data has {} bytes, expected {size}*{num_elements}",
+                data.len()
+            )));
+        }
+        self.finish()?;
+        Ok(Some(RecordedBuffer {
+            ty,
+            size,
+            num_elements,
+            data,
+        }))
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,201 @@
+use std::{slice, time::Duration};
+
+use crate::{
+    backend::FakeBackend,
+    buffer::{Buffer, BufferType},
+    config::Config,
+    context::Context,
+    display::Display,
+    error::ErrorKind,
+    surface::{RTFormat, Surface, SurfaceAttrib, SurfaceAttribEnum, SurfaceAttribType},
+    Entrypoint, PixelFormat, Profile,
+};
+
+use super::{Event, Recording, Session};
+
+#[test]
+fn record_and_replay() {
+    let recording = Recording::new();
+    let fake = FakeBackend::new();
+    let display = Display::builder()
+        .record(recording.clone())
+        .open_backend(fake.clone())
+        .unwrap();
+
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let mut surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let param = Buffer::new_param(&context, BufferType::PictureParameter, 0xdeadbeef_u32).unwrap();
+    let mut data = Buffer::<u8>::new_empty(&context, BufferType::SliceData, 4).unwrap();
+    data.map().unwrap().copy_from_slice(&[1, 2, 3, 4]);
+    let mut picture = context.begin_picture(&surface).unwrap();
+    picture.render_picture(param).unwrap();
+    picture.render_picture(data).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+    surface.sync().unwrap();
+
+    let session = recording.session();
+    let Event::RenderPicture { buffers, .. } = &session.events()[4] else {
+        panic!("unexpected event: {:?}", session.events()[4]);
+    };
+    assert_eq!(
+        buffers[0].as_ref().unwrap().data(),
+        0xdeadbeef_u32.to_ne_bytes()
+    );
+    let Event::RenderPicture { buffers, .. } = &session.events()[5] else {
+        panic!("unexpected event: {:?}", session.events()[5]);
+    };
+    assert_eq!(buffers[0].as_ref().unwrap().data(), [1, 2, 3, 4]);
+    assert!(matches!(
+        session.events().last(),
+        Some(Event::SyncSurface { .. })
+    ));
+
+    // Round-trip through a file.
+    let path = std::env::temp_dir().join(format!("v-ayylmao-session-{}.txt", std::process::id()));
+    session.save(&path).unwrap();
+    let loaded = Session::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, session);
+    let err = Session::load(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io);
+    assert!(std::error::Error::source(&err).is_some());
+
+    // Replaying on another display performs the same submissions.
+    let other = FakeBackend::new();
+    let replay = loaded
+        .replay(&Display::with_backend(other.clone()).unwrap())
+        .unwrap();
+    let expected = fake.submissions();
+    let actual = other.submissions();
+    assert_eq!(actual.len(), expected.len());
+    for (actual, expected) in actual.iter().zip(&expected) {
+        assert_eq!(actual.buffer_types(), expected.buffer_types());
+        for (a, e) in actual.buffers().iter().zip(expected.buffers()) {
+            assert_eq!(a.data(), e.data());
+        }
+    }
+    assert_eq!(
+        replay.surface(surface.id()).unwrap().id(),
+        actual[0].target()
+    );
+}
+
+#[test]
+fn parse_errors() {
+    let err = "config 1 9 1".parse::<Session>().unwrap_err();
+    assert_eq!(err.to_string(), "missing session header");
+
+    let err = "v-ayylmao session 1\nrender 1 1\nend 1"
+        .parse::<Session>()
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid session (line 3): expected `buffer` or `missing`, found `end`"
+    );
+
+    let err = "v-ayylmao session 1\n\n# comment\nbuffer 5 4 1 0102"
+        .parse::<Session>()
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid session (line 4): unknown operation `buffer`"
+    );
+
+    let session = "v-ayylmao session 1\nrender 1 1\nbuffer 5 2 1 0102  # SliceData\nend 1\n"
+        .parse::<Session>()
+        .unwrap();
+    assert_eq!(session.events().len(), 2);
+}
+
+#[test]
+fn replay_keeps_buffers_until_sync() {
+    let recording = Recording::new();
+    let display = Display::builder()
+        .record(recording.clone())
+        .open_backend(FakeBackend::new())
+        .unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let buf = Buffer::new_data(&context, BufferType::SliceData, &[0xff, 0xd8]).unwrap();
+    let mut picture = context.begin_picture(&surface).unwrap();
+    picture.render_picture(buf).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+
+    let other = FakeBackend::new();
+    other.delay_next_picture(Duration::from_millis(50));
+    let mut replay = recording
+        .session()
+        .replay(&Display::with_backend(other.clone()).unwrap())
+        .unwrap();
+    assert_eq!(other.live_objects().buffers, 1);
+    replay.surface_mut(surface.id()).unwrap().sync().unwrap();
+    assert_eq!(other.live_objects().buffers, 0);
+}
+
+#[test]
+fn surface_attribs() {
+    let recording = Recording::new();
+    let display = Display::builder()
+        .record(recording.clone())
+        .open_backend(FakeBackend::new())
+        .unwrap();
+    let mut attrib = SurfaceAttrib::from(SurfaceAttribEnum::PixelFormat(PixelFormat::NV12));
+    Surface::with_attribs(
+        &display,
+        64,
+        64,
+        RTFormat::YUV420,
+        slice::from_mut(&mut attrib),
+    )
+    .unwrap();
+
+    let session = recording.session();
+    let Event::CreateSurfaces { attribs, .. } = &session.events()[0] else {
+        panic!("unexpected event: {:?}", session.events()[0]);
+    };
+    assert_eq!(
+        attribs,
+        &[(
+            SurfaceAttribType::PixelFormat,
+            PixelFormat::NV12.to_u32_le() as i32
+        )]
+    );
+    let parsed = session.to_string().parse::<Session>().unwrap();
+    assert_eq!(parsed, session);
+    parsed
+        .replay(&Display::with_backend(FakeBackend::new()).unwrap())
+        .unwrap();
+}
+
+#[test]
+fn missing_buffers() {
+    let text = "v-ayylmao session 1\n\
+                config 1 12 1\n\
+                surfaces 1 64 64 2\n\
+                context 3 1 64 64 0\n\
+                begin 3 2\n\
+                render 3 2\n\
+                buffer 5 2 1 0102\n\
+                missing\n\
+                end 3\n";
+    let session = text.parse::<Session>().unwrap();
+    let Event::RenderPicture { buffers, .. } = &session.events()[4] else {
+        panic!("unexpected event: {:?}", session.events()[4]);
+    };
+    assert!(buffers[0].is_some());
+    assert!(buffers[1].is_none());
+    assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+
+    let fake = FakeBackend::new();
+    let err = session
+        .replay(&Display::with_backend(fake.clone()).unwrap())
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "session is missing the contents of a buffer submitted to context 3"
+    );
+    assert!(fake.submissions().is_empty());
+}
@@ -1,26 +1,41 @@
 //! [`Surface`]s and surface attributes.
 
+mod pool;
+#[cfg(test)]
+mod tests;
+
 use core::fmt;
 use std::{
     ffi::{c_int, c_uint, c_void},
-    mem::MaybeUninit,
+    future::Future,
+    mem::{self, MaybeUninit},
     ops::{Deref, DerefMut},
-    sync::Arc,
-    time::Instant,
+    pin::Pin,
+    ptr,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{self, Poll},
+    thread,
+    time::{Duration, Instant},
     vec,
 };
 
 use crate::{
-    buffer::Mapping,
+    buffer::{Mapping, RawBuffer},
     check, check_log,
-    display::{Display, DisplayOwner},
-    error::VAError,
+    display::{Display, DisplayOwner, LibvaFeatures},
+    error::{Object, Retry, VAError},
     image::{Image, ImageFormat},
     pixelformat::PixelFormat,
     raw::{VAGenericFunc, VASurfaceID, VA_PADDING_LOW},
+    wait::{self, Waiter},
     Error, Result,
 };
 
+pub use self::pool::{SurfaceLease, SurfacePool};
+
+/// How often a [`SurfaceSync`] polls the surface status if `vaSyncSurface2` isn't available.
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
 bitflags! {
     /// Flags for configuring how a [`Surface`] should be exported.
     pub struct ExportSurfaceFlags: u32 {
@@ -186,6 +201,8 @@ ffi_enum! {
     }
 }
 
+/// A range of macroblocks that could not be decoded, as reported by [`Surface::decode_errors`].
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct SurfaceDecodeMBErrors {
     status: i32,
@@ -196,6 +213,69 @@ pub struct SurfaceDecodeMBErrors {
     va_reserved: [u32; VA_PADDING_LOW - 1],
 }
 
+impl SurfaceDecodeMBErrors {
+    /// The `status` value that terminates the error array returned by `vaQuerySurfaceError`.
+    const TERMINATOR: i32 = -1;
+
+    /// Creates an error record for the macroblocks `start_mb..=end_mb`.
+    ///
+    /// This is only useful for implementing a [`Backend`][crate::backend::Backend].
+    pub fn new(error_type: DecodeErrorType, start_mb: u32, end_mb: u32, num_mb: u32) -> Self {
+        Self {
+            status: 1,
+            start_mb,
+            end_mb,
+            decode_error_type: error_type,
+            num_mb,
+            va_reserved: [0; VA_PADDING_LOW - 1],
+        }
+    }
+
+    pub(crate) fn terminator() -> Self {
+        Self {
+            status: Self::TERMINATOR,
+            ..Self::new(DecodeErrorType(0), 0, 0, 0)
+        }
+    }
+
+    /// Returns whether the macroblocks are missing entirely, or failed to decode.
+    #[inline]
+    pub fn error_type(&self) -> DecodeErrorType {
+        self.decode_error_type
+    }
+
+    /// Returns the index of the first affected macroblock.
+    #[inline]
+    pub fn start_mb(&self) -> u32 {
+        self.start_mb
+    }
+
+    /// Returns the index of the last affected macroblock (inclusive).
+    #[inline]
+    pub fn end_mb(&self) -> u32 {
+        self.end_mb
+    }
+
+    /// Returns the number of macroblocks with errors in this range.
+    ///
+    /// Older drivers may leave this at 0.
+    #[inline]
+    pub fn num_mb(&self) -> u32 {
+        self.num_mb
+    }
+}
+
+impl fmt::Debug for SurfaceDecodeMBErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SurfaceDecodeMBErrors")
+            .field("error_type", &self.decode_error_type)
+            .field("start_mb", &self.start_mb)
+            .field("end_mb", &self.end_mb)
+            .field("num_mb", &self.num_mb)
+            .finish()
+    }
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 union VAGenericValueUnion {
@@ -313,8 +393,189 @@ bitflags! {
 /// A graphics surface or texture.
 #[derive(Debug)]
 pub struct Surface {
+    owner: Arc<SurfaceOwner>,
+}
+
+/// Destroys the libva surface once the [`Surface`] and every object using it are dropped.
+#[derive(Debug)]
+pub(crate) struct SurfaceOwner {
     d: Arc<DisplayOwner>,
     id: VASurfaceID,
+    /// Buffers submitted as part of pictures targeting this surface. The driver may read them
+    /// until the surface has been synced.
+    in_flight: Mutex<Vec<RawBuffer>>,
+}
+
+impl SurfaceOwner {
+    #[inline]
+    pub(crate) fn id(&self) -> VASurfaceID {
+        self.id
+    }
+
+    /// Keeps `buffers` alive until the surface has been synced, or its status shows that the
+    /// driver is done with it.
+    pub(crate) fn retain_until_synced(&self, buffers: Vec<RawBuffer>) {
+        self.in_flight().extend(buffers);
+    }
+
+    /// Destroys the in-flight buffers if the driver is done with them.
+    ///
+    /// Called before submitting another picture targeting this surface, so that the list doesn't
+    /// grow without bounds if the surface is never synced.
+    pub(crate) fn release_finished(&self) {
+        if !self.in_flight().is_empty() {
+            // `status` releases the buffers if the surface is idle.
+            self.status().ok();
+        }
+    }
+
+    /// Destroys all buffers that were in use by pictures targeting this surface.
+    ///
+    /// Must only be called after the surface has been synced.
+    fn release_in_flight(&self) {
+        let buffers = mem::take(&mut *self.in_flight());
+        drop(buffers);
+    }
+
+    fn in_flight(&self) -> MutexGuard<'_, Vec<RawBuffer>> {
+        // The list is never left in an inconsistent state.
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Releases the in-flight buffers if `result` of a sync indicates that the pending
+    /// operations have finished.
+    fn finish_sync(&self, result: &Result<()>) {
+        match result {
+            Ok(()) => self.release_in_flight(),
+            // A decoding error still means that the operation has finished.
+            Err(e) if e.as_libva() == Some(VAError::ERROR_DECODING_ERROR) => {
+                self.release_in_flight()
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn sync(&self) -> Result<()> {
+        let start = Instant::now();
+
+        let result = Retry::default().run(|| unsafe {
+            check(
+                self.d.libva.vaSyncSurface(self.d.raw, self.id),
+                "vaSyncSurface",
+                &[Object::Surface(self.id)],
+            )
+        });
+        self.finish_sync(&result);
+        result?;
+
+        log::trace!("vaSyncSurface took {:?}", start.elapsed());
+        Ok(())
+    }
+
+    fn sync_timeout(&self, timeout: Duration) -> Result<()> {
+        self.d
+            .require(LibvaFeatures::SYNC_SURFACE2, "vaSyncSurface2")?;
+        let result = unsafe {
+            check(
+                self.d
+                    .libva
+                    .vaSyncSurface2(self.d.raw, self.id, wait::timeout_ns(timeout)),
+                "vaSyncSurface2",
+                &[Object::Surface(self.id)],
+            )
+        };
+        self.finish_sync(&result);
+        result
+    }
+
+    fn status(&self) -> Result<SurfaceStatus> {
+        let mut status = SurfaceStatus(0);
+        unsafe {
+            check(
+                self.d
+                    .libva
+                    .vaQuerySurfaceStatus(self.d.raw, self.id, &mut status),
+                "vaQuerySurfaceStatus",
+                &[Object::Surface(self.id)],
+            )?;
+        }
+        if status != SurfaceStatus::Rendering {
+            self.release_in_flight();
+        }
+        Ok(status)
+    }
+
+    /// Waits for at most about `timeout`, and returns whether the pending operations have
+    /// finished.
+    ///
+    /// Without `vaSyncSurface2`, this polls the surface status instead.
+    fn try_sync(&self, timeout: Duration) -> Result<bool> {
+        if self.d.features.contains(LibvaFeatures::SYNC_SURFACE2) {
+            wait::finished(self.sync_timeout(timeout))
+        } else if self.status()? == SurfaceStatus::Rendering {
+            thread::sleep(timeout.min(STATUS_POLL_INTERVAL));
+            Ok(false)
+        } else {
+            self.sync().map(|()| true)
+        }
+    }
+}
+
+impl Drop for SurfaceOwner {
+    fn drop(&mut self) {
+        unsafe {
+            let in_flight = self.in_flight.get_mut().unwrap_or_else(|e| e.into_inner());
+            if !in_flight.is_empty() {
+                // Wait for the driver to stop using the buffers before destroying them.
+                check_log(
+                    self.d.libva.vaSyncSurface(self.d.raw, self.id),
+                    "vaSyncSurface",
+                    &[Object::Surface(self.id)],
+                );
+                in_flight.clear();
+            }
+            check_log(
+                self.d.libva.vaDestroySurfaces(self.d.raw, &mut self.id, 1),
+                "vaDestroySurfaces",
+                &[Object::Surface(self.id)],
+            );
+        }
+    }
+}
+
+/// A [`Future`] that completes once all pending operations on a [`Surface`] have finished.
+///
+/// Returned by [`Surface::sync_async`]. If the operations are still in progress when the future
+/// is first polled, it waits on a helper thread, using `vaSyncSurface2` if available and
+/// `vaQuerySurfaceStatus` otherwise. All pending [`SurfaceSync`] and
+/// [`BufferSync`][crate::buffer::BufferSync] futures of a [`Display`] share the same thread, and
+/// dropping one doesn't block.
+#[must_use = "futures do nothing unless polled"]
+pub struct SurfaceSync<'a> {
+    owner: &'a Arc<SurfaceOwner>,
+    waiter: Option<Waiter>,
+}
+
+impl Future for SurfaceSync<'_> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let waiter = match &mut this.waiter {
+            Some(waiter) => waiter,
+            None => {
+                if this.owner.try_sync(Duration::ZERO)? {
+                    return Poll::Ready(Ok(()));
+                }
+                let owner = this.owner.clone();
+                this.waiter
+                    .insert(Waiter::spawn(&this.owner.d.waits, move |timeout| {
+                        owner.try_sync(timeout)
+                    })?)
+            }
+        };
+        waiter.poll(cx)
+    }
 }
 
 impl Surface {
@@ -329,50 +590,125 @@ impl Surface {
         format: RTFormat,
         attribs: &mut [SurfaceAttrib],
     ) -> Result<Self> {
-        let mut id = 0;
+        let mut surfaces = Self::create_batch(&display.d, width, height, format, attribs, 1)?;
+        Ok(surfaces.remove(0))
+    }
+
+    /// Creates `count` surfaces with a single `vaCreateSurfaces` call.
+    pub(crate) fn create_batch(
+        d: &Arc<DisplayOwner>,
+        width: u32,
+        height: u32,
+        format: RTFormat,
+        attribs: &mut [SurfaceAttrib],
+        count: usize,
+    ) -> Result<Vec<Self>> {
+        let mut ids = vec![0; count];
         unsafe {
-            check(display.d.libva.vaCreateSurfaces(
-                display.d.raw,
-                format,
-                width as c_uint,
-                height as c_uint,
-                &mut id,
-                1,
-                attribs.as_mut_ptr(),
-                attribs.len() as c_uint,
-            ))?;
+            check(
+                d.libva.vaCreateSurfaces(
+                    d.raw,
+                    format,
+                    width as c_uint,
+                    height as c_uint,
+                    ids.as_mut_ptr(),
+                    c_uint::try_from(count).map_err(Error::from)?,
+                    attribs.as_mut_ptr(),
+                    attribs.len() as c_uint,
+                ),
+                "vaCreateSurfaces",
+                &[],
+            )?;
         }
-        Ok(Surface {
-            d: display.d.clone(),
-            id,
-        })
+        Ok(ids
+            .into_iter()
+            .map(|id| Surface {
+                owner: Arc::new(SurfaceOwner {
+                    d: d.clone(),
+                    id,
+                    in_flight: Mutex::new(Vec::new()),
+                }),
+            })
+            .collect())
     }
 
     #[inline]
     pub(crate) fn id(&self) -> VASurfaceID {
-        self.id
+        self.owner.id
+    }
+
+    /// Returns a handle that keeps the libva surface alive, even after `self` is dropped.
+    #[inline]
+    pub(crate) fn owner(&self) -> &Arc<SurfaceOwner> {
+        &self.owner
     }
 
     /// Blocks until all pending operations writing to or reading from the surface have finished.
+    ///
+    /// Once the operations have finished, the buffers submitted along with them are destroyed.
+    ///
+    /// [Transient][Error::is_transient] failures are retried with the default [`Retry`] policy.
     pub fn sync(&mut self) -> Result<()> {
-        let start = Instant::now();
+        self.owner.sync()
+    }
 
-        unsafe { check(self.d.libva.vaSyncSurface(self.d.raw, self.id))? }
+    /// Blocks until all pending operations on the surface have finished, or until `timeout` has
+    /// elapsed.
+    ///
+    /// If the operations are still in progress after `timeout`, this fails with
+    /// [`VAError::ERROR_TIMEDOUT`] (which is a [transient][Error::is_transient] error).
+    ///
+    /// This requires [`LibvaFeatures::SYNC_SURFACE2`].
+    pub fn sync_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.owner.sync_timeout(timeout)
+    }
 
-        log::trace!("vaSyncSurface took {:?}", start.elapsed());
-        Ok(())
+    /// Returns a [`Future`] that completes once all pending operations on the surface have
+    /// finished, without blocking the thread that polls it.
+    ///
+    /// Unlike [`Surface::sync`], transient failures are not retried.
+    pub fn sync_async(&mut self) -> SurfaceSync<'_> {
+        SurfaceSync {
+            owner: &self.owner,
+            waiter: None,
+        }
     }
 
+    /// Queries whether the surface is still being rendered to.
+    ///
+    /// Once it isn't, the buffers submitted with earlier pictures targeting it are destroyed.
     pub fn status(&self) -> Result<SurfaceStatus> {
-        let mut status = SurfaceStatus(0);
+        self.owner.status()
+    }
+
+    /// Returns the macroblock ranges that the driver failed to decode into this surface.
+    ///
+    /// This should be called when [`Surface::sync`] fails with
+    /// [`VAError::ERROR_DECODING_ERROR`], to find out whether the decoded picture is partially
+    /// usable. If the driver didn't record any errors, an empty list is returned.
+    pub fn decode_errors(&self) -> Result<Vec<SurfaceDecodeMBErrors>> {
+        let mut ptr: *mut c_void = ptr::null_mut();
         unsafe {
             check(
-                self.d
-                    .libva
-                    .vaQuerySurfaceStatus(self.d.raw, self.id, &mut status),
+                self.owner.d.libva.vaQuerySurfaceError(
+                    self.owner.d.raw,
+                    self.owner.id,
+                    VAError::ERROR_DECODING_ERROR.into(),
+                    &mut ptr,
+                ),
+                "vaQuerySurfaceError",
+                &[Object::Surface(self.owner.id)],
             )?;
+
+            // The array is owned by the driver and terminated by an entry with a status of -1.
+            let mut errors = Vec::new();
+            let mut entry = ptr as *const SurfaceDecodeMBErrors;
+            while !entry.is_null() && (*entry).status != SurfaceDecodeMBErrors::TERMINATOR {
+                errors.push(*entry);
+                entry = entry.add(1);
+            }
+            Ok(errors)
         }
-        Ok(status)
     }
 
     /// Copies all pixels from `self` to the given [`Image`].
@@ -386,15 +722,19 @@ impl Surface {
         let start = Instant::now();
 
         unsafe {
-            check(self.d.libva.vaGetImage(
-                self.d.raw,
-                self.id,
-                0,
-                0,
-                image.width().into(),
-                image.height().into(),
-                image.id(),
-            ))?;
+            check(
+                self.owner.d.libva.vaGetImage(
+                    self.owner.d.raw,
+                    self.owner.id,
+                    0,
+                    0,
+                    image.width().into(),
+                    image.height().into(),
+                    image.id(),
+                ),
+                "vaGetImage",
+                &[Object::Surface(self.owner.id), Object::Image(image.id())],
+            )?;
         }
 
         log::trace!("vaGetImage took {:?}", start.elapsed());
@@ -410,29 +750,22 @@ impl Surface {
         unsafe {
             let mut image = MaybeUninit::uninit();
             check(
-                self.d
-                    .libva
-                    .vaDeriveImage(self.d.raw, self.id, image.as_mut_ptr()),
+                self.owner.d.libva.vaDeriveImage(
+                    self.owner.d.raw,
+                    self.owner.id,
+                    image.as_mut_ptr(),
+                ),
+                "vaDeriveImage",
+                &[Object::Surface(self.owner.id)],
             )?;
             Ok(Image {
-                d: self.d.clone(),
+                d: self.owner.d.clone(),
                 raw: image.assume_init(),
             })
         }
     }
 }
 
-impl Drop for Surface {
-    fn drop(&mut self) {
-        unsafe {
-            check_log(
-                self.d.libva.vaDestroySurfaces(self.d.raw, &mut self.id, 1),
-                "vaDestroySurfaces call in drop",
-            );
-        }
-    }
-}
-
 /// Bundles a [`Surface`] and [`Image`] with matching formats.
 ///
 /// Allows copying surface contents to the image.
@@ -0,0 +1,407 @@
+//! Recycling of [`Surface`]s.
+
+use std::{
+    collections::HashMap,
+    ffi::c_int,
+    fmt,
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    display::{Display, DisplayOwner},
+    error::Repr,
+    image::Image,
+    Error, Result,
+};
+
+use super::{RTFormat, Surface, SurfaceAttrib, SurfaceStatus, SurfaceSync, VAGenericValueType};
+
+/// Number of surfaces a [`SurfacePool`] creates at once by default.
+const DEFAULT_BATCH_SIZE: usize = 4;
+
+/// A pool of [`Surface`]s that are handed out as [`SurfaceLease`]s and recycled once the lease is
+/// dropped.
+///
+/// Surfaces are grouped by their [`RTFormat`], size and [`SurfaceAttrib`]s, and are created in
+/// batches, with a single `vaCreateSurfaces` call per batch. Returned surfaces are only handed
+/// out again once the driver reports that they are no longer [rendering][SurfaceStatus::Rendering]
+/// or [displaying][SurfaceStatus::Displaying]. Free surfaces whose status can't be queried are
+/// destroyed instead.
+///
+/// The pool never holds more than its maximum number of surfaces. When that limit is reached,
+/// free surfaces of other formats and sizes are destroyed to make room, so that users of
+/// different sizes can share the same memory budget. [`SurfacePool`] is cheap to clone, and all
+/// clones share the same surfaces.
+#[derive(Clone)]
+pub struct SurfacePool {
+    d: Arc<DisplayOwner>,
+    shared: Arc<Mutex<PoolState>>,
+}
+
+/// A [`Surface`] borrowed from a [`SurfacePool`].
+///
+/// Dereferences to the [`Surface`], and returns it to the pool on drop. Only the [`Surface`]
+/// methods that need `&mut self` and can't replace the surface are available on the lease.
+pub struct SurfaceLease {
+    surface: Option<Surface>,
+    key: SurfaceKey,
+    shared: Arc<Mutex<PoolState>>,
+}
+
+/// Surfaces with the same [`SurfaceKey`] are interchangeable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SurfaceKey {
+    format: RTFormat,
+    width: u32,
+    height: u32,
+    attribs: Vec<AttribKey>,
+}
+
+/// The bit pattern of a [`SurfaceAttrib`].
+///
+/// Pointer and function values are rejected by [`SurfacePool::get`], so they don't need to be
+/// part of the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct AttribKey {
+    ty: c_int,
+    flags: c_int,
+    value_type: c_int,
+    value: u64,
+}
+
+impl From<&SurfaceAttrib> for AttribKey {
+    fn from(attrib: &SurfaceAttrib) -> Self {
+        let value = attrib.value;
+        Self {
+            ty: attrib.type_.0,
+            flags: attrib.flags.bits(),
+            value_type: value.type_.0,
+            value: match value.type_ {
+                VAGenericValueType::Integer => u64::from(value.as_int().unwrap() as u32),
+                VAGenericValueType::Float => u64::from(value.as_float().unwrap().to_bits()),
+                _ => 0,
+            },
+        }
+    }
+}
+
+struct PoolState {
+    max_surfaces: usize,
+    batch_size: usize,
+    /// Number of surfaces that currently exist, including leased ones and ones that are still
+    /// being created.
+    allocated: usize,
+    free: HashMap<SurfaceKey, Vec<Surface>>,
+}
+
+impl PoolState {
+    /// Takes the free surfaces matching `key` out of the pool.
+    fn take_free(&mut self, key: &SurfaceKey) -> Vec<(SurfaceKey, Surface)> {
+        let free = self.free.remove(key).unwrap_or_default();
+        free.into_iter()
+            .map(|surface| (key.clone(), surface))
+            .collect()
+    }
+
+    /// Takes all free surfaces out of the pool.
+    fn take_all_free(&mut self) -> Vec<(SurfaceKey, Surface)> {
+        self.free
+            .drain()
+            .flat_map(|(key, free)| free.into_iter().map(move |surface| (key.clone(), surface)))
+            .collect()
+    }
+
+    /// Returns surfaces taken out with [`PoolState::take_free`] or [`PoolState::take_all_free`]
+    /// that were not used, and accounts for the ones that were destroyed meanwhile.
+    fn put_back(&mut self, surfaces: Vec<(SurfaceKey, Surface)>, destroyed: usize) {
+        self.allocated -= destroyed;
+        for (key, surface) in surfaces {
+            self.free.entry(key).or_default().push(surface);
+        }
+    }
+}
+
+/// Surfaces taken out of the pool, sorted by [`sort_out`].
+#[derive(Default)]
+struct Sorted {
+    /// Surfaces that are no longer busy.
+    idle: Vec<(SurfaceKey, Surface)>,
+    /// Surfaces that are busy, or that weren't queried.
+    rest: Vec<(SurfaceKey, Surface)>,
+    /// Number of surfaces that were destroyed because their status couldn't be queried.
+    destroyed: usize,
+}
+
+/// Queries the status of `surfaces` until `count` idle ones are found.
+///
+/// This calls into the driver, so the pool must not be locked. Surfaces whose status can't be
+/// queried are destroyed, since it can't be known when they could be reused.
+fn sort_out(surfaces: Vec<(SurfaceKey, Surface)>, count: usize) -> Sorted {
+    let mut sorted = Sorted::default();
+    for (key, surface) in surfaces {
+        if sorted.idle.len() == count {
+            sorted.rest.push((key, surface));
+            continue;
+        }
+        match is_busy(&surface) {
+            Ok(false) => sorted.idle.push((key, surface)),
+            Ok(true) => sorted.rest.push((key, surface)),
+            Err(e) => {
+                log::warn!("destroying pooled surface whose status can't be queried: {e}");
+                sorted.destroyed += 1;
+            }
+        }
+    }
+    sorted
+}
+
+fn is_busy(surface: &Surface) -> Result<bool> {
+    let status = surface.status()?;
+    Ok(status == SurfaceStatus::Rendering || status == SurfaceStatus::Displaying)
+}
+
+impl SurfacePool {
+    /// Creates an empty pool that will hold at most `max_surfaces` surfaces.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_surfaces` is 0.
+    pub fn new(display: &Display, max_surfaces: usize) -> Self {
+        assert!(
+            max_surfaces > 0,
+            "the pool has to allow at least one surface"
+        );
+        Self {
+            d: display.d.clone(),
+            shared: Arc::new(Mutex::new(PoolState {
+                max_surfaces,
+                batch_size: DEFAULT_BATCH_SIZE,
+                allocated: 0,
+                free: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Sets how many surfaces are created at once when no free surface is available (default: 4).
+    ///
+    /// Batches are cut short if they would exceed the maximum number of surfaces.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        assert!(
+            batch_size > 0,
+            "at least one surface has to be created per batch"
+        );
+        self.lock().batch_size = batch_size;
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // The state is consistent whenever the lock is released.
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hands out a [`Surface`] with the given size, format and attributes.
+    ///
+    /// A free surface is reused if one is available. Otherwise, a new batch of surfaces is
+    /// created, destroying free surfaces of other formats and sizes if the pool is full.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ErrorKind::PoolExhausted`][crate::error::ErrorKind::PoolExhausted] if the pool
+    /// is full and none of its surfaces can be reused or destroyed. That error is
+    /// [transient][Error::is_transient], since surfaces become available again once they are
+    /// returned and the driver is done with them.
+    ///
+    /// Attributes with pointer or function values (like external buffer descriptors) are rejected
+    /// with [`ErrorKind::InvalidInput`][crate::error::ErrorKind::InvalidInput], since surfaces
+    /// created from them are tied to the pointed-to data and can't be shared.
+    pub fn get(
+        &self,
+        width: u32,
+        height: u32,
+        format: RTFormat,
+        attribs: &[SurfaceAttrib],
+    ) -> Result<SurfaceLease> {
+        if let Some(attrib) = attribs.iter().find(|attrib| {
+            attrib.value.type_ == VAGenericValueType::Pointer
+                || attrib.value.type_ == VAGenericValueType::Func
+        }) {
+            return Err(Error::invalid_input(format!(
+                "surface attribute {:?} has a {:?} value, which can't be pooled",
+                attrib.type_, attrib.value.type_
+            )));
+        }
+
+        let key = SurfaceKey {
+            format,
+            width,
+            height,
+            attribs: attribs.iter().map(AttribKey::from).collect(),
+        };
+
+        // The status of free surfaces is queried without holding the lock, so they are taken out
+        // of the pool in the meantime.
+        let free = self.lock().take_free(&key);
+        let mut sorted = sort_out(free, 1);
+        let mut state = self.lock();
+        state.put_back(sorted.rest, sorted.destroyed);
+        if let Some((_, surface)) = sorted.idle.pop() {
+            drop(state);
+            return Ok(self.lease(key, surface));
+        }
+
+        let mut evicted = Vec::new();
+        if state.allocated == state.max_surfaces {
+            // Make room by destroying a free surface that is no longer busy. Surfaces matching
+            // `key` may have become idle since they were checked, in which case one is reused.
+            let free = state.take_all_free();
+            drop(state);
+            let sorted = sort_out(free, 1);
+            state = self.lock();
+            state.put_back(sorted.rest, sorted.destroyed);
+            evicted = sorted.idle;
+            if let Some(i) = evicted.iter().position(|(k, _)| *k == key) {
+                let (_, surface) = evicted.swap_remove(i);
+                drop(state);
+                return Ok(self.lease(key, surface));
+            }
+            state.allocated -= evicted.len();
+        }
+
+        let room = state.max_surfaces - state.allocated;
+        if room == 0 {
+            return Err(Error::from(Repr::PoolExhausted {
+                max: state.max_surfaces,
+            }));
+        }
+        let count = state.batch_size.min(room);
+        state.allocated += count;
+        drop(state);
+        drop(evicted);
+
+        log::trace!("creating {count} {format:?} surfaces of size {width}x{height} for pool");
+        let mut attribs = attribs.to_vec();
+        let mut surfaces =
+            match Surface::create_batch(&self.d, width, height, format, &mut attribs, count) {
+                Ok(surfaces) => surfaces,
+                Err(e) => {
+                    self.lock().allocated -= count;
+                    return Err(e);
+                }
+            };
+        let surface = surfaces.pop().unwrap();
+        if !surfaces.is_empty() {
+            self.lock()
+                .free
+                .entry(key.clone())
+                .or_default()
+                .extend(surfaces);
+        }
+        Ok(self.lease(key, surface))
+    }
+
+    fn lease(&self, key: SurfaceKey, surface: Surface) -> SurfaceLease {
+        SurfaceLease {
+            surface: Some(surface),
+            key,
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Returns the maximum number of surfaces the pool will hold.
+    pub fn max_surfaces(&self) -> usize {
+        self.lock().max_surfaces
+    }
+
+    /// Returns the number of surfaces that currently exist, including leased ones.
+    pub fn allocated(&self) -> usize {
+        self.lock().allocated
+    }
+
+    /// Returns the number of surfaces that are not leased.
+    ///
+    /// Some of them may still be busy.
+    pub fn free(&self) -> usize {
+        self.lock().free.values().map(Vec::len).sum()
+    }
+
+    /// Destroys all free surfaces that are no longer busy.
+    pub fn trim(&self) {
+        let free = self.lock().take_all_free();
+        let sorted = sort_out(free, usize::MAX);
+        let mut state = self.lock();
+        state.put_back(sorted.rest, sorted.destroyed + sorted.idle.len());
+        drop(state);
+        drop(sorted.idle);
+    }
+}
+
+impl fmt::Debug for SurfacePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("SurfacePool")
+            .field("max_surfaces", &state.max_surfaces)
+            .field("batch_size", &state.batch_size)
+            .field("allocated", &state.allocated)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Deref for SurfaceLease {
+    type Target = Surface;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.surface.as_ref().unwrap()
+    }
+}
+
+impl SurfaceLease {
+    fn surface_mut(&mut self) -> &mut Surface {
+        self.surface.as_mut().unwrap()
+    }
+
+    /// See [`Surface::sync`].
+    pub fn sync(&mut self) -> Result<()> {
+        self.surface_mut().sync()
+    }
+
+    /// See [`Surface::sync_timeout`].
+    pub fn sync_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.surface_mut().sync_timeout(timeout)
+    }
+
+    /// See [`Surface::sync_async`].
+    pub fn sync_async(&mut self) -> SurfaceSync<'_> {
+        self.surface_mut().sync_async()
+    }
+
+    /// See [`Surface::copy_to_image`].
+    pub fn copy_to_image(&mut self, image: &mut Image) -> Result<()> {
+        self.surface_mut().copy_to_image(image)
+    }
+}
+
+impl fmt::Debug for SurfaceLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SurfaceLease").field(&**self).finish()
+    }
+}
+
+impl Drop for SurfaceLease {
+    fn drop(&mut self) {
+        if let Some(surface) = self.surface.take() {
+            let mut state = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+            state
+                .free
+                .entry(self.key.clone())
+                .or_default()
+                .push(surface);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    future::Future,
+    pin::pin,
+    ptr,
+    sync::Arc,
+    task::{self, Poll, Wake, Waker},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    backend::FakeBackend,
+    buffer::{Buffer, BufferType},
+    config::Config,
+    context::Context,
+    display::{Display, LibvaFeatures},
+    error::{ErrorKind, VAError},
+    Entrypoint, PixelFormat, Profile,
+};
+
+use super::{
+    DecodeErrorType, GenericValue, RTFormat, Surface, SurfaceAttrib, SurfaceAttribEnum,
+    SurfaceDecodeMBErrors, SurfacePool, SurfaceStatus, VAGenericValueType, VAGenericValueUnion,
+};
+
+#[test]
+fn decode_errors() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let mut surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    assert!(surface.decode_errors().unwrap().is_empty());
+
+    fake.corrupt_next_picture(vec![
+        SurfaceDecodeMBErrors::new(DecodeErrorType::MBError, 2, 5, 4),
+        SurfaceDecodeMBErrors::new(DecodeErrorType::SliceMissing, 8, 15, 8),
+    ]);
+    let picture = context.begin_picture(&surface).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+    let err = surface.sync().unwrap_err();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_DECODING_ERROR));
+    assert!(err.is_invalid_input());
+
+    let errors = surface.decode_errors().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].error_type(), DecodeErrorType::MBError);
+    assert_eq!(
+        (errors[0].start_mb(), errors[0].end_mb(), errors[0].num_mb()),
+        (2, 5, 4)
+    );
+    assert_eq!(errors[1].error_type(), DecodeErrorType::SliceMissing);
+    assert_eq!(
+        (errors[1].start_mb(), errors[1].end_mb(), errors[1].num_mb()),
+        (8, 15, 8)
+    );
+
+    // Decoding a new picture into the surface clears the errors.
+    let picture = context.begin_picture(&surface).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+    surface.sync().unwrap();
+    assert!(surface.decode_errors().unwrap().is_empty());
+}
+
+#[test]
+fn buffers_live_until_sync() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let mut surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+
+    let buf = Buffer::new_data(&context, BufferType::SliceData, &[1, 2, 3]).unwrap();
+    let mut picture = context.begin_picture(&surface).unwrap();
+    picture.render_picture(buf).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+    assert_eq!(fake.live_objects().buffers, 1);
+
+    // A failed sync doesn't release the buffers, but a decoding error does.
+    fake.fail_next("vaSyncSurface", VAError::ERROR_INVALID_SURFACE);
+    surface.sync().unwrap_err();
+    assert_eq!(fake.live_objects().buffers, 1);
+    fake.fail_next("vaSyncSurface", VAError::ERROR_DECODING_ERROR);
+    surface.sync().unwrap_err();
+    assert_eq!(fake.live_objects().buffers, 0);
+
+    // Submitting another picture, or querying the status, releases the buffers of finished
+    // pictures without a sync.
+    let submit = |context: &mut Context, surface: &Surface| {
+        let buf = Buffer::new_data(context, BufferType::SliceData, &[1, 2, 3]).unwrap();
+        let mut picture = context.begin_picture(surface).unwrap();
+        picture.render_picture(buf).unwrap();
+        unsafe { picture.end_picture().unwrap() }
+    };
+    for _ in 0..3 {
+        submit(&mut context, &surface);
+    }
+    assert_eq!(fake.live_objects().buffers, 1);
+    assert_eq!(surface.status().unwrap(), SurfaceStatus::Ready);
+    assert_eq!(fake.live_objects().buffers, 0);
+    fake.delay_next_picture(Duration::from_millis(100));
+    submit(&mut context, &surface);
+    submit(&mut context, &surface);
+    assert_eq!(surface.status().unwrap(), SurfaceStatus::Rendering);
+    assert_eq!(fake.live_objects().buffers, 2);
+    surface.sync().unwrap();
+    assert_eq!(fake.live_objects().buffers, 0);
+
+    // The picture keeps its target alive. Once the surface is destroyed, it is synced first.
+    let buf = Buffer::new_data(&context, BufferType::SliceData, &[1, 2, 3]).unwrap();
+    let mut picture = context.begin_picture(&surface).unwrap();
+    picture.render_picture(buf).unwrap();
+    drop(surface);
+    assert_eq!(fake.live_objects().surfaces, 1);
+    unsafe { picture.end_picture().unwrap() }
+    assert_eq!(fake.live_objects().surfaces, 0);
+    assert_eq!(fake.live_objects().buffers, 0);
+    assert_eq!(fake.submissions().len(), 7);
+
+    // Buffers in flight keep their context alive, so they are destroyed before it.
+    let surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    submit(&mut context, &surface);
+    drop(context);
+    assert_eq!(fake.live_objects().contexts, 1);
+    drop(surface);
+    assert_eq!(fake.live_objects().contexts, 0);
+    assert_eq!(fake.live_objects().buffers, 0);
+}
+
+/// Polls `future` on the current thread until it completes.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = task::Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn sync_timeout() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let mut surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let mut coded = Buffer::<u8>::new_empty(&context, BufferType::EncCoded, 16).unwrap();
+
+    let submit = |context: &mut Context, surface: &Surface| {
+        fake.delay_next_picture(Duration::from_millis(100));
+        let buf = Buffer::new_data(context, BufferType::SliceData, &[1, 2, 3]).unwrap();
+        let mut picture = context.begin_picture(surface).unwrap();
+        picture.render_picture(buf).unwrap();
+        unsafe { picture.end_picture().unwrap() }
+    };
+
+    submit(&mut context, &surface);
+    assert_eq!(surface.status().unwrap(), SurfaceStatus::Rendering);
+    let err = surface.sync_timeout(Duration::from_millis(1)).unwrap_err();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_TIMEDOUT));
+    assert!(err.is_transient());
+    assert_eq!(fake.live_objects().buffers, 2);
+    let err = coded.sync_timeout(Duration::ZERO).unwrap_err();
+    assert_eq!(err.as_libva(), Some(VAError::ERROR_TIMEDOUT));
+
+    // The futures wait on a helper thread.
+    let mut future = pin!(surface.sync_async());
+    let waker = Waker::from(Arc::new(NoopWaker));
+    assert!(future
+        .as_mut()
+        .poll(&mut task::Context::from_waker(&waker))
+        .is_pending());
+    block_on(future).unwrap();
+    assert_eq!(surface.status().unwrap(), SurfaceStatus::Ready);
+    assert_eq!(fake.live_objects().buffers, 1);
+    block_on(coded.sync_async()).unwrap();
+    surface.sync_timeout(Duration::ZERO).unwrap();
+    coded.sync_timeout(Duration::ZERO).unwrap();
+
+    // Dropping a pending future doesn't block, and the helper thread stops waiting for it.
+    submit(&mut context, &surface);
+    let mut future = Box::pin(coded.sync_async());
+    assert!(future
+        .as_mut()
+        .poll(&mut task::Context::from_waker(&waker))
+        .is_pending());
+    drop(future);
+    thread::sleep(Duration::from_millis(20));
+    let calls = fake.call_count("vaSyncBuffer");
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(fake.call_count("vaSyncBuffer"), calls);
+
+    // Several futures can wait at the same time.
+    let mut surface_sync = Box::pin(surface.sync_async());
+    assert!(surface_sync
+        .as_mut()
+        .poll(&mut task::Context::from_waker(&waker))
+        .is_pending());
+    block_on(coded.sync_async()).unwrap();
+    block_on(surface_sync).unwrap();
+
+    // Without `vaSyncSurface2`, the surface status is polled instead.
+    let fake = FakeBackend::new();
+    fake.set_features(LibvaFeatures::empty());
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let mut surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let err = surface.sync_timeout(Duration::ZERO).unwrap_err();
+    assert_eq!(err.missing_function(), Some("vaSyncSurface2"));
+    fake.delay_next_picture(Duration::from_millis(50));
+    let picture = context.begin_picture(&surface).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+    block_on(surface.sync_async()).unwrap();
+    assert_eq!(surface.status().unwrap(), SurfaceStatus::Ready);
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+#[test]
+fn surface_pool() {
+    let fake = FakeBackend::new();
+    let display = Display::with_backend(fake.clone()).unwrap();
+    let pool = SurfacePool::new(&display, 4).with_batch_size(3);
+
+    // Surfaces are created in batches, and returned to the pool when the lease is dropped.
+    let lease = pool.get(64, 64, RTFormat::YUV420, &[]).unwrap();
+    let id = lease.id();
+    assert_eq!(fake.live_objects().surfaces, 3);
+    assert_eq!((pool.allocated(), pool.free()), (3, 2));
+    drop(lease);
+    assert_eq!(pool.free(), 3);
+
+    // The format, size and attributes have to match.
+    let other = pool
+        .get(
+            64,
+            64,
+            RTFormat::YUV420,
+            &[SurfaceAttribEnum::PixelFormat(PixelFormat::NV12).into()],
+        )
+        .unwrap();
+    assert_eq!(pool.allocated(), 4);
+    assert_eq!(fake.live_objects().surfaces, 4);
+    drop(other);
+
+    // Surfaces that the driver is still rendering to aren't handed out again.
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let leases = (0..3)
+        .map(|_| pool.get(64, 64, RTFormat::YUV420, &[]).unwrap())
+        .collect::<Vec<_>>();
+    assert!(leases.iter().any(|lease| lease.id() == id));
+    fake.delay_next_picture(Duration::from_millis(100));
+    let picture = context.begin_picture(&leases[0]).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+    let busy = leases[0].id();
+    drop(leases);
+    let leases = [(); 2].map(|()| pool.get(64, 64, RTFormat::YUV420, &[]).unwrap());
+    assert!(leases.iter().all(|lease| lease.id() != busy));
+
+    // Free surfaces of other sizes are destroyed to stay below the maximum. Once the pool is
+    // full, a transient error is returned.
+    let small = pool.get(32, 32, RTFormat::YUV420, &[]).unwrap();
+    assert_eq!(pool.allocated(), 4);
+    assert_eq!(fake.live_objects().surfaces, 4);
+    let err = pool.get(64, 64, RTFormat::YUV420, &[]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PoolExhausted);
+    assert!(err.is_transient());
+
+    // Busy surfaces are reused once the driver is done with them.
+    thread::sleep(Duration::from_millis(100));
+    let reused = pool.get(64, 64, RTFormat::YUV420, &[]).unwrap();
+    assert_eq!(reused.id(), busy);
+    drop((small, leases, reused));
+    pool.trim();
+    assert_eq!((pool.allocated(), pool.free()), (0, 0));
+    assert_eq!(fake.live_objects().surfaces, 0);
+
+    // Creation failures don't count towards the maximum.
+    fake.fail_next("vaCreateSurfaces", VAError::ERROR_ALLOCATION_FAILED);
+    pool.get(64, 64, RTFormat::YUV420, &[]).unwrap_err();
+    assert_eq!(pool.allocated(), 0);
+
+    // Free surfaces whose status can't be queried are destroyed.
+    drop(pool.get(64, 64, RTFormat::YUV420, &[]).unwrap());
+    fake.fail_next("vaQuerySurfaceStatus", VAError::ERROR_OPERATION_FAILED);
+    let _lease = pool.get(64, 64, RTFormat::YUV420, &[]).unwrap();
+    assert_eq!((pool.allocated(), pool.free()), (2, 1));
+    assert_eq!(fake.live_objects().surfaces, 2);
+
+    // Attributes with pointer values are rejected.
+    let mut attrib = SurfaceAttrib::from(SurfaceAttribEnum::PixelFormat(PixelFormat::NV12));
+    attrib.value = GenericValue {
+        type_: VAGenericValueType::Pointer,
+        value: VAGenericValueUnion { p: ptr::null_mut() },
+    };
+    let err = pool.get(64, 64, RTFormat::YUV420, &[attrib]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(pool.allocated(), 2);
+}
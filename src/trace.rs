@@ -0,0 +1,573 @@
+//! Tracing of libva calls.
+//!
+//! A [`Trace`] records every libva call made through a [`Display`][crate::display::Display]: the
+//! function name, its arguments (including object IDs and buffer types and sizes), the returned
+//! value and how long the call took. This is similar to libva's `LIBVA_TRACE` environment
+//! variable, but can be enabled per display from within the application, via
+//! [`DisplayBuilder::trace`][crate::display::DisplayBuilder::trace]. This works with any
+//! [`Backend`][crate::backend::Backend].
+//!
+//! Records are either kept in an in-memory ring buffer, or written to a file as JSON lines. When
+//! writing to a file, every call is announced by a line written before it's forwarded to the
+//! driver, and its record is written as soon as it returns, so that the file contains the exact
+//! sequence of calls leading up to a crash in the driver, including the call that crashed.
+//!
+//! # Example
+//!
+//! ```
+//! use v_ayylmao::{backend::FakeBackend, display::Display, trace::Trace};
+//!
+//! let trace = Trace::ring_buffer(100);
+//! let display = Display::builder()
+//!     .trace(trace.clone())
+//!     .open_backend(FakeBackend::new())?;
+//! display.query_profiles()?;
+//!
+//! let last = trace.records().pop().unwrap();
+//! assert_eq!(last.function(), "vaQueryConfigProfiles");
+//! # Ok::<_, v_ayylmao::error::Error>(())
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use core::fmt;
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::{c_char, c_float, c_int, c_uint, c_void},
+    fmt::Write as _,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    buffer::BufferType,
+    error::{VAError, VAStatus},
+    intercept::Call,
+    raw::VAPrivFunc,
+    subpicture::SubpictureFlags,
+    surface::{ExportSurfaceFlags, RTFormat, SurfaceAttribMemoryType},
+    vpp::FilterType,
+    Entrypoint, Error, Profile, Result,
+};
+
+/// A traced argument or return value.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TraceValue {
+    /// An integer, including object IDs.
+    Int(i64),
+    Float(f64),
+    /// A named constant or set of flags.
+    Named {
+        /// The name, as formatted by the type's [`Debug`][fmt::Debug] impl.
+        name: String,
+        /// The raw value.
+        value: i64,
+    },
+    /// A pointer whose target is not traced.
+    Pointer(usize),
+    /// Values that were read through a pointer, like an array of object IDs.
+    List(Vec<TraceValue>),
+}
+
+impl TraceValue {
+    fn named(name: String, value: i64) -> Self {
+        Self::Named { name, value }
+    }
+
+    /// Returns the integer value of an integer or named constant.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            TraceValue::Int(i) | TraceValue::Named { value: i, .. } => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the address of a [`TraceValue::Pointer`].
+    pub(crate) fn as_pointer(&self) -> Option<usize> {
+        match self {
+            TraceValue::Pointer(p) => Some(*p),
+            _ => None,
+        }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match self {
+            TraceValue::Int(i) => write!(out, "{i}").unwrap(),
+            TraceValue::Float(f) if f.is_finite() => write!(out, "{f}").unwrap(),
+            TraceValue::Float(_) => out.push_str("null"),
+            TraceValue::Named { name, .. } => write_json_str(out, name),
+            TraceValue::Pointer(0) => out.push_str("null"),
+            TraceValue::Pointer(p) => write_json_str(out, &format!("{p:#x}")),
+            TraceValue::List(list) => {
+                out.push('[');
+                for (i, value) in list.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    value.write_json(out);
+                }
+                out.push(']');
+            }
+        }
+    }
+}
+
+impl fmt::Display for TraceValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceValue::Int(i) => i.fmt(f),
+            TraceValue::Float(v) => v.fmt(f),
+            TraceValue::Named { name, .. } => f.write_str(name),
+            TraceValue::Pointer(p) => write!(f, "{p:#x}"),
+            TraceValue::List(list) => f
+                .debug_list()
+                .entries(list.iter().map(DisplayAsDebug))
+                .finish(),
+        }
+    }
+}
+
+struct DisplayAsDebug<'a>(&'a TraceValue);
+
+impl fmt::Debug for DisplayAsDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.0, f)
+    }
+}
+
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Converts a libva function argument into a [`TraceValue`].
+pub(crate) trait TraceArg {
+    fn trace(&self) -> TraceValue;
+}
+
+macro_rules! trace_int {
+    ($($t:ty),+) => {
+        $(
+            impl TraceArg for $t {
+                fn trace(&self) -> TraceValue {
+                    TraceValue::Int(*self as i64)
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! trace_enum {
+    ($($t:ty),+) => {
+        $(
+            impl TraceArg for $t {
+                fn trace(&self) -> TraceValue {
+                    TraceValue::named(format!("{:?}", self), self.0.into())
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! trace_flags {
+    ($($t:ty),+) => {
+        $(
+            impl TraceArg for $t {
+                fn trace(&self) -> TraceValue {
+                    TraceValue::named(format!("{:?}", self), self.bits().into())
+                }
+            }
+        )+
+    };
+}
+
+trace_int!(i16, u16, c_int, c_uint, u64);
+trace_enum!(Profile, Entrypoint, BufferType, FilterType);
+trace_flags!(
+    RTFormat,
+    SubpictureFlags,
+    SurfaceAttribMemoryType,
+    ExportSurfaceFlags
+);
+
+impl TraceArg for c_float {
+    fn trace(&self) -> TraceValue {
+        TraceValue::Float(f64::from(*self))
+    }
+}
+
+impl TraceArg for VAStatus {
+    fn trace(&self) -> TraceValue {
+        let name = if *self == VAStatus::SUCCESS {
+            format!("{self:?}")
+        } else {
+            format!("{:?}", VAError(self.0))
+        };
+        TraceValue::named(name, self.0.into())
+    }
+}
+
+impl<T> TraceArg for *const T {
+    fn trace(&self) -> TraceValue {
+        TraceValue::Pointer(*self as usize)
+    }
+}
+
+impl<T> TraceArg for *mut T {
+    fn trace(&self) -> TraceValue {
+        TraceValue::Pointer(*self as usize)
+    }
+}
+
+impl TraceArg for unsafe extern "C" fn(*mut c_void, *const c_char) {
+    fn trace(&self) -> TraceValue {
+        TraceValue::Pointer(*self as usize)
+    }
+}
+
+impl TraceArg for Option<VAPrivFunc> {
+    fn trace(&self) -> TraceValue {
+        TraceValue::Pointer(self.map_or(0, |f| f as usize))
+    }
+}
+
+/// Converts the return value of a libva function into a [`TraceValue`].
+pub(crate) trait TraceRet {
+    fn trace_ret(&self) -> Option<TraceValue>;
+}
+
+impl TraceRet for () {
+    fn trace_ret(&self) -> Option<TraceValue> {
+        None
+    }
+}
+
+impl<T: TraceArg> TraceRet for T {
+    fn trace_ret(&self) -> Option<TraceValue> {
+        Some(self.trace())
+    }
+}
+
+/// A single recorded libva call.
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+    seq: u64,
+    time: Duration,
+    function: &'static str,
+    args: Vec<(&'static str, TraceValue)>,
+    result: Option<TraceValue>,
+    duration: Duration,
+}
+
+impl TraceRecord {
+    /// Returns the sequence number of the call.
+    ///
+    /// Sequence numbers start at 0 and increase by one for every call made through the [`Trace`],
+    /// so gaps indicate that records were dropped from the ring buffer. They are assigned when the
+    /// call is made, while records are added once it returns, so concurrent calls may be recorded
+    /// out of order.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the time at which the call was made, relative to the creation of the [`Trace`].
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Returns the name of the libva function that was called.
+    pub fn function(&self) -> &'static str {
+        self.function
+    }
+
+    /// Returns the arguments of the call, by parameter name.
+    ///
+    /// Pointers to object IDs (like the list of buffers passed to `vaRenderPicture`, or the ID
+    /// returned by `vaCreateBuffer`) are resolved to [`TraceValue::List`]s. `vaRenderPicture` calls
+    /// additionally have `buffer_types` and `buffer_sizes` arguments listing the [`BufferType`]
+    /// and size in bytes of each buffer.
+    pub fn args(&self) -> &[(&'static str, TraceValue)] {
+        &self.args
+    }
+
+    /// Returns the value of the argument called `name`.
+    pub fn arg(&self, name: &str) -> Option<&TraceValue> {
+        self.args.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// Returns the integer written through the output pointer argument called `name`.
+    fn output(&self, name: &str) -> Option<i64> {
+        match self.arg(name) {
+            Some(TraceValue::List(values)) => values.first().and_then(TraceValue::as_int),
+            _ => None,
+        }
+    }
+
+    /// Returns the value returned by the call, or [`None`] if the function doesn't return
+    /// anything.
+    pub fn result(&self) -> Option<&TraceValue> {
+        self.result.as_ref()
+    }
+
+    /// Returns how long the call took.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Formats the record as a single line of JSON (without a trailing newline).
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write_json_call(&mut out, self.seq, self.time, self.function, &self.args);
+        out.push_str(",\"result\":");
+        match &self.result {
+            Some(value) => value.write_json(&mut out),
+            None => out.push_str("null"),
+        }
+        write!(out, ",\"duration_us\":{}}}", self.duration.as_micros()).unwrap();
+        out
+    }
+}
+
+/// Writes the fields describing a call, leaving the JSON object open.
+fn write_json_call(
+    out: &mut String,
+    seq: u64,
+    time: Duration,
+    function: &str,
+    args: &[(&'static str, TraceValue)],
+) {
+    write!(
+        out,
+        "{{\"seq\":{seq},\"time_us\":{},\"function\":",
+        time.as_micros()
+    )
+    .unwrap();
+    write_json_str(out, function);
+    out.push_str(",\"args\":{");
+    for (i, (name, value)) in args.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        write_json_str(out, name);
+        out.push(':');
+        value.write_json(out);
+    }
+    out.push('}');
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.function)?;
+        for (i, (name, value)) in self.args.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{name}={value}")?;
+        }
+        f.write_str(")")?;
+        if let Some(result) = &self.result {
+            write!(f, " = {result}")?;
+        }
+        write!(f, " [{:?}]", self.duration)
+    }
+}
+
+enum Output {
+    RingBuffer {
+        records: VecDeque<TraceRecord>,
+        capacity: usize,
+    },
+    JsonLines(File),
+}
+
+struct TraceState {
+    next_seq: u64,
+    output: Output,
+    /// Type and size of every live buffer, to annotate `vaRenderPicture` calls.
+    buffers: HashMap<i64, (TraceValue, i64)>,
+}
+
+/// Destination of the records of traced libva calls.
+///
+/// [`Trace`] is a cheaply clonable handle, so a clone can be passed to
+/// [`DisplayBuilder::trace`][crate::display::DisplayBuilder::trace] while the original is used to
+/// access the records.
+#[derive(Clone)]
+pub struct Trace {
+    start: Instant,
+    state: Arc<Mutex<TraceState>>,
+}
+
+impl Trace {
+    fn new(output: Output) -> Self {
+        Self {
+            start: Instant::now(),
+            state: Arc::new(Mutex::new(TraceState {
+                next_seq: 0,
+                output,
+                buffers: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Creates a [`Trace`] that keeps the last `capacity` records in memory.
+    pub fn ring_buffer(capacity: usize) -> Self {
+        Self::new(Output::RingBuffer {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    /// Creates a [`Trace`] that writes records to the file at `path`, one JSON object per line.
+    ///
+    /// Before a call is forwarded to the driver, a line with its sequence number, time, function
+    /// and arguments, and a `"phase":"begin"` field is written. The full record, in the format of
+    /// [`TraceRecord::to_json`], follows once the call returns.
+    ///
+    /// The file is created if it doesn't exist, and truncated if it does.
+    pub fn json_lines(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            Error::from(io::Error::new(
+                e.kind(),
+                format!("failed to create {}: {e}", path.display()),
+            ))
+        })?;
+        Ok(Self::new(Output::JsonLines(file)))
+    }
+
+    /// Returns the records currently held in the ring buffer, oldest first.
+    ///
+    /// If this [`Trace`] writes to a file, this returns an empty list.
+    pub fn records(&self) -> Vec<TraceRecord> {
+        match &self.lock().output {
+            Output::RingBuffer { records, .. } => records.iter().cloned().collect(),
+            Output::JsonLines(_) => Vec::new(),
+        }
+    }
+
+    /// Removes all records from the ring buffer.
+    pub fn clear(&self) {
+        if let Output::RingBuffer { records, .. } = &mut self.lock().output {
+            records.clear();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TraceState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Assigns a sequence number to a call that is about to be made, and announces it in the file
+    /// if this [`Trace`] writes to one.
+    pub(crate) fn begin(&self, function: &'static str, args: &[(&'static str, TraceValue)]) -> u64 {
+        let mut state = self.lock();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        if let Output::JsonLines(file) = &mut state.output {
+            let mut line = String::new();
+            write_json_call(&mut line, seq, self.start.elapsed(), function, args);
+            line.push_str(",\"phase\":\"begin\"}\n");
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                log::error!("failed to write libva trace: {e}");
+            }
+        }
+        seq
+    }
+
+    /// Records a call that has returned. `seq` is the sequence number returned by [`Trace::begin`].
+    pub(crate) fn record(&self, seq: u64, start: Instant, duration: Duration, call: Call) {
+        let mut state = self.lock();
+        let mut record = TraceRecord {
+            seq,
+            time: start.saturating_duration_since(self.start),
+            function: call.function,
+            args: call.args,
+            result: call.result,
+            duration,
+        };
+        state.track_buffers(&mut record);
+
+        match &mut state.output {
+            Output::RingBuffer { records, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if records.len() == *capacity {
+                    records.pop_front();
+                }
+                records.push_back(record);
+            }
+            Output::JsonLines(file) => {
+                let mut line = record.to_json();
+                line.push('\n');
+                if let Err(e) = file.write_all(line.as_bytes()) {
+                    log::error!("failed to write libva trace: {e}");
+                }
+            }
+        }
+    }
+}
+
+impl TraceState {
+    /// Keeps track of buffer types and sizes, and adds them to `vaRenderPicture` records.
+    fn track_buffers(&mut self, record: &mut TraceRecord) {
+        let success = record.result.as_ref().and_then(TraceValue::as_int) == Some(0);
+        match record.function {
+            "vaCreateBuffer" if success => {
+                let size = record.arg("size").and_then(TraceValue::as_int);
+                let num = record.arg("num_elements").and_then(TraceValue::as_int);
+                if let (Some(id), Some(ty), Some(size), Some(num)) =
+                    (record.output("buf_id"), record.arg("type_"), size, num)
+                {
+                    self.buffers.insert(id, (ty.clone(), size * num));
+                }
+            }
+            "vaCreateBuffer2" if success => {
+                let height = record.arg("height").and_then(TraceValue::as_int);
+                if let (Some(id), Some(ty), Some(pitch), Some(height)) = (
+                    record.output("buf_id"),
+                    record.arg("type_"),
+                    record.output("pitch"),
+                    height,
+                ) {
+                    self.buffers.insert(id, (ty.clone(), pitch * height));
+                }
+            }
+            "vaDestroyBuffer" => {
+                if let Some(id) = record.arg("buffer_id").and_then(TraceValue::as_int) {
+                    self.buffers.remove(&id);
+                }
+            }
+            "vaRenderPicture" => {
+                let Some(TraceValue::List(ids)) = record.arg("buffers") else {
+                    return;
+                };
+                let (types, sizes) = ids
+                    .iter()
+                    .map(
+                        |id| match id.as_int().and_then(|id| self.buffers.get(&id)) {
+                            Some((ty, size)) => (ty.clone(), TraceValue::Int(*size)),
+                            None => (TraceValue::named("?".into(), -1), TraceValue::Int(-1)),
+                        },
+                    )
+                    .unzip();
+                record.args.push(("buffer_types", TraceValue::List(types)));
+                record.args.push(("buffer_sizes", TraceValue::List(sizes)));
+            }
+            _ => {}
+        }
+    }
+}
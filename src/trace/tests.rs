@@ -0,0 +1,186 @@
+use std::fs;
+
+use crate::{
+    backend::FakeBackend,
+    buffer::{Buffer, BufferType},
+    check,
+    config::Config,
+    context::Context,
+    display::Display,
+    error::VAError,
+    surface::{RTFormat, Surface},
+    Entrypoint, Profile,
+};
+
+use super::{Trace, TraceValue};
+
+#[test]
+fn ring_buffer() {
+    let trace = Trace::ring_buffer(64);
+    let fake = FakeBackend::new();
+    let display = Display::builder()
+        .trace(trace.clone())
+        .open_backend(fake.clone())
+        .unwrap();
+    trace.clear();
+
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let mut context = Context::new(&config, 64, 64).unwrap();
+    let mut surface = Surface::new(&display, 64, 64, RTFormat::YUV420).unwrap();
+    let buf = Buffer::new_param(&context, BufferType::PictureParameter, 0u32).unwrap();
+    let mut picture = context.begin_picture(&surface).unwrap();
+    picture.render_picture(buf).unwrap();
+    unsafe { picture.end_picture().unwrap() }
+
+    fake.fail_next("vaSyncSurface", VAError::ERROR_DECODING_ERROR);
+    assert!(surface.sync().is_err());
+
+    let records = trace.records();
+    let functions = records.iter().map(|r| r.function()).collect::<Vec<_>>();
+    assert_eq!(
+        functions,
+        [
+            "vaCreateConfig",
+            "vaCreateContext",
+            "vaCreateSurfaces",
+            "vaCreateBuffer",
+            "vaBeginPicture",
+            "vaRenderPicture",
+            "vaEndPicture",
+            "vaSyncSurface",
+            // The submitted buffer is released once the picture has finished.
+            "vaDestroyBuffer",
+        ]
+    );
+    assert!(records.windows(2).all(|w| w[0].seq() + 1 == w[1].seq()));
+
+    let Some(TraceValue::List(config_id)) = records[0].arg("config_id") else {
+        panic!("config ID wasn't resolved: {}", records[0]);
+    };
+    assert_eq!(records[1].arg("config_id"), config_id.first());
+    let buffer = &records[3];
+    assert_eq!(buffer.arg("type_").unwrap().to_string(), "PictureParameter");
+    assert_eq!(buffer.arg("type_").unwrap().as_int(), Some(0));
+    assert_eq!(buffer.arg("size"), Some(&TraceValue::Int(4)));
+    let TraceValue::List(buf_id) = buffer.arg("buf_id").unwrap() else {
+        panic!("buffer ID wasn't resolved: {buffer}");
+    };
+
+    let render = &records[5];
+    assert_eq!(
+        render.arg("buffers"),
+        Some(&TraceValue::List(buf_id.clone()))
+    );
+    assert_eq!(
+        render.arg("buffer_types").unwrap().to_string(),
+        "[PictureParameter]"
+    );
+    assert_eq!(
+        render.arg("buffer_sizes"),
+        Some(&TraceValue::List(vec![TraceValue::Int(4)]))
+    );
+    assert_eq!(render.result().unwrap().to_string(), "SUCCESS");
+
+    assert_eq!(
+        records[7].result().unwrap().to_string(),
+        "ERROR_DECODING_ERROR"
+    );
+
+    // Old records are dropped once the capacity is reached.
+    let trace = Trace::ring_buffer(2);
+    let display = Display::builder()
+        .trace(trace.clone())
+        .open_backend(FakeBackend::new())
+        .unwrap();
+    let surfaces = (0..3)
+        .map(|_| Surface::new(&display, 16, 16, RTFormat::YUV420).unwrap())
+        .collect::<Vec<_>>();
+    drop(surfaces);
+    let records = trace.records();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].function(), "vaDestroySurfaces");
+}
+
+#[test]
+fn json_lines() {
+    let path = std::env::temp_dir().join(format!("v-ayylmao-trace-{}.jsonl", std::process::id()));
+    let trace = Trace::json_lines(&path).unwrap();
+    let display = Display::builder()
+        .trace(trace)
+        .open_backend(FakeBackend::new())
+        .unwrap();
+    let surface = Surface::new(&display, 16, 16, RTFormat::YUV420).unwrap();
+    drop((surface, display));
+
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let lines = contents.lines().collect::<Vec<_>>();
+    let pos = lines
+        .iter()
+        .position(|line| line.contains("\"function\":\"vaCreateSurfaces\""))
+        .unwrap();
+    // The call is announced before it's made, and recorded again once it returns.
+    let (begin, create) = (lines[pos], lines[pos + 1]);
+    assert!(begin.ends_with(",\"phase\":\"begin\"}"), "{begin}");
+    assert!(!begin.contains("\"result\""), "{begin}");
+    assert_eq!(begin.split(',').next(), create.split(',').next());
+    assert!(
+        create.contains("\"format\":\"YUV420\",\"width\":16,\"height\":16,\"surfaces\":[",),
+        "{create}"
+    );
+    assert!(create.contains("\"result\":\"SUCCESS\""), "{create}");
+    assert!(lines
+        .last()
+        .unwrap()
+        .contains("\"function\":\"vaTerminate\""));
+}
+
+#[test]
+fn create_buffer2() {
+    let trace = Trace::ring_buffer(8);
+    let display = Display::builder()
+        .trace(trace.clone())
+        .open_backend(FakeBackend::new())
+        .unwrap();
+    let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD).unwrap();
+    let context = Context::new(&config, 64, 64).unwrap();
+    let (mut unit_size, mut pitch, mut id) = (0, 0, 0);
+    unsafe {
+        let d = &context.d;
+        check(
+            d.libva.vaCreateBuffer2(
+                d.raw,
+                context.id,
+                BufferType::SliceData,
+                16,
+                4,
+                &mut unit_size,
+                &mut pitch,
+                &mut id,
+            ),
+            "vaCreateBuffer2",
+            &[],
+        )
+        .unwrap();
+        // Rendering fails without a picture, but the call is annotated anyway.
+        d.libva.vaRenderPicture(d.raw, context.id, &mut id, 1);
+        d.libva.vaDestroyBuffer(d.raw, id);
+    }
+
+    let records = trace.records();
+    let create = &records[records.len() - 3];
+    assert_eq!(create.function(), "vaCreateBuffer2");
+    assert_eq!(
+        create.arg("pitch"),
+        Some(&TraceValue::List(vec![TraceValue::Int(16)]))
+    );
+    let render = &records[records.len() - 2];
+    assert_eq!(
+        render.arg("buffer_types").unwrap().to_string(),
+        "[SliceData]"
+    );
+    assert_eq!(
+        render.arg("buffer_sizes"),
+        Some(&TraceValue::List(vec![TraceValue::Int(64)]))
+    );
+}
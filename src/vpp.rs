@@ -7,13 +7,15 @@
 use std::{ffi::c_uint, marker::PhantomData, mem, slice, vec};
 
 use crate::{
-    buffer::{Buffer, RawBuffer},
+    buffer::{Buffer, BufferType, RawBuffer},
     check,
-    context::Context,
+    context::{BufferSlot, Context, PictureLayout},
+    display::LibvaFeatures,
+    error::Object,
     pixelformat::PixelFormat,
     raw::{Rectangle, VABufferID, VASurfaceID, VA_PADDING_HIGH, VA_PADDING_LARGE, VA_PADDING_LOW},
     surface::Surface,
-    Mirror, Result, Rotation,
+    Entrypoint, Error, Mirror, Profile, Result, Rotation,
 };
 
 impl Context {
@@ -31,12 +33,16 @@ impl Context {
         let mut num_filters = PREALLOC as c_uint;
         let mut filters = vec![FilterType::None; PREALLOC];
         unsafe {
-            check(self.d.libva.vaQueryVideoProcFilters(
-                self.d.raw,
-                self.id,
-                filters.as_mut_ptr(),
-                &mut num_filters,
-            ))?;
+            check(
+                self.d.libva.vaQueryVideoProcFilters(
+                    self.d.raw,
+                    self.id,
+                    filters.as_mut_ptr(),
+                    &mut num_filters,
+                ),
+                "vaQueryVideoProcFilters",
+                &[Object::Context(self.id)],
+            )?;
         }
 
         assert_ne!(
@@ -54,6 +60,11 @@ impl Context {
         &self,
         filters: &mut Filters,
     ) -> Result<ProcPipelineCaps> {
+        self.d.require(
+            LibvaFeatures::VIDEO_PROC_PIPELINE_CAPS,
+            "vaQueryVideoProcPipelineCaps",
+        )?;
+
         let mut input_color_standards = vec![ColorStandardType(0); 32];
         let mut output_color_standards = vec![ColorStandardType(0); 32];
         let mut input_pixel_formats = vec![PixelFormat::from_u32_le(0); 32];
@@ -69,13 +80,17 @@ impl Context {
             caps.num_input_pixel_formats = input_pixel_formats.len() as _;
             caps.output_pixel_format = output_pixel_formats.as_mut_ptr();
             caps.num_output_pixel_formats = output_pixel_formats.len() as _;
-            check(self.d.libva.vaQueryVideoProcPipelineCaps(
-                self.d.raw,
-                self.id,
-                filters.as_mut_ptr(),
-                filters.len().try_into().unwrap(),
-                &mut caps,
-            ))?;
+            check(
+                self.d.libva.vaQueryVideoProcPipelineCaps(
+                    self.d.raw,
+                    self.id,
+                    filters.as_mut_ptr(),
+                    filters.len().try_into().unwrap(),
+                    &mut caps,
+                ),
+                "vaQueryVideoProcPipelineCaps",
+                &[Object::Context(self.id)],
+            )?;
 
             // Intel's and Mesa's implementation doesn't use the user-provided buffers, but changes
             // the pointer to point to static data, despite the `va_vpp.h` docs implying otherwise.
@@ -96,10 +111,10 @@ impl Context {
 
             // Aaaand of course nobody actually supports the pixel format part of the interface.
             // So when the number is unchanged, assume the data is too, and clear it.
-            if caps.num_input_pixel_formats == input_pixel_formats.len() as _ {
+            if caps.num_input_pixel_formats as usize == input_pixel_formats.len() {
                 input_pixel_formats.clear();
             }
-            if caps.num_output_pixel_formats == output_pixel_formats.len() as _ {
+            if caps.num_output_pixel_formats as usize == output_pixel_formats.len() {
                 output_pixel_formats.clear();
             }
 
@@ -260,6 +275,7 @@ bitflags! {
 bitflags! {
     /// The supported [`Rotation`]s.
     pub struct RotationFlags: u32 {
+        const NONE = 1 << Rotation::NONE.0;
         const R90 = 1 << Rotation::R90.0;
         const R180 = 1 << Rotation::R180.0;
         const R270 = 1 << Rotation::R270.0;
@@ -377,6 +393,21 @@ impl IntoIterator for FilterTypes {
     }
 }
 
+/// [`PictureLayout`] of a video processing operation, submitted to a context created with
+/// [`Entrypoint::VideoProc`].
+///
+/// The [`ProcPipelineParameterBuffer`] references all other inputs, so it is the only buffer.
+pub struct VideoProcPicture;
+
+unsafe impl PictureLayout for VideoProcPicture {
+    const BUFFERS: &'static [BufferSlot] =
+        &[BufferSlot::required(BufferType::ProcPipelineParameter)];
+
+    fn supports(_profile: Profile, entrypoint: Entrypoint) -> bool {
+        entrypoint == Entrypoint::VideoProc
+    }
+}
+
 /// Configuration for a video processing pipeline.
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -416,6 +447,50 @@ pub struct ProcPipelineParameterBuffer<'a> {
     _p: PhantomData<&'a ()>,
 }
 
+impl ProcPipelineParameterBuffer<'_> {
+    /// Replaces the input surface ID in `data`, which holds the contents of a
+    /// [`BufferType::ProcPipelineParameter`][crate::buffer::BufferType::ProcPipelineParameter]
+    /// buffer.
+    ///
+    /// Fails if the parameters point to any other memory (like regions or filter lists), since
+    /// those pointers are meaningless outside of the process that created them.
+    pub(crate) fn remap_surface(
+        data: &mut [u8],
+        map: impl FnOnce(VASurfaceID) -> Option<VASurfaceID>,
+    ) -> Result<()> {
+        if data.len() != mem::size_of::<Self>() {
+            return Err(Error::from(format!(
+                "pipeline parameter buffer has size {}, expected {}",
+                data.len(),
+                mem::size_of::<Self>()
+            )));
+        }
+        // Safety: all fields are integers, pointers, or newtypes around them.
+        let mut params = unsafe { data.as_ptr().cast::<Self>().read_unaligned() };
+        if !params.surface_region.is_null()
+            || !params.output_region.is_null()
+            || !params.filters.is_null()
+            || !params.forward_references.is_null()
+            || !params.backward_references.is_null()
+            || !params.blend_state.is_null()
+            || !params.additional_outputs.is_null()
+            || !params.output_hdr_metadata.is_null()
+        {
+            return Err(Error::from(
+                "pipeline parameters that reference other memory cannot be remapped",
+            ));
+        }
+        params.surface = map(params.surface).ok_or_else(|| {
+            Error::from(format!(
+                "pipeline parameters reference unknown surface {}",
+                params.surface
+            ))
+        })?;
+        unsafe { data.as_mut_ptr().cast::<Self>().write_unaligned(params) };
+        Ok(())
+    }
+}
+
 impl<'a> ProcPipelineParameterBuffer<'a> {
     /// Creates default processing pipeline parameters using the given [`Surface`] as the input
     /// image.
@@ -600,9 +675,11 @@ impl ProcPipelineCaps {
     }
 }
 
+/// Raw video processing pipeline capabilities, as exchanged with a
+/// [`Backend`][crate::backend::Backend].
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
-pub(crate) struct RawProcPipelineCaps {
+pub struct RawProcPipelineCaps {
     pipeline_flags: PipelineFlags,
     filter_flags: FilterFlags,
     num_forward_references: u32,
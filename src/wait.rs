@@ -0,0 +1,176 @@
+//! Waiting for libva operations without blocking the calling thread.
+
+use std::{
+    cmp, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+use crate::{error::VAError, raw::VA_TIMEOUT_INFINITE, Error, Result};
+
+/// How long the helper thread waits for all pending operations combined, before checking whether
+/// the waiting futures were dropped.
+pub(crate) const SLICE: Duration = Duration::from_millis(10);
+
+/// The shortest time the helper thread waits for a single operation.
+const MIN_SLICE: Duration = Duration::from_millis(1);
+
+/// Converts `timeout` to nanoseconds, saturating at [`VA_TIMEOUT_INFINITE`].
+pub(crate) fn timeout_ns(timeout: Duration) -> u64 {
+    u64::try_from(timeout.as_nanos()).unwrap_or(VA_TIMEOUT_INFINITE)
+}
+
+/// Returns `Ok(false)` if `result` is a [`VAError::ERROR_TIMEDOUT`] error, and `Ok(true)` if the
+/// operation finished successfully.
+pub(crate) fn finished(result: Result<()>) -> Result<bool> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) if e.as_libva() == Some(VAError::ERROR_TIMEDOUT) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+type WaitFn = Box<dyn FnMut(Duration) -> Result<bool> + Send>;
+
+/// The operations of a display that [`Waiter`]s are waiting for.
+///
+/// All of them are waited for on a single helper thread, which is started when the first
+/// operation is added, and exits once none are left.
+#[derive(Default)]
+pub(crate) struct WaitQueue {
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    waits: Vec<(Arc<Shared>, WaitFn)>,
+    running: bool,
+}
+
+impl WaitQueue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        // The state is consistent whenever the lock is released.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn run(&self) {
+        loop {
+            let mut waits = {
+                let mut state = self.lock();
+                if state.waits.is_empty() {
+                    state.running = false;
+                    return;
+                }
+                mem::take(&mut state.waits)
+            };
+
+            let slice = cmp::max(SLICE / waits.len() as u32, MIN_SLICE);
+            waits.retain_mut(|(shared, wait)| {
+                if shared.cancelled.load(Ordering::Relaxed) {
+                    return false;
+                }
+                let result = match wait(slice) {
+                    Ok(false) => return true,
+                    Ok(true) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                shared.finish(result);
+                false
+            });
+            self.lock().waits.append(&mut waits);
+        }
+    }
+}
+
+/// Waits for an operation on the helper thread of a [`WaitQueue`], and wakes the task polling it
+/// once it's done.
+///
+/// Dropping a [`Waiter`] doesn't block. The helper thread stops waiting for the operation within
+/// about [`SLICE`].
+pub(crate) struct Waiter {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    cancelled: AtomicBool,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    result: Option<Result<()>>,
+    waker: Option<Waker>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is only ever replaced as a whole.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stores the result of the operation and wakes the waiting task.
+    fn finish(&self, result: Result<()>) {
+        let waker = {
+            let mut state = self.lock();
+            state.result = Some(result);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl Waiter {
+    /// Adds an operation to `queue`, starting its helper thread if needed. The thread calls
+    /// `wait` until it returns `Ok(true)` or an error.
+    ///
+    /// `wait` should block for at most the [`Duration`] passed to it, and return `Ok(false)` if
+    /// the operation is still in progress.
+    pub(crate) fn spawn(
+        queue: &Arc<WaitQueue>,
+        wait: impl FnMut(Duration) -> Result<bool> + Send + 'static,
+    ) -> Result<Self> {
+        let shared = Arc::new(Shared {
+            cancelled: AtomicBool::new(false),
+            state: Mutex::default(),
+        });
+
+        let mut state = queue.lock();
+        state.waits.push((shared.clone(), Box::new(wait)));
+        if !state.running {
+            let spawned = thread::Builder::new().name("va-wait".into()).spawn({
+                let queue = queue.clone();
+                move || queue.run()
+            });
+            if let Err(e) = spawned {
+                state.waits.pop();
+                return Err(Error::from(e));
+            }
+            state.running = true;
+        }
+        Ok(Self { shared })
+    }
+
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut state = self.shared.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+}